@@ -1,6 +1,15 @@
-use crate::{
-    app::scenes::{scene_creators, SceneCreators},
-    raytracer::color::Color,
+use raytracer_rs::{
+    cgmath::Vec3,
+    raytracer::{
+        camera::CameraDescription,
+        framebuffer::Framebuffer,
+        output::{save_image, timestamped_file_name, ExrPrecision, RenderInfo},
+        render::{render_chunks, Chunk, RenderSettings},
+        sampler::SamplerKind,
+        scene::Scene,
+        scene_file::{load_scene_file, save_scene_file},
+    },
+    scenes::{create_scene, scene_creators, SceneCreators},
 };
 
 use eframe::{egui, epi};
@@ -9,7 +18,11 @@ use rayon::{ThreadPool, ThreadPoolBuilder};
 
 use crossbeam::channel;
 
-use std::time::Duration;
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 //
 //
@@ -19,16 +32,6 @@ enum AppState {
     Rendering,
 }
 
-//
-//
-//
-struct Chunk {
-    x: usize,
-    y: usize,
-    duration: Duration,
-    pixels: Vec<Color>,
-}
-
 //
 //
 //
@@ -39,18 +42,32 @@ pub struct App {
     //
     selected_scene: &'static str,
     scene_creators: SceneCreators,
+    scene: Arc<Scene>,
+    // built in scene name or scene file path, for render info
+    scene_name: String,
+
+    scene_file_path: String,
+    scene_file_status: String,
+
+    // starts as scene's camera, can be edited before rendering
+    camera: CameraDescription,
 
     //
-    image_width: usize,
-    image_height: usize,
-    sample_count: usize,
-    max_depth: usize,
-    chunk_size: usize,
+    settings: RenderSettings,
 
     //
     tex_id: Option<egui::TextureId>,
     pixels: Vec<egui::Color32>,
 
+    // last render, kept for saving
+    framebuffer: Option<Framebuffer>,
+    render_info: Option<RenderInfo>,
+    render_begin: Instant,
+
+    save_file_name: String,
+    save_half_float: bool,
+    save_status: String,
+
     //
     total_chunk_count: usize,
     chunks_received: usize,
@@ -74,63 +91,214 @@ impl App {
                 ui.centered_and_justified(|ui| {
                     ui.image(
                         tex_id,
-                        egui::Vec2::new(self.image_width as f32, self.image_height as f32),
+                        egui::Vec2::new(
+                            self.settings.image_width as f32,
+                            self.settings.image_height as f32,
+                        ),
                     );
                 })
             });
         }
 
         //
+        let previous_scene = self.selected_scene;
+        let previous_seed = self.settings.seed;
+
         egui::SidePanel::left("setup_panel", 200.0).show(ctx, |ui| {
             ui.vertical_centered(|ui| {
                 //
                 egui::ComboBox::from_label("Select scene")
                     .selected_text(self.selected_scene)
                     .show_ui(ui, |ui| {
-                        for (key, _) in &self.scene_creators {
+                        for key in self.scene_creators.keys() {
                             ui.selectable_value(&mut self.selected_scene, *key, *key);
                         }
                     });
 
+                ui.label("Scene file");
+                ui.text_edit_singleline(&mut self.scene_file_path);
+                ui.horizontal(|ui| {
+                    if ui.button("Load").clicked() {
+                        self.load_scene_file();
+                    }
+                    if ui.button("Export").clicked() {
+                        self.export_scene_file();
+                    }
+                });
+                if !self.scene_file_status.is_empty() {
+                    ui.label(&self.scene_file_status);
+                }
+
                 //
                 ui.separator();
 
                 //
                 egui::Grid::new("setup_grid").show(ui, |ui| {
                     ui.label("Image width");
-                    ui.add(egui::Slider::new(&mut self.image_width, 10..=4000));
+                    ui.add(egui::Slider::new(&mut self.settings.image_width, 10..=4000));
                     ui.end_row();
 
                     ui.label("Image height");
-                    ui.add(egui::Slider::new(&mut self.image_height, 10..=4000));
+                    ui.add(egui::Slider::new(
+                        &mut self.settings.image_height,
+                        10..=4000,
+                    ));
                     ui.end_row();
 
                     ui.label("Samples per pixel");
-                    ui.add(egui::Slider::new(&mut self.sample_count, 1..=1000));
+                    ui.add(egui::Slider::new(&mut self.settings.sample_count, 1..=1000));
                     ui.end_row();
 
                     ui.label("Max depths");
-                    ui.add(egui::Slider::new(&mut self.max_depth, 1..=1000));
+                    ui.add(egui::Slider::new(&mut self.settings.max_depth, 1..=1000));
                     ui.end_row();
 
                     ui.label("Chunk size");
-                    ui.add(egui::Slider::new(&mut self.chunk_size, 1..=128));
+                    ui.add(egui::Slider::new(&mut self.settings.chunk_size, 1..=128));
+                    ui.end_row();
+
+                    ui.label("Seed");
+                    ui.add(egui::DragValue::new(&mut self.settings.seed));
+                    ui.end_row();
+
+                    ui.label("Sampler");
+                    egui::ComboBox::from_id_source("sampler")
+                        .selected_text(self.settings.sampler.name())
+                        .show_ui(ui, |ui| {
+                            for kind in SamplerKind::ALL.iter() {
+                                ui.selectable_value(&mut self.settings.sampler, *kind, kind.name());
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("AOVs");
+                    ui.checkbox(&mut self.settings.aovs, "Albedo, normal and depth in EXR");
                     ui.end_row()
                 });
 
                 //
                 ui.separator();
 
+                //
+                egui::Grid::new("camera_grid").show(ui, |ui| {
+                    ui.label("Eye");
+                    vec3_drag_values(ui, &mut self.camera.eye);
+                    ui.end_row();
+
+                    ui.label("Target");
+                    vec3_drag_values(ui, &mut self.camera.target);
+                    ui.end_row();
+
+                    ui.label("Up");
+                    vec3_drag_values(ui, &mut self.camera.up);
+                    ui.end_row();
+
+                    ui.label("Vertical fov");
+                    ui.add(egui::Slider::new(
+                        &mut self.camera.vertical_fov.0,
+                        1.0..=179.0,
+                    ));
+                    ui.end_row();
+
+                    ui.label("Aperture");
+                    ui.add(
+                        egui::DragValue::new(&mut self.camera.aperture)
+                            .speed(0.01)
+                            .clamp_range(0.0..=10.0),
+                    );
+                    ui.end_row();
+
+                    ui.label("Focus distance");
+                    ui.add(
+                        egui::DragValue::new(&mut self.camera.focus_distance)
+                            .speed(0.1)
+                            .clamp_range(0.01..=1000.0),
+                    );
+                    ui.end_row();
+
+                    ui.label("Shutter");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut self.camera.shutter_open).speed(0.01));
+                        ui.add(
+                            egui::DragValue::new(&mut self.camera.shutter_close)
+                                .speed(0.01)
+                                .clamp_range(self.camera.shutter_open..=f32::INFINITY),
+                        );
+                    });
+                    ui.end_row()
+                });
+
+                if ui.button("Reset camera").clicked() {
+                    self.camera = *self.scene.camera();
+                }
+
+                //
+                ui.separator();
+
                 //
                 render_clicked = ui.button("Render").clicked();
+
+                //
+                if self.framebuffer.is_some() {
+                    ui.separator();
+                    self.save_image_ui(ui);
+                }
             })
         });
 
+        // built in scenes are generated from the seed too
+        let is_built_in = self.scene_name == self.selected_scene;
+
+        if self.selected_scene != previous_scene
+            || (is_built_in && self.settings.seed != previous_seed)
+        {
+            self.create_scene();
+        }
+
         if render_clicked {
             self.start_render(frame);
         }
     }
 
+    fn create_scene(&mut self) {
+        let scene_creator = *self.scene_creators.get(self.selected_scene).unwrap();
+        let scene = create_scene(scene_creator, self.settings.seed);
+
+        self.camera = *scene.camera();
+        self.scene = Arc::new(scene);
+        self.scene_name = self.selected_scene.to_string();
+    }
+
+    fn load_scene_file(&mut self) {
+        match load_scene_file(Path::new(&self.scene_file_path)) {
+            Ok(file) => {
+                self.camera = *file.scene.camera();
+                self.scene = Arc::new(file.scene);
+                self.settings = file.settings;
+                self.scene_name = self.scene_file_path.clone();
+                self.scene_file_status = format!("Loaded {}", self.scene_file_path);
+            }
+            Err(e) => {
+                log::error!("{:#}", e);
+                self.scene_file_status = format!("Error: {:#}", e);
+            }
+        }
+    }
+
+    // current camera and settings go into the file too
+    fn export_scene_file(&mut self) {
+        let path = Path::new(&self.scene_file_path);
+
+        self.scene_file_status =
+            match save_scene_file(path, &self.scene, &self.camera, Some(&self.settings)) {
+                Ok(()) => format!("Exported {}", self.scene_file_path),
+                Err(e) => {
+                    log::error!("{:#}", e);
+                    format!("Error: {:#}", e)
+                }
+            };
+    }
+
     fn update_when_rendering(
         &mut self,
         ctx: &egui::CtxRef,
@@ -141,38 +309,47 @@ impl App {
         if let Some(Ok(chunk)) = next {
             self.chunks_received += 1;
 
+            log::debug!(
+                "chunk ({}, {}) rendered in {:?}",
+                chunk.x,
+                chunk.y,
+                chunk.duration
+            );
+
             //
-            for j in 0..self.chunk_size {
-                let y = chunk.y * self.chunk_size + j;
+            if let Some(framebuffer) = &mut self.framebuffer {
+                chunk.copy_to(framebuffer);
+            }
 
-                if y >= self.image_height {
-                    continue;
-                }
+            for j in 0..chunk.height {
+                for i in 0..chunk.width {
+                    let color = chunk.pixels[j * chunk.width + i].gamma_corrected().as_u8();
 
-                for i in 0..self.chunk_size {
-                    let x = chunk.x * self.chunk_size + i;
+                    self.pixels[(chunk.y + j) * self.settings.image_width + chunk.x + i] =
+                        egui::Color32::from_rgb(color[0], color[1], color[2]);
+                }
+            }
 
-                    if x >= self.image_width {
-                        continue;
-                    }
+            //
+            if self.chunks_received == self.total_chunk_count {
+                let render_time = Instant::now() - self.render_begin;
 
-                    let color = chunk.pixels[j * self.chunk_size + i].as_u8();
+                log::info!("render finished in {:?}", render_time);
 
-                    self.pixels[y * self.image_width + x] =
-                        egui::Color32::from_rgb(color[0], color[1], color[2]);
+                if let Some(info) = &mut self.render_info {
+                    info.render_time = render_time;
                 }
             }
 
             //
-            if let Some(tex_id) = self.tex_id.clone() {
+            if let Some(tex_id) = self.tex_id {
                 frame.tex_allocator().free(tex_id);
             }
 
-            self.tex_id = Some(
-                frame
-                    .tex_allocator()
-                    .alloc_srgba_premultiplied((self.image_width, self.image_height), &self.pixels),
-            );
+            self.tex_id = Some(frame.tex_allocator().alloc_srgba_premultiplied(
+                (self.settings.image_width, self.settings.image_height),
+                &self.pixels,
+            ));
         }
 
         //
@@ -180,10 +357,13 @@ impl App {
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.centered_and_justified(|ui| {
-                let tex_id = self.tex_id.clone().unwrap();
+                let tex_id = self.tex_id.unwrap();
                 ui.image(
                     tex_id,
-                    egui::Vec2::new(self.image_width as f32, self.image_height as f32),
+                    egui::Vec2::new(
+                        self.settings.image_width as f32,
+                        self.settings.image_height as f32,
+                    ),
                 );
             })
         });
@@ -197,6 +377,11 @@ impl App {
             ui.separator();
 
             stop_render_clicked = ui.button("Stop render").clicked();
+
+            if self.chunks_received == self.total_chunk_count {
+                ui.separator();
+                self.save_image_ui(ui);
+            }
         });
 
         if stop_render_clicked {
@@ -204,6 +389,53 @@ impl App {
         }
     }
 
+    fn save_image_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+    ) {
+        ui.label("File name (png, hdr, pfm or exr)");
+        ui.text_edit_singleline(&mut self.save_file_name);
+        ui.checkbox(&mut self.save_half_float, "Half float EXR");
+
+        if ui.button("Save image").clicked() {
+            self.save_status = match self.save_image() {
+                Ok(()) => format!("Saved {}", self.save_file_name),
+                Err(e) => {
+                    log::error!("{:#}", e);
+                    format!("Error: {:#}", e)
+                }
+            };
+        }
+
+        if !self.save_status.is_empty() {
+            ui.label(&self.save_status);
+        }
+    }
+
+    fn save_image(&self) -> anyhow::Result<()> {
+        let framebuffer = match &self.framebuffer {
+            Some(framebuffer) => framebuffer,
+            None => anyhow::bail!("nothing rendered yet"),
+        };
+
+        let exr_precision = if self.save_half_float {
+            ExrPrecision::Half
+        } else {
+            ExrPrecision::Float
+        };
+
+        save_image(
+            Path::new(&self.save_file_name),
+            framebuffer,
+            self.render_info.as_ref(),
+            exr_precision,
+        )?;
+
+        log::info!("saved {}", self.save_file_name);
+
+        Ok(())
+    }
+
     fn start_render(
         &mut self,
         frame: &mut epi::Frame<'_>,
@@ -212,16 +444,33 @@ impl App {
 
         //
         self.pixels.resize(
-            self.image_width * self.image_height,
+            self.settings.image_width * self.settings.image_height,
             egui::Color32::from_rgb(32, 32, 32),
         );
         self.pixels.fill(egui::Color32::from_rgb(32, 32, 32));
 
-        self.tex_id = Some(
-            frame
-                .tex_allocator()
-                .alloc_srgba_premultiplied((self.image_width, self.image_height), &self.pixels),
-        );
+        self.tex_id = Some(frame.tex_allocator().alloc_srgba_premultiplied(
+            (self.settings.image_width, self.settings.image_height),
+            &self.pixels,
+        ));
+
+        //
+        self.framebuffer = Some(Framebuffer::new(
+            self.settings.image_width,
+            self.settings.image_height,
+        ));
+        self.render_info = Some(RenderInfo {
+            scene: self.scene_name.clone(),
+            sample_count: self.settings.sample_count,
+            max_depth: self.settings.max_depth,
+            seed: self.settings.seed,
+            sampler: self.settings.sampler,
+            render_time: Duration::default(),
+        });
+        self.render_begin = Instant::now();
+
+        self.save_file_name = timestamped_file_name("png");
+        self.save_status.clear();
 
         //
         let (sender, receiver) = channel::bounded(1000);
@@ -230,33 +479,18 @@ impl App {
 
         self.thread_pool = Some(ThreadPoolBuilder::new().build().unwrap());
 
-        self.total_chunk_count = {
-            let xs = (self.image_width + self.chunk_size - 1) / self.chunk_size;
-            let ys = (self.image_height + self.chunk_size - 1) / self.chunk_size;
-            xs * ys
-        };
+        self.total_chunk_count = self.settings.chunk_count();
         self.chunks_received = 0;
 
         //
-        let scene = self.scene_creators.get(self.selected_scene).unwrap()();
-
-        //
-        let image_width = self.image_width;
-        let image_height = self.image_height;
-        let sample_count = self.sample_count;
-        let max_depth = self.max_depth;
-        let chunk_size = self.chunk_size;
+        let scene = self.scene.clone();
+        let camera = self.camera;
+        let settings = self.settings;
 
         self.thread_pool.as_ref().unwrap().spawn(move || {
-            raytrace_task(
-                sender,
-                scene,
-                image_width,
-                image_height,
-                sample_count,
-                max_depth,
-                chunk_size,
-            )
+            render_chunks(&scene, &camera, &settings, |chunk| {
+                let _ = sender.send(chunk);
+            })
         });
     }
 
@@ -272,22 +506,36 @@ impl App {
 
 impl Default for App {
     fn default() -> Self {
+        let selected_scene = "Book 1 final scene";
+        let scene_creators = scene_creators();
+        let settings = RenderSettings::default();
+        let scene = create_scene(*scene_creators.get(selected_scene).unwrap(), settings.seed);
+
         App {
             //
             state: AppState::Setup,
             //
-            selected_scene: "Book 1 final scene",
-            scene_creators: scene_creators(),
+            selected_scene,
+            scene_creators,
+            camera: *scene.camera(),
+            scene: Arc::new(scene),
+            scene_name: selected_scene.to_string(),
+            scene_file_path: String::new(),
+            scene_file_status: String::new(),
             //
-            image_width: 400,
-            image_height: 400,
-            sample_count: 10,
-            max_depth: 10,
-            chunk_size: 16,
+            settings,
             //
             tex_id: None,
             pixels: vec![],
 
+            //
+            framebuffer: None,
+            render_info: None,
+            render_begin: Instant::now(),
+            save_file_name: String::new(),
+            save_half_float: ExrPrecision::default() == ExrPrecision::Half,
+            save_status: String::new(),
+
             //
             total_chunk_count: 0,
             chunks_received: 0,
@@ -333,117 +581,13 @@ impl epi::App for App {
 //
 //
 //
-fn raytrace_task(
-    sender: channel::Sender<Chunk>,
-    scene: crate::raytracer::scene::Scene,
-    image_width: usize,
-    image_height: usize,
-    sample_count: usize,
-    max_depth: usize,
-    chunk_size: usize,
+fn vec3_drag_values(
+    ui: &mut egui::Ui,
+    v: &mut Vec3,
 ) {
-    //
-    use crate::{
-        cgmath::*,
-        raytracer::{
-            camera::Camera,
-            raytrace::{ray_color, RayCastOptions},
-        },
-    };
-
-    use rand::{thread_rng, Rng};
-
-    use rayon::prelude::*;
-
-    use std::time::Instant;
-
-    //
-    let aspect_ratio = image_width as f32 / image_height as f32;
-
-    let camera = {
-        let eye = Vec3::new(13.0, 2.0, 3.0);
-        let target = Vec3::ZERO;
-        let up = Vec3::Y;
-        let vertical_fov = Degrees(20.0).into();
-        let aperture = 0.1;
-        let focal_distance = 10.0;
-
-        &Camera::new(
-            eye,
-            target,
-            up,
-            vertical_fov,
-            aspect_ratio,
-            aperture,
-            focal_distance,
-        )
-    };
-
-    //
-    let chunks = {
-        let x_chunks = (image_width + chunk_size - 1) / chunk_size;
-        let y_chunks = (image_height + chunk_size - 1) / chunk_size;
-
-        let mut chunks = Vec::with_capacity(x_chunks * y_chunks);
-
-        for y in 0..y_chunks {
-            for x in 0..x_chunks {
-                chunks.push((x, y));
-            }
-        }
-
-        chunks
-    };
-
-    chunks.into_par_iter().for_each(|(x, y)| {
-        let mut colors = vec![Color::from_rgb(0.0, 0.0, 0.0); chunk_size * chunk_size];
-
-        let begin = Instant::now();
-
-        for j in 0..chunk_size {
-            for i in 0..chunk_size {
-                let tx = x * chunk_size + i;
-                let ty = y * chunk_size + j;
-
-                if tx >= image_width || ty >= image_height {
-                    continue;
-                }
-
-                let u = tx as f32 / (image_width - 1) as f32;
-                let v = 1.0 - (ty as f32 / (image_height - 1) as f32);
-
-                let mut comps = Vec3::ZERO;
-                for _ in 0..sample_count {
-                    let du = thread_rng().gen_range(-0.5..0.5) / image_width as f32;
-                    let dv = thread_rng().gen_range(-0.5..0.5) / image_height as f32;
-
-                    let ray = camera.ray_at(u + du, v + dv);
-
-                    let ray_cast_options = RayCastOptions {
-                        sample_count: sample_count,
-                        max_depth: max_depth,
-                    };
-                    comps = comps + ray_color(&ray_cast_options, &scene, &ray, 0).into();
-                }
-
-                comps = comps / sample_count as f32;
-
-                // color correction ?
-                comps.x = comps.x.sqrt();
-                comps.y = comps.y.sqrt();
-                comps.z = comps.z.sqrt();
-
-                colors[j * chunk_size + i] = comps.into();
-            }
-        }
-
-        let end = Instant::now();
-
-        let _ = sender.send(Chunk {
-            x,
-            y,
-            duration: end - begin,
-            pixels: colors,
-        });
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut v.x).speed(0.1));
+        ui.add(egui::DragValue::new(&mut v.y).speed(0.1));
+        ui.add(egui::DragValue::new(&mut v.z).speed(0.1));
     });
 }
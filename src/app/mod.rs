@@ -1,4 +1,4 @@
+#[allow(clippy::module_inception)]
 mod app;
-mod scenes;
 
 pub use app::App;
@@ -0,0 +1,355 @@
+use crate::cgmath::mat4::*;
+use crate::cgmath::quaternion::*;
+use crate::cgmath::vec3::*;
+
+use std::ops::Mul;
+
+/*
+ * Linear map followed by a translation, a `Mat4` with bottom row 0 0 0 1 that is cheaper to
+ * apply and invert
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Affine3 {
+    // rows of the linear part
+    pub linear: [[f32; 3]; 3],
+    pub translation: Vec3,
+}
+
+impl Affine3 {
+    pub const IDENTITY: Affine3 = Affine3::new(
+        [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        Vec3::ZERO,
+    );
+
+    pub const fn new(
+        linear: [[f32; 3]; 3],
+        translation: Vec3,
+    ) -> Affine3 {
+        Affine3 {
+            linear,
+            translation,
+        }
+    }
+
+    pub fn from_translation(translation: Vec3) -> Affine3 {
+        Affine3 {
+            translation,
+            ..Affine3::IDENTITY
+        }
+    }
+
+    pub fn from_scale(scale: Vec3) -> Affine3 {
+        Affine3::new(
+            [
+                [scale.x, 0.0, 0.0],
+                [0.0, scale.y, 0.0],
+                [0.0, 0.0, scale.z],
+            ],
+            Vec3::ZERO,
+        )
+    }
+
+    pub fn from_rotation(rotation: Quaternion) -> Affine3 {
+        let Quaternion { x, y, z, w } = rotation.normalized();
+
+        Affine3::new(
+            [
+                [
+                    1.0 - 2.0 * (y * y + z * z),
+                    2.0 * (x * y - w * z),
+                    2.0 * (x * z + w * y),
+                ],
+                [
+                    2.0 * (x * y + w * z),
+                    1.0 - 2.0 * (x * x + z * z),
+                    2.0 * (y * z - w * x),
+                ],
+                [
+                    2.0 * (x * z - w * y),
+                    2.0 * (y * z + w * x),
+                    1.0 - 2.0 * (x * x + y * y),
+                ],
+            ],
+            Vec3::ZERO,
+        )
+    }
+
+    /*
+     * `Mat4` with bottom row 0 0 0 1, `None` for projective matrices
+     */
+    pub fn from_mat4(matrix: &Mat4) -> Option<Affine3> {
+        let m = &matrix.rows;
+        if m[3] != [0.0, 0.0, 0.0, 1.0] {
+            return None;
+        }
+
+        Some(Affine3::new(
+            [
+                [m[0][0], m[0][1], m[0][2]],
+                [m[1][0], m[1][1], m[1][2]],
+                [m[2][0], m[2][1], m[2][2]],
+            ],
+            Vec3::new(m[0][3], m[1][3], m[2][3]),
+        ))
+    }
+
+    /*
+     * Applies `self` and then `next`
+     */
+    pub fn then(
+        self,
+        next: Affine3,
+    ) -> Affine3 {
+        next * self
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let m = &self.linear;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /*
+     * `None` for singular maps
+     */
+    pub fn inverse(&self) -> Option<Affine3> {
+        let m = &self.linear;
+
+        let det = self.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+
+        let d = 1.0 / det;
+
+        // adjugate over determinant
+        let linear = [
+            [
+                d * (m[1][1] * m[2][2] - m[1][2] * m[2][1]),
+                d * (m[0][2] * m[2][1] - m[0][1] * m[2][2]),
+                d * (m[0][1] * m[1][2] - m[0][2] * m[1][1]),
+            ],
+            [
+                d * (m[1][2] * m[2][0] - m[1][0] * m[2][2]),
+                d * (m[0][0] * m[2][2] - m[0][2] * m[2][0]),
+                d * (m[0][2] * m[1][0] - m[0][0] * m[1][2]),
+            ],
+            [
+                d * (m[1][0] * m[2][1] - m[1][1] * m[2][0]),
+                d * (m[0][1] * m[2][0] - m[0][0] * m[2][1]),
+                d * (m[0][0] * m[1][1] - m[0][1] * m[1][0]),
+            ],
+        ];
+
+        let inverse = Affine3::new(linear, Vec3::ZERO);
+        let translation = -inverse.transform_vector(self.translation);
+
+        Some(Affine3 {
+            translation,
+            ..inverse
+        })
+    }
+
+    pub fn transform_point(
+        &self,
+        p: Vec3,
+    ) -> Vec3 {
+        self.transform_vector(p) + self.translation
+    }
+
+    pub fn transform_vector(
+        &self,
+        v: Vec3,
+    ) -> Vec3 {
+        let m = &self.linear;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    /*
+     * Applies the transposed linear part. Normals go through the inverse transpose of a map, so
+     * called on the inverse this transforms normals, unnormalized.
+     */
+    pub fn transform_vector_transposed(
+        &self,
+        v: Vec3,
+    ) -> Vec3 {
+        let m = &self.linear;
+        Vec3::new(
+            m[0][0] * v.x + m[1][0] * v.y + m[2][0] * v.z,
+            m[0][1] * v.x + m[1][1] * v.y + m[2][1] * v.z,
+            m[0][2] * v.x + m[1][2] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    /*
+     * Top three rows of the equivalent `Mat4`
+     */
+    pub fn rows(&self) -> [[f32; 4]; 3] {
+        let (m, t) = (&self.linear, self.translation);
+        [
+            [m[0][0], m[0][1], m[0][2], t.x],
+            [m[1][0], m[1][1], m[1][2], t.y],
+            [m[2][0], m[2][1], m[2][2], t.z],
+        ]
+    }
+}
+
+impl Default for Affine3 {
+    fn default() -> Affine3 {
+        Affine3::IDENTITY
+    }
+}
+
+impl From<Affine3> for Mat4 {
+    fn from(affine: Affine3) -> Mat4 {
+        let [r0, r1, r2] = affine.rows();
+        Mat4::new([r0, r1, r2, [0.0, 0.0, 0.0, 1.0]])
+    }
+}
+
+/*
+ * `a * b` applies `b` first
+ */
+impl Mul for Affine3 {
+    type Output = Affine3;
+    fn mul(
+        self,
+        other: Affine3,
+    ) -> Affine3 {
+        let (a, b) = (&self.linear, &other.linear);
+        let mut linear = [[0.0; 3]; 3];
+        for (i, row) in linear.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+
+        // translation of the composition is where `other` moves the origin to, moved by `self`
+        Affine3::new(linear, self.transform_point(other.translation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cgmath::angle::*;
+
+    fn assert_near(
+        a: Vec3,
+        b: Vec3,
+    ) {
+        assert!((a - b).norm() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    // rotation, non-uniform scale and translation, nothing special about it
+    fn general() -> Affine3 {
+        let rotation = Quaternion::from_axis_angle(Vec3::new(1.0, 2.0, -0.5), Degrees(37.0).into());
+
+        Affine3::from_scale(Vec3::new(2.0, 0.5, -1.5))
+            .then(Affine3::from_rotation(rotation))
+            .then(Affine3::from_translation(Vec3::new(3.0, -1.0, 0.25)))
+    }
+
+    #[test]
+    fn inverse_undoes_transform() {
+        let transform = general();
+        let inverse = transform.inverse().unwrap();
+
+        for p in [Vec3::ZERO, Vec3::X, Vec3::new(-3.0, 0.7, 12.0)] {
+            assert_near(inverse.transform_point(transform.transform_point(p)), p);
+            assert_near(transform.transform_point(inverse.transform_point(p)), p);
+        }
+    }
+
+    #[test]
+    fn singular_has_no_inverse() {
+        let flat = Affine3::from_scale(Vec3::new(1.0, 0.0, 1.0));
+        assert!(flat.inverse().is_none());
+        assert!(Mat4::from(flat).inverse().is_none());
+    }
+
+    #[test]
+    fn mat4_agrees_with_affine() {
+        let transform = general();
+        let matrix = Mat4::from(transform);
+
+        let inverse = Mat4::from(transform.inverse().unwrap());
+        let mat4_inverse = matrix.inverse().unwrap();
+        for (a, b) in inverse
+            .rows
+            .iter()
+            .flatten()
+            .zip(mat4_inverse.rows.iter().flatten())
+        {
+            assert!((a - b).abs() < 1e-4, "{:?} != {:?}", inverse, mat4_inverse);
+        }
+
+        assert!((matrix.determinant() - transform.determinant()).abs() < 1e-4);
+        assert_eq!(Affine3::from_mat4(&matrix), Some(transform));
+
+        let p = Vec3::new(0.3, -2.0, 5.0);
+        assert_near(matrix.transform_point(p), transform.transform_point(p));
+        assert_near(
+            (matrix * matrix).transform_point(p),
+            (transform * transform).transform_point(p),
+        );
+    }
+
+    #[test]
+    fn inverse_transpose_keeps_normals_perpendicular() {
+        let transform = general();
+        let inverse = transform.inverse().unwrap();
+
+        // two tangents of a surface and its normal
+        let (t1, t2) = (Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 1.0, -2.0));
+        let normal = Vec3::cross(t1, t2);
+
+        let normal = inverse.transform_vector_transposed(normal);
+
+        assert!(Vec3::dot(normal, transform.transform_vector(t1)).abs() < 1e-4);
+        assert!(Vec3::dot(normal, transform.transform_vector(t2)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn quaternion_rotation_matches_matrix() {
+        let q = Quaternion::from_axis_angle(Vec3::new(-1.0, 0.5, 2.0), Degrees(123.0).into());
+        let matrix = Affine3::from_rotation(q);
+
+        for v in [Vec3::X, Vec3::Y, Vec3::new(0.3, -4.0, 1.0)] {
+            assert_near(q.rotate(v), matrix.transform_vector(v));
+        }
+
+        // quarter turn around z takes x to y
+        let quarter = Quaternion::from_axis_angle(Vec3::Z, Degrees(90.0).into());
+        assert_near(quarter.rotate(Vec3::X), Vec3::Y);
+
+        // product rotates by the right factor first
+        let r = Quaternion::from_axis_angle(Vec3::X, Degrees(90.0).into());
+        assert_near(
+            (quarter * r).rotate(Vec3::Y),
+            quarter.rotate(r.rotate(Vec3::Y)),
+        );
+        assert_near(q.conjugate().rotate(q.rotate(Vec3::Y)), Vec3::Y);
+    }
+
+    #[test]
+    fn slerp_interpolates_angle() {
+        let a = Quaternion::IDENTITY;
+        let b = Quaternion::from_axis_angle(Vec3::Y, Degrees(120.0).into());
+
+        let half = Quaternion::slerp(0.5, a, b);
+        let expected = Quaternion::from_axis_angle(Vec3::Y, Degrees(60.0).into());
+        assert!((half.dot(expected).abs() - 1.0).abs() < 1e-5);
+
+        // -b is the same rotation, the result mustn't take the long way
+        let half = Quaternion::slerp(0.5, a, -b);
+        assert!((half.dot(expected).abs() - 1.0).abs() < 1e-5);
+
+        assert_eq!(Quaternion::slerp(0.0, a, b), a);
+    }
+}
@@ -9,9 +9,9 @@ const FRAC_1_180: f32 = 1.0 / 180.0;
 #[repr(transparent)]
 pub struct Radians(pub f32);
 
-impl Into<Degrees> for Radians {
-    fn into(self) -> Degrees {
-        Degrees(FRAC_1_PI * 180.0 * self.0)
+impl From<Radians> for Degrees {
+    fn from(radians: Radians) -> Degrees {
+        Degrees(FRAC_1_PI * 180.0 * radians.0)
     }
 }
 
@@ -46,9 +46,9 @@ impl Neg for Radians {
 #[repr(transparent)]
 pub struct Degrees(pub f32);
 
-impl Into<Radians> for Degrees {
-    fn into(self) -> Radians {
-        Radians(PI * FRAC_1_180 * self.0)
+impl From<Degrees> for Radians {
+    fn from(degrees: Degrees) -> Radians {
+        Radians(PI * FRAC_1_180 * degrees.0)
     }
 }
 
@@ -0,0 +1,163 @@
+use crate::cgmath::vec3::*;
+
+use std::ops::Mul;
+
+/*
+ * Row major 4x4 matrix, applied to column vectors
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    pub rows: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4::new([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
+    pub const fn new(rows: [[f32; 4]; 4]) -> Mat4 {
+        Mat4 { rows }
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let m = &self.rows;
+        Mat4::new([
+            [m[0][0], m[1][0], m[2][0], m[3][0]],
+            [m[0][1], m[1][1], m[2][1], m[3][1]],
+            [m[0][2], m[1][2], m[2][2], m[3][2]],
+            [m[0][3], m[1][3], m[2][3], m[3][3]],
+        ])
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let (s, c) = self.sub_determinants();
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+    }
+
+    /*
+     * `None` for singular matrices
+     */
+    pub fn inverse(&self) -> Option<Mat4> {
+        let m = &self.rows;
+        let (s, c) = self.sub_determinants();
+
+        let det = s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+
+        let d = 1.0 / det;
+
+        let inverse = Mat4::new([
+            [
+                d * (m[1][1] * c[5] - m[1][2] * c[4] + m[1][3] * c[3]),
+                d * (-m[0][1] * c[5] + m[0][2] * c[4] - m[0][3] * c[3]),
+                d * (m[3][1] * s[5] - m[3][2] * s[4] + m[3][3] * s[3]),
+                d * (-m[2][1] * s[5] + m[2][2] * s[4] - m[2][3] * s[3]),
+            ],
+            [
+                d * (-m[1][0] * c[5] + m[1][2] * c[2] - m[1][3] * c[1]),
+                d * (m[0][0] * c[5] - m[0][2] * c[2] + m[0][3] * c[1]),
+                d * (-m[3][0] * s[5] + m[3][2] * s[2] - m[3][3] * s[1]),
+                d * (m[2][0] * s[5] - m[2][2] * s[2] + m[2][3] * s[1]),
+            ],
+            [
+                d * (m[1][0] * c[4] - m[1][1] * c[2] + m[1][3] * c[0]),
+                d * (-m[0][0] * c[4] + m[0][1] * c[2] - m[0][3] * c[0]),
+                d * (m[3][0] * s[4] - m[3][1] * s[2] + m[3][3] * s[0]),
+                d * (-m[2][0] * s[4] + m[2][1] * s[2] - m[2][3] * s[0]),
+            ],
+            [
+                d * (-m[1][0] * c[3] + m[1][1] * c[1] - m[1][2] * c[0]),
+                d * (m[0][0] * c[3] - m[0][1] * c[1] + m[0][2] * c[0]),
+                d * (-m[3][0] * s[3] + m[3][1] * s[1] - m[3][2] * s[0]),
+                d * (m[2][0] * s[3] - m[2][1] * s[1] + m[2][2] * s[0]),
+            ],
+        ]);
+
+        Some(inverse)
+    }
+
+    /*
+     * 2x2 determinants of the top two (`s`) and bottom two (`c`) rows, the inverse is built from
+     * them by Laplace expansion
+     */
+    fn sub_determinants(&self) -> ([f32; 6], [f32; 6]) {
+        let m = &self.rows;
+        let det2 =
+            |r0: usize, r1: usize, a: usize, b: usize| m[r0][a] * m[r1][b] - m[r1][a] * m[r0][b];
+
+        let s = [
+            det2(0, 1, 0, 1),
+            det2(0, 1, 0, 2),
+            det2(0, 1, 0, 3),
+            det2(0, 1, 1, 2),
+            det2(0, 1, 1, 3),
+            det2(0, 1, 2, 3),
+        ];
+        let c = [
+            det2(2, 3, 0, 1),
+            det2(2, 3, 0, 2),
+            det2(2, 3, 0, 3),
+            det2(2, 3, 1, 2),
+            det2(2, 3, 1, 3),
+            det2(2, 3, 2, 3),
+        ];
+
+        (s, c)
+    }
+
+    /*
+     * Point with w = 1, divided by the resulting w
+     */
+    pub fn transform_point(
+        &self,
+        p: Vec3,
+    ) -> Vec3 {
+        let m = &self.rows;
+        let row = |i: usize| m[i][0] * p.x + m[i][1] * p.y + m[i][2] * p.z + m[i][3];
+        let w = row(3);
+        Vec3::new(row(0), row(1), row(2)) / w
+    }
+
+    /*
+     * Direction with w = 0, translation doesn't apply
+     */
+    pub fn transform_vector(
+        &self,
+        v: Vec3,
+    ) -> Vec3 {
+        let m = &self.rows;
+        let row = |i: usize| m[i][0] * v.x + m[i][1] * v.y + m[i][2] * v.z;
+        Vec3::new(row(0), row(1), row(2))
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Mat4 {
+        Mat4::IDENTITY
+    }
+}
+
+/*
+ * `a * b` applies `b` first
+ */
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(
+        self,
+        other: Mat4,
+    ) -> Mat4 {
+        let (a, b) = (&self.rows, &other.rows);
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+        Mat4::new(rows)
+    }
+}
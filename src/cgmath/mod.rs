@@ -1,5 +1,13 @@
+mod affine3;
 mod angle;
+mod mat4;
+mod polynomial;
+mod quaternion;
 mod vec3;
 
+pub use crate::cgmath::affine3::*;
 pub use crate::cgmath::angle::*;
+pub use crate::cgmath::mat4::*;
+pub use crate::cgmath::polynomial::*;
+pub use crate::cgmath::quaternion::*;
 pub use crate::cgmath::vec3::*;
@@ -0,0 +1,204 @@
+use std::f64::consts::PI;
+
+/*
+ * Real roots of `a x^2 + b x + c`, ascending. Falls back to the linear equation when `a` is
+ * zero.
+ *
+ * Computes the root away from cancellation first and the other one from their product.
+ */
+pub fn solve_quadratic(
+    a: f64,
+    b: f64,
+    c: f64,
+) -> Vec<f64> {
+    if a == 0.0 {
+        return if b == 0.0 {
+            Vec::new()
+        } else {
+            finite_sorted(vec![-c / b])
+        };
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q == 0.0 {
+        // b and c are both zero
+        return vec![0.0, 0.0];
+    }
+
+    finite_sorted(vec![q / a, c / q])
+}
+
+/*
+ * Real roots of `a x^3 + b x^2 + c x + d`, ascending
+ */
+pub fn solve_cubic(
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+) -> Vec<f64> {
+    if a == 0.0 {
+        return solve_quadratic(b, c, d);
+    }
+
+    let (b, c, d) = (b / a, c / a, d / a);
+
+    // Numerical Recipes 5.6
+    let q = (b * b - 3.0 * c) / 9.0;
+    let r = (2.0 * b * b * b - 9.0 * b * c + 27.0 * d) / 54.0;
+    let shift = b / 3.0;
+
+    let roots = if r * r < q * q * q {
+        let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+        let scale = -2.0 * q.sqrt();
+
+        vec![
+            scale * (theta / 3.0).cos() - shift,
+            scale * ((theta + 2.0 * PI) / 3.0).cos() - shift,
+            scale * ((theta - 2.0 * PI) / 3.0).cos() - shift,
+        ]
+    } else {
+        let s = -r.signum() * (r.abs() + (r * r - q * q * q).sqrt()).cbrt();
+        let t = if s == 0.0 { 0.0 } else { q / s };
+        vec![s + t - shift]
+    };
+
+    finite_sorted(roots)
+}
+
+/*
+ * Real roots of `a x^4 + b x^3 + c x^2 + d x + e`, ascending.
+ *
+ * Ferrari's method: the depressed quartic is split into two quadratics by a root of its
+ * resolvent cubic, then each root is polished with Newton's method on the original polynomial.
+ */
+pub fn solve_quartic(
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+) -> Vec<f64> {
+    if a == 0.0 {
+        return solve_cubic(b, c, d, e);
+    }
+
+    let (b, c, d, e) = (b / a, c / a, d / a, e / a);
+
+    // x = y - b / 4 leaves y^4 + p y^2 + q y + r
+    let bb = b * b;
+    let p = c - 3.0 * bb / 8.0;
+    let q = d - b * c / 2.0 + bb * b / 8.0;
+    let r = e - b * d / 4.0 + bb * c / 16.0 - 3.0 * bb * bb / 256.0;
+
+    // (y^2 + p/2 + m)^2 = (s y - q / (2 s))^2 with s = sqrt(2 m)
+    let m = solve_cubic(8.0, 8.0 * p, 2.0 * p * p - 8.0 * r, -q * q)
+        .last()
+        .copied()
+        .unwrap_or(0.0);
+
+    let mut roots = if m <= 0.0 || q.abs() < 1e-12 {
+        // biquadratic in y^2
+        solve_quadratic(1.0, p, r)
+            .into_iter()
+            .filter(|&z| z >= 0.0)
+            .flat_map(|z| [-z.sqrt(), z.sqrt()])
+            .collect()
+    } else {
+        let s = (2.0 * m).sqrt();
+        let mut roots = solve_quadratic(1.0, -s, p / 2.0 + m + q / (2.0 * s));
+        roots.extend(solve_quadratic(1.0, s, p / 2.0 + m - q / (2.0 * s)));
+        roots
+    };
+
+    for root in roots.iter_mut() {
+        let mut x = *root - b / 4.0;
+
+        for _ in 0..2 {
+            let value = (((x + b) * x + c) * x + d) * x + e;
+            let slope = ((4.0 * x + 3.0 * b) * x + 2.0 * c) * x + d;
+            if slope == 0.0 {
+                break;
+            }
+            x -= value / slope;
+        }
+
+        *root = x;
+    }
+
+    finite_sorted(roots)
+}
+
+// nan or infinite coefficients give nan roots, which are dropped rather than ordered
+fn finite_sorted(mut roots: Vec<f64>) -> Vec<f64> {
+    roots.retain(|root| root.is_finite());
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(
+        actual: Vec<f64>,
+        expected: &[f64],
+    ) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, b) in actual.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-9, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn quadratic_without_cancellation() {
+        assert_roots(solve_quadratic(1.0, -3.0, 2.0), &[1.0, 2.0]);
+        assert_roots(solve_quadratic(0.0, 2.0, -1.0), &[0.5]);
+        assert_roots(solve_quadratic(1.0, 0.0, 1.0), &[]);
+
+        // the small root would be lost to cancellation in the textbook formula
+        let roots = solve_quadratic(1.0, -1e8, 1.0);
+        assert!((roots[0] - 1e-8).abs() < 1e-20, "{:?}", roots);
+    }
+
+    #[test]
+    fn cubic_with_one_and_three_roots() {
+        // (x - 1)(x - 2)(x + 3)
+        assert_roots(solve_cubic(1.0, 0.0, -7.0, 6.0), &[-3.0, 1.0, 2.0]);
+        // (x - 2)(x^2 + 1)
+        assert_roots(solve_cubic(2.0, -4.0, 2.0, -4.0), &[2.0]);
+    }
+
+    #[test]
+    fn quartic_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(
+            solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0),
+            &[1.0, 2.0, 3.0, 4.0],
+        );
+
+        // biquadratic (x^2 - 1)(x^2 - 4)
+        assert_roots(
+            solve_quartic(2.0, 0.0, -10.0, 0.0, 8.0),
+            &[-2.0, -1.0, 1.0, 2.0],
+        );
+
+        // (x - 0.5)(x + 2)(x^2 + x + 1), two complex roots
+        assert_roots(solve_quartic(1.0, 2.5, 1.5, 0.5, -1.0), &[-2.0, 0.5]);
+
+        assert_roots(solve_quartic(1.0, 0.0, 1.0, 0.0, 1.0), &[]);
+    }
+
+    #[test]
+    fn nan_coefficients_give_no_roots() {
+        assert_roots(solve_quadratic(1.0, f64::NAN, 1.0), &[]);
+        assert_roots(solve_cubic(1.0, 0.0, f64::NAN, 0.0), &[]);
+        assert_roots(solve_quartic(1.0, 0.0, f64::NAN, 0.0, -1.0), &[]);
+        assert_roots(solve_quartic(f64::NAN, 1.0, 0.0, 0.0, 0.0), &[]);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::cgmath::angle::*;
+use crate::cgmath::vec3::*;
+
+use std::ops::{Mul, Neg};
+
+/*
+ * Rotation as a unit quaternion `w + xi + yj + zk`
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion::new(0.0, 0.0, 0.0, 1.0);
+
+    pub const fn new(
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+    ) -> Quaternion {
+        Quaternion { x, y, z, w }
+    }
+
+    /*
+     * Rotation by `angle` around `axis`, counterclockwise looking against the axis
+     */
+    pub fn from_axis_angle(
+        axis: Vec3,
+        angle: Radians,
+    ) -> Quaternion {
+        let axis = axis.normalized();
+        let (sin, cos) = (0.5 * angle.0).sin_cos();
+        Quaternion::new(sin * axis.x, sin * axis.y, sin * axis.z, cos)
+    }
+
+    pub fn vector(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    pub fn dot(
+        self,
+        other: Quaternion,
+    ) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn norm(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalized(self) -> Quaternion {
+        let n = self.norm();
+        Quaternion::new(self.x / n, self.y / n, self.z / n, self.w / n)
+    }
+
+    // inverse rotation of a unit quaternion
+    pub fn conjugate(self) -> Quaternion {
+        Quaternion::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn rotate(
+        self,
+        v: Vec3,
+    ) -> Vec3 {
+        let q = self.vector();
+        let t = 2.0 * Vec3::cross(q, v);
+        v + self.w * t + Vec3::cross(q, t)
+    }
+
+    /*
+     * Constant speed interpolation along the shorter arc, `t` in [0, 1]
+     */
+    pub fn slerp(
+        t: f32,
+        a: Quaternion,
+        b: Quaternion,
+    ) -> Quaternion {
+        // q and -q are the same rotation, take the one closer to a
+        let (b, cos) = match a.dot(b) {
+            cos if cos < 0.0 => (-b, -cos),
+            cos => (b, cos),
+        };
+
+        // nearly parallel, sin of the angle would lose all precision
+        let (wa, wb) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+
+        Quaternion::new(
+            wa * a.x + wb * b.x,
+            wa * a.y + wb * b.y,
+            wa * a.z + wb * b.z,
+            wa * a.w + wb * b.w,
+        )
+        .normalized()
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Quaternion {
+        Quaternion::IDENTITY
+    }
+}
+
+impl Neg for Quaternion {
+    type Output = Quaternion;
+    fn neg(self) -> Quaternion {
+        Quaternion::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+/*
+ * Hamilton product, `a * b` rotates by `b` first
+ */
+impl Mul for Quaternion {
+    type Output = Quaternion;
+    fn mul(
+        self,
+        other: Quaternion,
+    ) -> Quaternion {
+        let (a, b) = (self, other);
+        Quaternion::new(
+            a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+            a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+        )
+    }
+}
@@ -1,9 +1,9 @@
 use crate::cgmath::angle::*;
 
-use rand::{thread_rng, Rng};
-use std::ops::{Add, Div, Mul, Neg, Range, Sub};
+use rand::{Rng, RngCore};
+use std::ops::{Add, Div, Index, Mul, Neg, Range, Sub};
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -49,6 +49,28 @@ impl Vec3 {
         self / self.norm()
     }
 
+    pub fn min(
+        self,
+        other: Vec3,
+    ) -> Vec3 {
+        Vec3 {
+            x: self.x.min(other.x),
+            y: self.y.min(other.y),
+            z: self.z.min(other.z),
+        }
+    }
+
+    pub fn max(
+        self,
+        other: Vec3,
+    ) -> Vec3 {
+        Vec3 {
+            x: self.x.max(other.x),
+            y: self.y.max(other.y),
+            z: self.z.max(other.z),
+        }
+    }
+
     pub fn lerp(
         t: f32,
         u: Vec3,
@@ -90,6 +112,10 @@ impl Vec3 {
             + (t * t * t) * d
     }
 
+    pub fn is_finite(self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+
     pub fn near_zero(self) -> bool {
         let precision = 1e-8;
         self.x.abs() < precision && self.y.abs() < precision && self.z.abs() < precision
@@ -99,37 +125,58 @@ impl Vec3 {
         self,
         other: Vec3,
     ) -> Radians {
-        let rads = self.dot(other) / (self.norm() * other.norm());
-        Radians(rads)
+        let cosine = self.dot(other) / (self.norm() * other.norm());
+        Radians(cosine.clamp(-1.0, 1.0).acos())
     }
 
-    pub fn random() -> Vec3 {
-        Vec3::new(thread_rng().gen(), thread_rng().gen(), thread_rng().gen())
+    /*
+     * Two vectors completing `self` (must be normalized) to an orthonormal basis.
+     *
+     * Duff et al. "Building an Orthonormal Basis, Revisited"
+     */
+    pub fn orthonormal_basis(self) -> (Vec3, Vec3) {
+        let sign = 1.0f32.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vec3::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+
+    pub fn random(rng: &mut dyn RngCore) -> Vec3 {
+        Vec3::new(rng.gen(), rng.gen(), rng.gen())
     }
 
-    pub fn random_range(r: Range<f32>) -> Vec3 {
+    pub fn random_range(
+        rng: &mut dyn RngCore,
+        r: Range<f32>,
+    ) -> Vec3 {
         Vec3::new(
-            thread_rng().gen_range(r.clone()),
-            thread_rng().gen_range(r.clone()),
-            thread_rng().gen_range(r),
+            rng.gen_range(r.clone()),
+            rng.gen_range(r.clone()),
+            rng.gen_range(r),
         )
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
+    pub fn random_in_unit_sphere(rng: &mut dyn RngCore) -> Vec3 {
         loop {
-            let v = Vec3::random_range(-1.0..1.0);
+            let v = Vec3::random_range(rng, -1.0..1.0);
             if v.norm_squared() < 1.0 {
                 return v;
             }
         }
     }
 
-    pub fn random_unit_vector() -> Vec3 {
-        Vec3::random_in_unit_sphere().normalized()
+    pub fn random_unit_vector(rng: &mut dyn RngCore) -> Vec3 {
+        Vec3::random_in_unit_sphere(rng).normalized()
     }
 
-    pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
-        let u = Vec3::random_unit_vector();
+    pub fn random_in_hemisphere(
+        rng: &mut dyn RngCore,
+        normal: Vec3,
+    ) -> Vec3 {
+        let u = Vec3::random_unit_vector(rng);
         if Vec3::dot(u, normal) > 0.0 {
             u
         } else {
@@ -137,13 +184,9 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_disk() -> Vec3 {
+    pub fn random_in_unit_disk(rng: &mut dyn RngCore) -> Vec3 {
         loop {
-            let v = Vec3::new(
-                thread_rng().gen_range(-1.0..1.0),
-                thread_rng().gen_range(-1.0..1.0),
-                0.0,
-            );
+            let v = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
             if v.norm_squared() < 1.0 {
                 return v;
             }
@@ -164,6 +207,33 @@ impl Vec3 {
     pub const ONE: Vec3 = Vec3::new(1.0, 1.0, 1.0);
 }
 
+impl Index<usize> for Vec3 {
+    type Output = f32;
+    fn index(
+        &self,
+        axis: usize,
+    ) -> &f32 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis out of range: {}", axis),
+        }
+    }
+}
+
+impl From<[f32; 3]> for Vec3 {
+    fn from(components: [f32; 3]) -> Vec3 {
+        Vec3::new(components[0], components[1], components[2])
+    }
+}
+
+impl From<Vec3> for [f32; 3] {
+    fn from(v: Vec3) -> [f32; 3] {
+        [v.x, v.y, v.z]
+    }
+}
+
 impl Neg for Vec3 {
     type Output = Vec3;
     fn neg(self) -> Vec3 {
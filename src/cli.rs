@@ -0,0 +1,273 @@
+use raytracer_rs::{
+    raytracer::{
+        output::{save_image, ExrPrecision, ImageFormat, RenderInfo},
+        render::{render, RenderSettings},
+        sampler::SamplerKind,
+        scene::Scene,
+        scene_file::{load_scene_file, save_scene_file},
+    },
+    scenes::{create_scene, scene_creators},
+};
+
+use anyhow::{anyhow, bail, Context};
+
+use rayon::ThreadPoolBuilder;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic,
+    time::Instant,
+};
+
+//
+//
+//
+const USAGE: &str = "\
+usage: raytracer_rs render [options]
+
+options:
+    --scene <name|file>   built in scene (see --list-scenes) or .toml scene file
+    --width <pixels>      image width
+    --height <pixels>     image height
+    --samples <count>     samples per pixel
+    --max-depth <count>   maximum number of bounces
+    --chunk-size <pixels> side of a square chunk rendered by one task
+    --seed <number>       seed for random numbers, same seed gives the same image
+    --sampler <name>      independent, stratified, halton or sobol
+    --threads <count>     worker threads, 0 uses all cores
+    --output <path>       png, hdr, pfm or exr, picked by extension. exr also gets
+                          albedo, normal and depth channels
+    --half                write exr as half float instead of float
+    --export <path>       write the scene as a .toml scene file instead of rendering
+    --list-scenes         print scene names and exit
+    --help                print this message and exit
+
+size, sample, depth, seed and sampler options override values from a scene file";
+
+struct Options {
+    scene: String,
+    // override settings from the scene file
+    image_width: Option<usize>,
+    image_height: Option<usize>,
+    sample_count: Option<usize>,
+    max_depth: Option<usize>,
+    chunk_size: Option<usize>,
+    seed: Option<u64>,
+    sampler: Option<SamplerKind>,
+    threads: usize,
+    output: PathBuf,
+    exr_precision: ExrPrecision,
+    export: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            scene: "Book 1 final scene".to_string(),
+            image_width: None,
+            image_height: None,
+            sample_count: None,
+            max_depth: None,
+            chunk_size: None,
+            seed: None,
+            sampler: None,
+            threads: 0,
+            output: PathBuf::from("render.png"),
+            exr_precision: ExrPrecision::default(),
+            export: None,
+        }
+    }
+}
+
+//
+//
+//
+
+/*
+ * Headless render, `args` are the ones following the `render` subcommand
+ */
+pub fn run(args: &[String]) -> anyhow::Result<()> {
+    let options = match parse_args(args)? {
+        Some(options) => options,
+        None => return Ok(()),
+    };
+
+    let (scene, settings) = load_scene(&options.scene, options.seed.unwrap_or(0))?;
+
+    let settings = RenderSettings {
+        image_width: options.image_width.unwrap_or(settings.image_width),
+        image_height: options.image_height.unwrap_or(settings.image_height),
+        sample_count: options.sample_count.unwrap_or(settings.sample_count),
+        max_depth: options.max_depth.unwrap_or(settings.max_depth),
+        chunk_size: options.chunk_size.unwrap_or(settings.chunk_size),
+        seed: options.seed.unwrap_or(settings.seed),
+        sampler: options.sampler.unwrap_or(settings.sampler),
+        // only exr has room for them
+        aovs: ImageFormat::from_path(&options.output)? == ImageFormat::Exr,
+    };
+
+    settings.validate()?;
+
+    if let Some(path) = &options.export {
+        save_scene_file(path, &scene, scene.camera(), Some(&settings))?;
+        log::info!("exported {}", path.display());
+        return Ok(());
+    }
+
+    log::info!(
+        "rendering '{}' at {}x{}, {} spp, max depth {}, {} sampler",
+        options.scene,
+        settings.image_width,
+        settings.image_height,
+        settings.sample_count,
+        settings.max_depth,
+        settings.sampler.name()
+    );
+
+    let thread_pool = ThreadPoolBuilder::new()
+        .num_threads(options.threads)
+        .build()
+        .context("cannot create thread pool")?;
+
+    let begin = Instant::now();
+
+    // log every 10%, chunks finish out of order so remember the last reported step
+    let last_reported = atomic::AtomicUsize::new(0);
+
+    let framebuffer = thread_pool.install(|| {
+        render(&scene, scene.camera(), &settings, |done, total| {
+            let step = done * 10 / total;
+            if last_reported.fetch_max(step, atomic::Ordering::Relaxed) < step {
+                log::info!("{}% done", step * 10);
+            }
+        })
+    });
+
+    let render_time = Instant::now() - begin;
+
+    log::info!("render finished in {:?}", render_time);
+
+    let info = RenderInfo {
+        scene: options.scene.clone(),
+        sample_count: settings.sample_count,
+        max_depth: settings.max_depth,
+        seed: settings.seed,
+        sampler: settings.sampler,
+        render_time,
+    };
+
+    save_image(
+        &options.output,
+        &framebuffer,
+        Some(&info),
+        options.exr_precision,
+    )?;
+
+    log::info!("saved {}", options.output.display());
+
+    Ok(())
+}
+
+/*
+ * Returns `None` when there is nothing to render (help or scene list was printed)
+ */
+fn parse_args(args: &[String]) -> anyhow::Result<Option<Options>> {
+    let mut options = Options::default();
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(String::as_str)
+                .ok_or_else(|| anyhow!("missing value for {}", arg))
+        };
+
+        match arg.as_str() {
+            "--scene" => options.scene = value()?.to_string(),
+            "--width" => options.image_width = Some(parse_count(arg, value()?)?),
+            "--height" => options.image_height = Some(parse_count(arg, value()?)?),
+            "--samples" => options.sample_count = Some(parse_count(arg, value()?)?),
+            "--max-depth" => options.max_depth = Some(parse_count(arg, value()?)?),
+            "--chunk-size" => options.chunk_size = Some(parse_count(arg, value()?)?),
+            "--seed" => {
+                options.seed = Some(
+                    value()?
+                        .parse()
+                        .with_context(|| format!("invalid value for {}", arg))?,
+                )
+            }
+            "--sampler" => {
+                let name = value()?;
+                options.sampler = Some(SamplerKind::from_name(name).ok_or_else(|| {
+                    anyhow!(
+                        "unknown sampler '{}', expected independent, stratified, halton or sobol",
+                        name
+                    )
+                })?)
+            }
+            "--threads" => {
+                options.threads = value()?
+                    .parse()
+                    .with_context(|| format!("invalid value for {}", arg))?
+            }
+            "--output" => options.output = PathBuf::from(value()?),
+            "--half" => options.exr_precision = ExrPrecision::Half,
+            "--export" => options.export = Some(PathBuf::from(value()?)),
+            "--list-scenes" => {
+                let mut names: Vec<_> = scene_creators().keys().copied().collect();
+                names.sort_unstable();
+                for name in names {
+                    println!("{}", name);
+                }
+                return Ok(None);
+            }
+            "--help" => {
+                println!("{}", USAGE);
+                return Ok(None);
+            }
+            _ => bail!("unknown argument '{}'\n\n{}", arg, USAGE),
+        }
+    }
+
+    // fail before rendering rather than after
+    ImageFormat::from_path(&options.output)?;
+
+    Ok(Some(options))
+}
+
+/*
+ * Scene files are told apart from built in scenes by extension, built in scenes are generated
+ * with `seed`
+ */
+fn load_scene(
+    scene: &str,
+    seed: u64,
+) -> anyhow::Result<(Scene, RenderSettings)> {
+    if scene.ends_with(".toml") {
+        let file = load_scene_file(Path::new(scene))?;
+        return Ok((file.scene, file.settings));
+    }
+
+    let scene_creator = scene_creators()
+        .get(scene)
+        .copied()
+        .ok_or_else(|| anyhow!("unknown scene '{}', see --list-scenes", scene))?;
+
+    let settings = RenderSettings {
+        seed,
+        ..RenderSettings::default()
+    };
+
+    Ok((create_scene(scene_creator, seed), settings))
+}
+
+fn parse_count(
+    arg: &str,
+    value: &str,
+) -> anyhow::Result<usize> {
+    match value.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => bail!("{} expects a positive integer, got '{}'", arg, value),
+    }
+}
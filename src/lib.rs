@@ -0,0 +1,19 @@
+/*
+ * Path tracer library, the `raytracer_rs` binary (gui and headless `render` subcommand) is a
+ * thin layer on top of it.
+ *
+ * Build a `Scene` (by hand, from an obj file or one of `scenes`), describe the image with
+ * `RenderSettings` and call `render` with a progress callback, or `render_chunks` to receive
+ * chunks as they finish.
+ */
+pub mod cgmath;
+pub mod raytracer;
+pub mod scenes;
+
+pub use crate::raytracer::{
+    camera::CameraDescription,
+    color::Color,
+    framebuffer::Framebuffer,
+    render::{render, render_chunks, Chunk, RenderSettings},
+    scene::Scene,
+};
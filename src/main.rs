@@ -1,6 +1,6 @@
+#[cfg(feature = "gui")]
 mod app;
-mod cgmath;
-mod raytracer;
+mod cli;
 
 fn init_logger() {
     use env_logger::*;
@@ -12,5 +12,30 @@ fn init_logger() {
 fn main() {
     init_logger();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("render") => {
+            if let Err(e) = cli::run(&args[1..]) {
+                log::error!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(arg) => {
+            log::error!("unknown subcommand '{}', expected 'render'", arg);
+            std::process::exit(1);
+        }
+        None => run_gui(),
+    }
+}
+
+#[cfg(feature = "gui")]
+fn run_gui() {
     eframe::run_native(Box::new(app::App::default()));
 }
+
+#[cfg(not(feature = "gui"))]
+fn run_gui() {
+    log::error!("built without the gui feature, use the 'render' subcommand");
+    std::process::exit(1);
+}
@@ -0,0 +1,154 @@
+use crate::cgmath::*;
+use crate::raytracer::ray::*;
+
+//
+//
+//
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(
+        a: Vec3,
+        b: Vec3,
+    ) -> Aabb {
+        Aabb {
+            min: Vec3::min(a, b),
+            max: Vec3::max(a, b),
+        }
+    }
+
+    /*
+     * Box that contains nothing, identity for `union`
+     */
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn union(
+        self,
+        other: Aabb,
+    ) -> Aabb {
+        Aabb {
+            min: Vec3::min(self.min, other.min),
+            max: Vec3::max(self.max, other.max),
+        }
+    }
+
+    // empty if the boxes don't overlap
+    pub fn intersection(
+        self,
+        other: Aabb,
+    ) -> Aabb {
+        Aabb {
+            min: Vec3::max(self.min, other.min),
+            max: Vec3::min(self.max, other.max),
+        }
+    }
+
+    pub fn grow(
+        self,
+        point: Vec3,
+    ) -> Aabb {
+        Aabb {
+            min: Vec3::min(self.min, point),
+            max: Vec3::max(self.max, point),
+        }
+    }
+
+    /*
+     * Box around the transformed box, each axis of the result takes the extreme of every term
+     * separately (Arvo, "Transforming Axis-Aligned Bounding Boxes")
+     */
+    pub fn transformed(
+        &self,
+        transform: &Affine3,
+    ) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+
+        let t = transform.translation;
+        let mut min = [t.x, t.y, t.z];
+        let mut max = min;
+
+        for i in 0..3 {
+            for j in 0..3 {
+                let a = transform.linear[i][j] * self.min[j];
+                let b = transform.linear[i][j] * self.max[j];
+                min[i] += a.min(b);
+                max[i] += a.max(b);
+            }
+        }
+
+        Aabb {
+            min: Vec3::from(min),
+            max: Vec3::from(max),
+        }
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let e = self.extent();
+        2.0 * (e.x * e.y + e.y * e.z + e.z * e.x)
+    }
+
+    /*
+     * Slab test, returns distance at which the ray enters the box.
+     *
+     * `inv_direction` is component-wise `1 / ray.direction()`, computed once per ray.
+     */
+    pub fn hit(
+        &self,
+        ray: &Ray,
+        inv_direction: Vec3,
+        near: f32,
+        far: f32,
+    ) -> Option<f32> {
+        let origin = *ray.origin();
+
+        let mut t_min = near;
+        let mut t_max = far;
+
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - origin[axis]) * inv_direction[axis];
+            let t1 = (self.max[axis] - origin[axis]) * inv_direction[axis];
+
+            let (t0, t1) = if inv_direction[axis] < 0.0 {
+                (t1, t0)
+            } else {
+                (t0, t1)
+            };
+
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+
+            if t_max < t_min {
+                return None;
+            }
+        }
+
+        Some(t_min)
+    }
+}
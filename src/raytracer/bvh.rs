@@ -0,0 +1,340 @@
+use crate::cgmath::*;
+use crate::raytracer::aabb::*;
+use crate::raytracer::ray::*;
+
+//
+//
+//
+
+const BIN_COUNT: usize = 16;
+const MAX_LEAF_SIZE: usize = 4;
+
+// relative costs used by the surface area heuristic
+const TRAVERSAL_COST: f32 = 1.0;
+const INTERSECTION_COST: f32 = 1.0;
+
+//
+//
+//
+
+/*
+ * Anything the bvh can return as a hit, traversal needs its distance to shrink the search
+ */
+pub trait BvhHit {
+    fn t(&self) -> f32;
+}
+
+impl BvhHit for ShapeHit {
+    fn t(&self) -> f32 {
+        self.t
+    }
+}
+
+//
+//
+//
+#[derive(Debug, Clone, Copy)]
+enum NodeKind {
+    Leaf { first: usize, count: usize },
+    // left child is always the next node, right child is stored explicitly
+    Interior { right: usize, axis: usize },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    bounds: Aabb,
+    kind: NodeKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Primitive {
+    index: usize,
+    bounds: Aabb,
+    centroid: Vec3,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bin {
+    bounds: Aabb,
+    count: usize,
+}
+
+impl Default for Bin {
+    fn default() -> Bin {
+        Bin {
+            bounds: Aabb::empty(),
+            count: 0,
+        }
+    }
+}
+
+/*
+ * Bounding volume hierarchy over primitives given by their bounding boxes.
+ *
+ * The bvh only stores indices, callers decide what a primitive is and how to hit it.
+ */
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut primitives: Vec<Primitive> = bounds
+            .iter()
+            .enumerate()
+            .map(|(index, bounds)| Primitive {
+                index,
+                bounds: *bounds,
+                centroid: bounds.centroid(),
+            })
+            .collect();
+
+        let mut nodes = Vec::with_capacity(2 * primitives.len());
+
+        if !primitives.is_empty() {
+            build_recursive(&mut nodes, &mut primitives, 0);
+        }
+
+        let indices = primitives.iter().map(|p| p.index).collect();
+
+        Bvh { nodes, indices }
+    }
+
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| node.bounds)
+    }
+
+    /*
+     * Visits primitives whose bounds the ray passes through, nearer children first.
+     *
+     * `hit_primitive` is called with primitive index and current search interval.
+     */
+    pub fn nearest_hit<H, F>(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+        mut hit_primitive: F,
+    ) -> Option<H>
+    where
+        H: BvhHit,
+        F: FnMut(usize, f32, f32) -> Option<H>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let direction = *ray.direction();
+        let inv_direction = Vec3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+        let is_negative = [direction.x < 0.0, direction.y < 0.0, direction.z < 0.0];
+
+        let mut far = far;
+        let mut nearest = None;
+
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+
+            if node.bounds.hit(ray, inv_direction, near, far).is_none() {
+                continue;
+            }
+
+            match node.kind {
+                NodeKind::Leaf { first, count } => {
+                    for &primitive in &self.indices[first..first + count] {
+                        if let Some(hit) = hit_primitive(primitive, near, far) {
+                            far = hit.t();
+                            nearest = Some(hit);
+                        }
+                    }
+                }
+                NodeKind::Interior { right, axis } => {
+                    // push farther child first so nearer one is visited first
+                    if is_negative[axis] {
+                        stack.push(index + 1);
+                        stack.push(right);
+                    } else {
+                        stack.push(right);
+                        stack.push(index + 1);
+                    }
+                }
+            }
+        }
+
+        nearest
+    }
+}
+
+//
+//
+//
+fn build_recursive(
+    nodes: &mut Vec<Node>,
+    primitives: &mut [Primitive],
+    offset: usize,
+) -> usize {
+    let bounds = primitives
+        .iter()
+        .fold(Aabb::empty(), |acc, p| acc.union(p.bounds));
+
+    let node_index = nodes.len();
+    nodes.push(Node {
+        bounds,
+        kind: NodeKind::Leaf {
+            first: offset,
+            count: primitives.len(),
+        },
+    });
+
+    if primitives.len() <= MAX_LEAF_SIZE {
+        return node_index;
+    }
+
+    let split = match find_sah_split(primitives, &bounds) {
+        Some(split) => split,
+        None => return node_index,
+    };
+
+    let mid = partition(primitives, |p| {
+        bin_index(p.centroid[split.axis], split.min, split.scale) < split.bin
+    });
+
+    // degenerate partition, can happen with many identical centroids
+    if mid == 0 || mid == primitives.len() {
+        return node_index;
+    }
+
+    let (left, right) = primitives.split_at_mut(mid);
+
+    build_recursive(nodes, left, offset);
+    let right_index = build_recursive(nodes, right, offset + mid);
+
+    nodes[node_index].kind = NodeKind::Interior {
+        right: right_index,
+        axis: split.axis,
+    };
+
+    node_index
+}
+
+struct Split {
+    axis: usize,
+    bin: usize,
+    min: f32,
+    scale: f32,
+}
+
+/*
+ * Binned surface area heuristic, returns `None` when making a leaf is cheaper than any split
+ */
+fn find_sah_split(
+    primitives: &[Primitive],
+    bounds: &Aabb,
+) -> Option<Split> {
+    let centroid_bounds = primitives
+        .iter()
+        .fold(Aabb::empty(), |acc, p| acc.grow(p.centroid));
+
+    let leaf_cost = INTERSECTION_COST * primitives.len() as f32;
+    let parent_area = bounds.surface_area();
+
+    let mut best: Option<(f32, Split)> = None;
+
+    for axis in 0..3 {
+        let min = centroid_bounds.min[axis];
+        let max = centroid_bounds.max[axis];
+
+        if max - min <= f32::EPSILON {
+            continue;
+        }
+
+        let scale = BIN_COUNT as f32 / (max - min);
+
+        let mut bins = [Bin::default(); BIN_COUNT];
+        for p in primitives {
+            let bin = &mut bins[bin_index(p.centroid[axis], min, scale)];
+            bin.bounds = bin.bounds.union(p.bounds);
+            bin.count += 1;
+        }
+
+        // sweep from the right to get cost of every right side
+        let mut right_areas = [0.0; BIN_COUNT];
+        let mut right_counts = [0; BIN_COUNT];
+        {
+            let mut acc = Bin::default();
+            for i in (1..BIN_COUNT).rev() {
+                acc.bounds = acc.bounds.union(bins[i].bounds);
+                acc.count += bins[i].count;
+                right_areas[i] = acc.bounds.surface_area();
+                right_counts[i] = acc.count;
+            }
+        }
+
+        let mut acc = Bin::default();
+        for i in 1..BIN_COUNT {
+            acc.bounds = acc.bounds.union(bins[i - 1].bounds);
+            acc.count += bins[i - 1].count;
+
+            if acc.count == 0 || right_counts[i] == 0 {
+                continue;
+            }
+
+            let cost = TRAVERSAL_COST
+                + INTERSECTION_COST
+                    * (acc.bounds.surface_area() * acc.count as f32
+                        + right_areas[i] * right_counts[i] as f32)
+                    / parent_area;
+
+            if best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost) {
+                best = Some((
+                    cost,
+                    Split {
+                        axis,
+                        bin: i,
+                        min,
+                        scale,
+                    },
+                ));
+            }
+        }
+    }
+
+    match best {
+        Some((cost, split)) if cost < leaf_cost || primitives.len() > 4 * MAX_LEAF_SIZE => {
+            Some(split)
+        }
+        _ => None,
+    }
+}
+
+fn bin_index(
+    value: f32,
+    min: f32,
+    scale: f32,
+) -> usize {
+    (((value - min) * scale) as usize).min(BIN_COUNT - 1)
+}
+
+/*
+ * Moves elements satisfying `predicate` to the front, returns their count
+ */
+fn partition<T, P>(
+    items: &mut [T],
+    predicate: P,
+) -> usize
+where
+    P: Fn(&T) -> bool,
+{
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if predicate(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
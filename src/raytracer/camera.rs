@@ -1,5 +1,46 @@
 use crate::cgmath::*;
 use crate::raytracer::ray::*;
+use crate::raytracer::sampler::*;
+
+/*
+ * Where a scene is viewed from, turned into a `Camera` once image aspect ratio is known
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct CameraDescription {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub vertical_fov: Degrees,
+    pub aperture: f32,
+    pub focus_distance: f32,
+    // rays get times uniformly distributed in this interval, equal times disable motion blur
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+}
+
+impl CameraDescription {
+    pub fn build(
+        &self,
+        aspect_ratio: f32,
+    ) -> Camera {
+        Camera::new(self, aspect_ratio)
+    }
+}
+
+impl Default for CameraDescription {
+    fn default() -> Self {
+        CameraDescription {
+            eye: Vec3::new(13.0, 2.0, 3.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            vertical_fov: Degrees(20.0),
+            aperture: 0.1,
+            focus_distance: 10.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Camera {
@@ -9,20 +50,29 @@ pub struct Camera {
     vertical: Vec3,
     u: Vec3,
     v: Vec3,
-    w: Vec3,
     lens_radius: f32,
+    shutter_open: f32,
+    shutter_close: f32,
 }
 
 impl Camera {
     pub fn new(
-        eye: Vec3,
-        target: Vec3,
-        up: Vec3,
-        vertical_fov: Radians,
+        description: &CameraDescription,
         aspect_ratio: f32,
-        aperture: f32,
-        focal_distance: f32,
     ) -> Camera {
+        let CameraDescription {
+            eye,
+            target,
+            up,
+            vertical_fov,
+            aperture,
+            focus_distance: focal_distance,
+            shutter_open,
+            shutter_close,
+        } = *description;
+
+        let vertical_fov: Radians = vertical_fov.into();
+
         let h = (vertical_fov.0 * 0.5).tan();
         let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
@@ -44,22 +94,32 @@ impl Camera {
             vertical,
             u,
             v,
-            w,
             lens_radius,
+            shutter_open,
+            shutter_close,
         }
     }
 
+    /*
+     * Ray through viewport coordinates `s`, `t`, lens position and time are taken from
+     * `LENS_DIMENSION` and `TIME_DIMENSION` of `sampler`
+     */
     pub fn ray_at(
         &self,
         s: f32,
         t: f32,
+        sampler: &mut dyn Sampler,
     ) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+        sampler.set_dimension(LENS_DIMENSION);
+        let rd = self.lens_radius * sample_unit_disk(sampler.get_2d());
         let offset = self.u * rd.x + self.v * rd.y;
 
+        sampler.set_dimension(TIME_DIMENSION);
+        let time = self.shutter_open + sampler.get_1d() * (self.shutter_close - self.shutter_open);
+
         let origin = self.eye + offset;
         let direction = self.lower_left_corner + s * self.horizontal + t * self.vertical - origin;
 
-        Ray::new(origin, direction)
+        Ray::with_time(origin, direction, time)
     }
 }
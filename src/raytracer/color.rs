@@ -1,8 +1,12 @@
 use crate::cgmath::*;
 
-use rand::{thread_rng, Rng};
-use std::ops::{Add, Mul, Sub};
+use rand::{Rng, RngCore};
+use std::ops::{Add, Div, Mul, Sub};
 
+/*
+ * Linear rgb radiance, never clamped. Only `as_u8` clamps, when color is finally displayed or
+ * exported.
+ */
 #[derive(Debug, Clone, Copy)]
 pub struct Color {
     r: f32,
@@ -16,19 +20,38 @@ impl Color {
         g: f32,
         b: f32,
     ) -> Color {
+        Color { r, g, b }
+    }
+
+    // quantizes to 8 bits, components outside [0, 1] are clamped
+    pub fn as_u8(&self) -> [u8; 3] {
+        let quantize = |c: f32| (c.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+        [quantize(self.r), quantize(self.g), quantize(self.b)]
+    }
+
+    pub fn clamped(&self) -> Color {
         Color {
-            r: r.clamp(0.0, 1.0),
-            g: g.clamp(0.0, 1.0),
-            b: b.clamp(0.0, 1.0),
+            r: self.r.clamp(0.0, 1.0),
+            g: self.g.clamp(0.0, 1.0),
+            b: self.b.clamp(0.0, 1.0),
         }
     }
 
-    pub fn as_u8(&self) -> [u8; 3] {
-        [
-            (self.r * 255.0) as u8,
-            (self.g * 255.0) as u8,
-            (self.b * 255.0) as u8,
-        ]
+    // gamma 2, matches what the renderer always did before display
+    pub fn gamma_corrected(&self) -> Color {
+        Color {
+            r: self.r.max(0.0).sqrt(),
+            g: self.g.max(0.0).sqrt(),
+            b: self.b.max(0.0).sqrt(),
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.r.is_finite() && self.g.is_finite() && self.b.is_finite()
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.r == 0.0 && self.g == 0.0 && self.b == 0.0
     }
 
     pub fn r(&self) -> f32 {
@@ -43,31 +66,43 @@ impl Color {
         self.b
     }
 
-    pub fn random() -> Color {
+    pub fn random(rng: &mut dyn RngCore) -> Color {
         Color {
-            r: thread_rng().gen_range(0.0..1.0),
-            g: thread_rng().gen_range(0.0..1.0),
-            b: thread_rng().gen_range(0.0..1.0),
+            r: rng.gen_range(0.0..1.0),
+            g: rng.gen_range(0.0..1.0),
+            b: rng.gen_range(0.0..1.0),
         }
     }
 }
 
-impl Into<Vec3> for Color {
-    fn into(self) -> Vec3 {
-        Vec3::new(self.r, self.g, self.b)
+impl From<Color> for Vec3 {
+    fn from(color: Color) -> Vec3 {
+        Vec3::new(color.r, color.g, color.b)
     }
 }
 
 impl From<Vec3> for Color {
     fn from(components: Vec3) -> Color {
         Color {
-            r: components.x.clamp(0.0, 1.0),
-            g: components.y.clamp(0.0, 1.0),
-            b: components.z.clamp(0.0, 1.0),
+            r: components.x,
+            g: components.y,
+            b: components.z,
         }
     }
 }
 
+impl From<[f32; 3]> for Color {
+    fn from(components: [f32; 3]) -> Color {
+        Color::from_rgb(components[0], components[1], components[2])
+    }
+}
+
+impl From<Color> for [f32; 3] {
+    fn from(color: Color) -> [f32; 3] {
+        [color.r, color.g, color.b]
+    }
+}
+
 impl Add for Color {
     type Output = Color;
     fn add(
@@ -108,6 +143,16 @@ impl Mul<Color> for f32 {
     }
 }
 
+impl Div<f32> for Color {
+    type Output = Color;
+    fn div(
+        self,
+        c: f32,
+    ) -> Color {
+        Color::from_rgb(self.r / c, self.g / c, self.b / c)
+    }
+}
+
 impl Mul for Color {
     type Output = Color;
     fn mul(
@@ -0,0 +1,303 @@
+use crate::raytracer::aabb::*;
+use crate::raytracer::description::{ShapeDescription, ShapeRef};
+use crate::raytracer::ray::*;
+use crate::raytracer::scene::*;
+
+use serde::{Deserialize, Serialize};
+
+use std::sync::Arc;
+
+//
+//
+//
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // left minus right
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(
+        self,
+        in_left: bool,
+        in_right: bool,
+    ) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/*
+ * Constructive solid geometry, two closed shapes of the scene combined without copying them.
+ *
+ * Walks the crossings of both shapes along a ray in order, keeping track of being inside each,
+ * and keeps those where being inside the result changes. Whether a ray starts inside a shape
+ * is told by its first crossing leaving it.
+ */
+#[derive(Debug)]
+pub struct Csg {
+    operation: CsgOperation,
+    left_id: ShapeId,
+    left: Arc<dyn HittableShape>,
+    right_id: ShapeId,
+    right: Arc<dyn HittableShape>,
+}
+
+impl Csg {
+    pub fn new(
+        scene: &Scene,
+        operation: CsgOperation,
+        left: ShapeId,
+        right: ShapeId,
+    ) -> Csg {
+        Csg {
+            operation,
+            left_id: left,
+            left: scene.shared_shape(left),
+            right_id: right,
+            right: scene.shared_shape(right),
+        }
+    }
+
+    pub fn operation(&self) -> CsgOperation {
+        self.operation
+    }
+
+    fn crossings(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+        first_only: bool,
+    ) -> Vec<ShapeHit> {
+        // past `far` too, a shape the ray is inside of until then is only known by leaving it
+        let left = self.left.hits(ray, near, f32::INFINITY);
+        let right = self.right.hits(ray, near, f32::INFINITY);
+
+        let starts_inside = |hits: &[ShapeHit]| hits.first().is_some_and(|hit| !hit.is_front_face);
+        let mut inside = [starts_inside(&left), starts_inside(&right)];
+
+        let mut crossings = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        loop {
+            let (side, hit) = match (left.get(i), right.get(j)) {
+                (Some(a), Some(b)) if a.t <= b.t => (0, a),
+                (Some(a), None) => (0, a),
+                (_, Some(b)) => (1, b),
+                (None, None) => break,
+            };
+
+            if hit.t >= far {
+                break;
+            }
+
+            if side == 0 {
+                i += 1;
+            } else {
+                j += 1;
+            }
+
+            let was_inside = self.operation.contains(inside[0], inside[1]);
+            inside[side] = hit.is_front_face;
+            let is_inside = self.operation.contains(inside[0], inside[1]);
+
+            // normals face the ray already, only whether it enters the result changes
+            if was_inside != is_inside {
+                crossings.push(ShapeHit {
+                    is_front_face: is_inside,
+                    ..hit.clone()
+                });
+
+                if first_only {
+                    break;
+                }
+            }
+        }
+
+        crossings
+    }
+}
+
+impl HittableShape for Csg {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        self.crossings(ray, near, far, true).into_iter().next()
+    }
+
+    fn hits(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Vec<ShapeHit> {
+        self.crossings(ray, near, far, false)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (left, right) = (self.left.bounding_box(), self.right.bounding_box());
+
+        match self.operation {
+            CsgOperation::Union => Some(left?.union(right?)),
+            CsgOperation::Intersection => match (left, right) {
+                (Some(left), Some(right)) => Some(left.intersection(right)),
+                (left, right) => left.or(right),
+            },
+            CsgOperation::Difference => left,
+        }
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Csg {
+            operation: self.operation,
+            left: Box::new(ShapeRef::Id(self.left_id)),
+            right: Box::new(ShapeRef::Id(self.right_id)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cgmath::*;
+    use crate::raytracer::instance::*;
+    use crate::raytracer::shape::*;
+
+    // unit spheres at x = -0.5 and 0.5
+    fn overlapping_spheres(operation: CsgOperation) -> (Scene, Csg) {
+        let mut scene = Scene::new();
+        let left = scene.insert_shape(Sphere {
+            center: Vec3::new(-0.5, 0.0, 0.0),
+            radius: 1.0,
+        });
+        let right = scene.insert_shape(Sphere {
+            center: Vec3::new(0.5, 0.0, 0.0),
+            radius: 1.0,
+        });
+        let csg = Csg::new(&scene, operation, left, right);
+        (scene, csg)
+    }
+
+    // distance and front face of every crossing
+    fn crossings(
+        shape: &dyn HittableShape,
+        ray: &Ray,
+    ) -> Vec<(f32, bool)> {
+        shape
+            .hits(ray, 0.0, f32::INFINITY)
+            .iter()
+            .map(|hit| (hit.t, hit.is_front_face))
+            .collect()
+    }
+
+    fn assert_crossings(
+        actual: Vec<(f32, bool)>,
+        expected: &[(f32, bool)],
+    ) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, b) in actual.iter().zip(expected) {
+            assert!((a.0 - b.0).abs() < 1e-4 && a.1 == b.1, "{:?}", actual);
+        }
+    }
+
+    #[test]
+    fn operations_keep_the_right_crossings() {
+        // spheres span x in [-1.5, 0.5] and [-0.5, 1.5]
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::X);
+
+        let (_, union) = overlapping_spheres(CsgOperation::Union);
+        assert_crossings(crossings(&union, &ray), &[(3.5, true), (6.5, false)]);
+
+        let (_, intersection) = overlapping_spheres(CsgOperation::Intersection);
+        assert_crossings(crossings(&intersection, &ray), &[(4.5, true), (5.5, false)]);
+
+        let (_, difference) = overlapping_spheres(CsgOperation::Difference);
+        assert_crossings(crossings(&difference, &ray), &[(3.5, true), (4.5, false)]);
+
+        // nearest crossing past `near`, and nothing at or beyond `far`
+        let hit = difference.hit(&ray, 4.0, f32::INFINITY).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-4 && !hit.is_front_face);
+        assert!((hit.normal - -Vec3::X).norm() < 1e-4);
+        assert!(difference.hit(&ray, 4.0, 4.5).is_none());
+    }
+
+    #[test]
+    fn rays_starting_inside() {
+        let ray = Ray::new(Vec3::ZERO, Vec3::X);
+
+        let (_, union) = overlapping_spheres(CsgOperation::Union);
+        assert_crossings(crossings(&union, &ray), &[(1.5, false)]);
+
+        // inside the left sphere but also the right one, so outside of the difference
+        let (_, difference) = overlapping_spheres(CsgOperation::Difference);
+        assert!(difference.hit(&ray, 0.0, f32::INFINITY).is_none());
+        let back = Ray::new(Vec3::ZERO, -Vec3::X);
+        assert_crossings(crossings(&difference, &back), &[(0.5, true), (1.5, false)]);
+    }
+
+    #[test]
+    fn hollow_sphere() {
+        let mut scene = Scene::new();
+        let outer = scene.insert_shape(Sphere {
+            center: Vec3::ZERO,
+            radius: 2.0,
+        });
+        let inner = scene.insert_shape(Sphere {
+            center: Vec3::ZERO,
+            radius: 1.0,
+        });
+        let shell = Csg::new(&scene, CsgOperation::Difference, outer, inner);
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::Z);
+        assert_crossings(
+            crossings(&shell, &ray),
+            &[(3.0, true), (4.0, false), (6.0, true), (7.0, false)],
+        );
+
+        // from the cavity the inner surface is entered
+        let ray = Ray::new(Vec3::ZERO, Vec3::Y);
+        assert_crossings(crossings(&shell, &ray), &[(1.0, true), (2.0, false)]);
+
+        let bounds = shell.bounding_box().unwrap();
+        assert!((bounds.max - Vec3::new(2.0, 2.0, 2.0)).norm() < 1e-6);
+    }
+
+    #[test]
+    fn nested_and_instanced_operands() {
+        let (mut scene, lens) = overlapping_spheres(CsgOperation::Intersection);
+        let lens = scene.insert_shape(lens);
+
+        // cut in half by a box, then moved along x
+        let half = scene.insert_shape(Cuboid::new(
+            Vec3::new(-2.0, 0.0, -2.0),
+            Vec3::new(2.0, 2.0, 2.0),
+        ));
+        let cut = scene.insert_shape(Csg::new(&scene, CsgOperation::Difference, lens, half));
+        let moved = Instance::new(&scene, cut, Affine3::from_translation(Vec3::X));
+
+        let ray = Ray::new(Vec3::new(1.0, 5.0, 0.0), -Vec3::Y);
+        let expected_top = 5.0;
+        let expected_bottom = 5.0 + 0.75f32.sqrt();
+        assert_crossings(
+            crossings(&moved, &ray),
+            &[(expected_top, true), (expected_bottom, false)],
+        );
+
+        let bounds = Csg::new(&scene, CsgOperation::Intersection, lens, half)
+            .bounding_box()
+            .unwrap();
+        assert!((bounds.min - Vec3::new(-0.5, 0.0, -1.0)).norm() < 1e-6);
+        assert!((bounds.max - Vec3::new(0.5, 1.0, 1.0)).norm() < 1e-6);
+    }
+}
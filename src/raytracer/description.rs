@@ -0,0 +1,383 @@
+use crate::raytracer::csg::CsgOperation;
+use crate::raytracer::scene::ShapeId;
+use crate::raytracer::texture::*;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use std::path::PathBuf;
+
+//
+//
+//
+pub type Vec3Desc = [f32; 3];
+pub type ColorDesc = [f32; 3];
+pub type UvDesc = [f32; 2];
+
+/*
+ * TOML integers are `i64`, seeds past `i64::MAX` are written as their two's complement, so every
+ * generated seed can be exported
+ */
+pub mod seed {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(
+        seed: &u64,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_i64(*seed as i64)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        i64::deserialize(deserializer).map(|seed| seed as u64)
+    }
+}
+
+/*
+ * Material as written in scene files, see `Material::description`
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDescription {
+    Lambertian {
+        albedo: ColorSource,
+    },
+    Metal {
+        albedo: ColorSource,
+        #[serde(default)]
+        fuzz: f32,
+    },
+    Dielectric {
+        refraction_index: f32,
+    },
+    DiffuseLight {
+        emit: ColorSource,
+    },
+    Isotropic {
+        albedo: ColorSource,
+    },
+    HenyeyGreenstein {
+        albedo: ColorSource,
+        // mean cosine of scattering, positive scatters forwards
+        g: f32,
+    },
+}
+
+/*
+ * Colour parameter of a material or texture: `[r, g, b]`, name of a texture or inline texture
+ * table
+ */
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum ColorSource {
+    Color(ColorDesc),
+    Named(String),
+    Texture(Box<TextureDescription>),
+}
+
+impl ColorSource {
+    // constant textures are written as plain colours
+    pub fn from_texture(texture: &dyn Texture) -> Option<ColorSource> {
+        Some(match texture.description()? {
+            TextureDescription::Constant { color } => ColorSource::Color(color),
+            description => ColorSource::Texture(Box::new(description)),
+        })
+    }
+
+    // names of textures this refers to, directly or through nested textures
+    pub fn texture_names(&self) -> Vec<&str> {
+        match self {
+            ColorSource::Color(_) => Vec::new(),
+            ColorSource::Named(name) => vec![name],
+            ColorSource::Texture(texture) => texture.texture_names(),
+        }
+    }
+}
+
+/*
+ * Texture as written in scene files, see `Texture::description`
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureDescription {
+    Constant {
+        color: ColorDesc,
+    },
+    Checker {
+        even: ColorSource,
+        odd: ColorSource,
+        #[serde(default = "default_checker_size")]
+        size: f32,
+        #[serde(default)]
+        space: CheckerSpace,
+    },
+    Image {
+        // relative to the scene file
+        path: PathBuf,
+        #[serde(default)]
+        wrap: WrapMode,
+        #[serde(default)]
+        filter: TextureFilter,
+    },
+    Noise {
+        noise: NoiseKind,
+        #[serde(default = "default_noise_scale")]
+        scale: f32,
+        #[serde(default, with = "seed")]
+        seed: u64,
+    },
+}
+
+impl TextureDescription {
+    pub fn texture_names(&self) -> Vec<&str> {
+        match self {
+            TextureDescription::Checker { even, odd, .. } => {
+                let mut names = even.texture_names();
+                names.extend(odd.texture_names());
+                names
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn default_checker_size() -> f32 {
+    1.0
+}
+
+fn default_noise_scale() -> f32 {
+    1.0
+}
+
+fn default_grid_density() -> f32 {
+    1.0
+}
+
+fn default_capped() -> bool {
+    true
+}
+
+/*
+ * Shape as written in scene files, see `HittableShape::description`
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ShapeDescription {
+    Sphere {
+        center: Vec3Desc,
+        radius: f32,
+    },
+    MovingSphere {
+        center0: Vec3Desc,
+        center1: Vec3Desc,
+        #[serde(default)]
+        time0: f32,
+        #[serde(default = "default_time1")]
+        time1: f32,
+        radius: f32,
+    },
+    Triangle {
+        positions: [Vec3Desc; 3],
+        normals: Option<[Vec3Desc; 3]>,
+        uvs: Option<[UvDesc; 3]>,
+    },
+    Mesh {
+        positions: Vec<Vec3Desc>,
+        normals: Option<Vec<Vec3Desc>>,
+        uvs: Option<Vec<UvDesc>>,
+        indices: Vec<[usize; 3]>,
+    },
+    Quad {
+        corner: Vec3Desc,
+        edge_u: Vec3Desc,
+        edge_v: Vec3Desc,
+    },
+    Box {
+        min: Vec3Desc,
+        max: Vec3Desc,
+    },
+    Plane {
+        point: Vec3Desc,
+        normal: Vec3Desc,
+    },
+    Disk {
+        center: Vec3Desc,
+        normal: Vec3Desc,
+        radius: f32,
+        #[serde(default)]
+        inner_radius: f32,
+    },
+    Cylinder {
+        base: Vec3Desc,
+        radius: f32,
+        height: f32,
+        #[serde(default = "default_capped")]
+        capped: bool,
+    },
+    Cone {
+        base: Vec3Desc,
+        radius: f32,
+        height: f32,
+        #[serde(default = "default_capped")]
+        capped: bool,
+    },
+    Torus {
+        center: Vec3Desc,
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    Instance {
+        shape: Box<ShapeRef>,
+        #[serde(default)]
+        transform: Vec<TransformStep>,
+    },
+    ConstantMedium {
+        boundary: Box<ShapeRef>,
+        density: f32,
+    },
+    GridMedium {
+        boundary: Box<ShapeRef>,
+        // relative to the scene file
+        path: PathBuf,
+        #[serde(default = "default_grid_density")]
+        density: f32,
+    },
+    NoiseMedium {
+        boundary: Box<ShapeRef>,
+        density: f32,
+        noise: NoiseKind,
+        #[serde(default = "default_noise_scale")]
+        scale: f32,
+        #[serde(default, with = "seed")]
+        seed: u64,
+    },
+    Csg {
+        operation: CsgOperation,
+        left: Box<ShapeRef>,
+        right: Box<ShapeRef>,
+    },
+}
+
+impl ShapeDescription {
+    // shapes this one is made of, for instances, media and csg
+    pub fn inner_shapes(&self) -> Vec<&ShapeRef> {
+        match self {
+            ShapeDescription::Instance { shape, .. } => vec![shape],
+            ShapeDescription::ConstantMedium { boundary, .. }
+            | ShapeDescription::GridMedium { boundary, .. }
+            | ShapeDescription::NoiseMedium { boundary, .. } => vec![boundary],
+            ShapeDescription::Csg { left, right, .. } => vec![left, right],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn inner_shapes_mut(&mut self) -> Vec<&mut ShapeRef> {
+        match self {
+            ShapeDescription::Instance { shape, .. } => vec![shape],
+            ShapeDescription::ConstantMedium { boundary, .. }
+            | ShapeDescription::GridMedium { boundary, .. }
+            | ShapeDescription::NoiseMedium { boundary, .. } => vec![boundary],
+            ShapeDescription::Csg { left, right, .. } => vec![left, right],
+            _ => Vec::new(),
+        }
+    }
+
+    // names of shapes this refers to, directly or through inline shapes
+    pub fn shape_names(&self) -> Vec<&str> {
+        self.inner_shapes()
+            .into_iter()
+            .flat_map(|shape| match shape {
+                ShapeRef::Named(name) => vec![name.as_str()],
+                ShapeRef::Inline(shape) => shape.shape_names(),
+                ShapeRef::Id(_) => Vec::new(),
+            })
+            .collect()
+    }
+}
+
+fn default_time1() -> f32 {
+    1.0
+}
+
+/*
+ * Shape used by an object or instance
+ */
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum ShapeRef {
+    Named(String),
+    Inline(ShapeDescription),
+    // shape of the scene, only in descriptions of instances, export replaces it with a name
+    #[serde(skip)]
+    Id(ShapeId),
+}
+
+/*
+ * Part of an instance transform
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum TransformStep {
+    Translate(Vec3Desc),
+    Scale(ScaleDesc),
+    Rotate {
+        axis: Vec3Desc,
+        // degrees, counterclockwise looking against the axis
+        angle: f32,
+    },
+    // top three rows of a 4x4 matrix applied to column vectors
+    Matrix([[f32; 4]; 3]),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ScaleDesc {
+    Uniform(f32),
+    Axes(Vec3Desc),
+}
+
+/*
+ * Colour, texture name or inline texture table
+ */
+impl<'de> Deserialize<'de> for ColorSource {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        match toml::Value::deserialize(deserializer)? {
+            toml::Value::String(name) => Ok(ColorSource::Named(name)),
+            value @ toml::Value::Array(_) => ColorDesc::deserialize(value)
+                .map(ColorSource::Color)
+                .map_err(D::Error::custom),
+            value => TextureDescription::deserialize(value)
+                .map(|texture| ColorSource::Texture(Box::new(texture)))
+                .map_err(D::Error::custom),
+        }
+    }
+}
+
+/*
+ * Shape name or inline shape table
+ */
+impl<'de> Deserialize<'de> for ShapeRef {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        match toml::Value::deserialize(deserializer)? {
+            toml::Value::String(name) => Ok(ShapeRef::Named(name)),
+            value => ShapeDescription::deserialize(value)
+                .map(ShapeRef::Inline)
+                .map_err(D::Error::custom),
+        }
+    }
+}
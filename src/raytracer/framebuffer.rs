@@ -0,0 +1,123 @@
+use crate::raytracer::color::*;
+
+//
+//
+//
+
+/*
+ * Arbitrary output variable, an extra per pixel channel next to color (depth, normal.x, ...)
+ */
+#[derive(Debug, Clone)]
+pub struct Aov {
+    name: String,
+    values: Vec<f32>,
+}
+
+impl Aov {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+}
+
+/*
+ * Rendered image in linear, unclamped radiance. Row major, top row first.
+ */
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    aovs: Vec<Aov>,
+}
+
+impl Framebuffer {
+    pub fn new(
+        width: usize,
+        height: usize,
+    ) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Color::from_rgb(0.0, 0.0, 0.0); width * height],
+            aovs: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn get(
+        &self,
+        x: usize,
+        y: usize,
+    ) -> Color {
+        assert!(x < self.width && y < self.height);
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(
+        &mut self,
+        x: usize,
+        y: usize,
+        color: Color,
+    ) {
+        assert!(x < self.width && y < self.height);
+        self.pixels[y * self.width + x] = color;
+    }
+
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+
+    // index for `set_aov` of the aov called `name`
+    pub fn aov_index(
+        &self,
+        name: &str,
+    ) -> Option<usize> {
+        self.aovs.iter().position(|aov| aov.name == name)
+    }
+
+    // returns index for `set_aov`
+    pub fn add_aov(
+        &mut self,
+        name: &str,
+    ) -> usize {
+        self.aovs.push(Aov {
+            name: name.to_string(),
+            values: vec![0.0; self.width * self.height],
+        });
+        self.aovs.len() - 1
+    }
+
+    pub fn set_aov(
+        &mut self,
+        aov: usize,
+        x: usize,
+        y: usize,
+        value: f32,
+    ) {
+        assert!(x < self.width && y < self.height);
+        self.aovs[aov].values[y * self.width + x] = value;
+    }
+
+    // gamma corrected and clamped 8 bit rgb, as shown on screen
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|color| color.gamma_corrected().as_u8())
+            .collect()
+    }
+}
@@ -0,0 +1,373 @@
+use crate::cgmath::*;
+use crate::raytracer::aabb::*;
+use crate::raytracer::description::{ShapeDescription, ShapeRef, TransformStep};
+use crate::raytracer::medium::*;
+use crate::raytracer::ray::*;
+use crate::raytracer::sampler::*;
+use crate::raytracer::scene::*;
+
+use std::sync::Arc;
+
+/*
+ * Shape of the scene placed by an affine transform, without copying it. Rays are moved into the
+ * space of the shape instead, so any number of instances share one mesh and its bvh.
+ */
+#[derive(Debug)]
+pub struct Instance {
+    shape_id: ShapeId,
+    shape: Arc<dyn HittableShape>,
+    // shape space to world space
+    transform: Affine3,
+    inverse: Affine3,
+}
+
+impl Instance {
+    /*
+     * Panics if `transform` is singular
+     */
+    pub fn new(
+        scene: &Scene,
+        shape: ShapeId,
+        transform: Affine3,
+    ) -> Instance {
+        let inverse = transform
+            .inverse()
+            .expect("instance transform must be invertible");
+
+        Instance {
+            shape_id: shape,
+            shape: scene.shared_shape(shape),
+            transform,
+            inverse,
+        }
+    }
+
+    pub fn transform(&self) -> &Affine3 {
+        &self.transform
+    }
+
+    /*
+     * Ray in shape space, and how much longer its distances are than in world space
+     */
+    fn to_shape_space(
+        &self,
+        ray: &Ray,
+    ) -> (Ray, f32) {
+        let direction = self.inverse.transform_vector(*ray.direction());
+        let origin = self.inverse.transform_point(*ray.origin());
+
+        (
+            Ray::with_time(origin, direction, ray.time()),
+            direction.norm(),
+        )
+    }
+
+    fn to_world_space(
+        &self,
+        hit: &ShapeHit,
+        scale: f32,
+    ) -> ShapeHit {
+        ShapeHit {
+            point: self.transform.transform_point(hit.point),
+            normal: self
+                .inverse
+                .transform_vector_transposed(hit.normal)
+                .normalized(),
+            t: hit.t / scale,
+            ..*hit
+        }
+    }
+
+    /*
+     * Converts solid angle density of `hit` seen from `origin` from shape to world space.
+     *
+     * Through area density on the surface: solid angle to area is `cos / distance^2` on both
+     * sides, and an area element grows by `|det| * |M^-T n|` (Nanson's formula). Only a
+     * similarity leaves the density unchanged.
+     */
+    fn world_pdf(
+        &self,
+        shape_pdf: f32,
+        shape_origin: Vec3,
+        shape_hit: &ShapeHit,
+        origin: Vec3,
+        hit: &ShapeHit,
+    ) -> f32 {
+        let area_density = |origin: Vec3, hit: &ShapeHit| {
+            let to_hit = hit.point - origin;
+            let distance_squared = to_hit.norm_squared();
+            let cosine = Vec3::dot(hit.normal, to_hit).abs() / distance_squared.sqrt();
+            cosine / distance_squared
+        };
+
+        let area_scale = self.transform.determinant().abs()
+            * self
+                .inverse
+                .transform_vector_transposed(shape_hit.normal)
+                .norm();
+
+        let world_area_density = area_density(origin, hit);
+        if world_area_density == 0.0 {
+            return 0.0;
+        }
+
+        shape_pdf * area_density(shape_origin, shape_hit) / (area_scale * world_area_density)
+    }
+}
+
+impl HittableShape for Instance {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        let (shape_ray, scale) = self.to_shape_space(ray);
+        let hit = self.shape.hit(&shape_ray, near * scale, far * scale)?;
+        Some(self.to_world_space(&hit, scale))
+    }
+
+    // crossings are found in shape space, rounding distances back and forth could repeat one
+    fn hits(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Vec<ShapeHit> {
+        let (shape_ray, scale) = self.to_shape_space(ray);
+        self.shape
+            .hits(&shape_ray, near * scale, far * scale)
+            .iter()
+            .map(|hit| self.to_world_space(hit, scale))
+            .collect()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.shape.bounding_box()?.transformed(&self.transform))
+    }
+
+    fn sample(
+        &self,
+        origin: Vec3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ShapeSample> {
+        let shape_origin = self.inverse.transform_point(origin);
+        let sample = self.shape.sample(shape_origin, time, sampler)?;
+
+        let point = self.transform.transform_point(sample.hit.point);
+        let scale = (sample.hit.point - shape_origin).norm() / (point - origin).norm();
+        let hit = self.to_world_space(&sample.hit, scale);
+
+        let pdf = self.world_pdf(sample.pdf, shape_origin, &sample.hit, origin, &hit);
+        if pdf == 0.0 || !pdf.is_finite() {
+            return None;
+        }
+
+        Some(ShapeSample { hit, pdf })
+    }
+
+    fn pdf(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        time: f32,
+    ) -> f32 {
+        let (shape_ray, scale) = self.to_shape_space(&Ray::with_time(origin, direction, time));
+
+        let shape_hit = match self.shape.hit(&shape_ray, 0.0, f32::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+
+        let shape_pdf = self
+            .shape
+            .pdf(*shape_ray.origin(), *shape_ray.direction(), time);
+
+        let hit = self.to_world_space(&shape_hit, scale);
+
+        self.world_pdf(shape_pdf, *shape_ray.origin(), &shape_hit, origin, &hit)
+    }
+
+    // the medium of the shape moves along with it
+    fn medium(&self) -> Option<&dyn Medium> {
+        self.shape.medium().map(|_| self as &dyn Medium)
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Instance {
+            shape: Box::new(ShapeRef::Id(self.shape_id)),
+            transform: vec![TransformStep::Matrix(self.transform.rows())],
+        })
+    }
+}
+
+/*
+ * Medium of the instanced shape, distances are converted both ways like for hits
+ */
+impl Medium for Instance {
+    fn sample_scattering(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<f32> {
+        let medium = self.shape.medium()?;
+        let (shape_ray, scale) = self.to_shape_space(ray);
+
+        let t = medium.sample_scattering(&shape_ray, near * scale, far * scale, sampler)?;
+        Some(t / scale)
+    }
+
+    fn transmittance(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+        sampler: &mut dyn Sampler,
+    ) -> f32 {
+        match self.shape.medium() {
+            Some(medium) => {
+                let (shape_ray, scale) = self.to_shape_space(ray);
+                medium.transmittance(&shape_ray, near * scale, far * scale, sampler)
+            }
+            None => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracer::shape::*;
+
+    use std::f32::consts::PI;
+
+    fn scene_with_instance(transform: Affine3) -> (Scene, Instance) {
+        let mut scene = Scene::new();
+        let sphere = scene.insert_shape(Sphere {
+            center: Vec3::new(0.5, 0.0, 0.0),
+            radius: 1.0,
+        });
+        let instance = Instance::new(&scene, sphere, transform);
+        (scene, instance)
+    }
+
+    // stretched, rotated and moved, so that world space differs from shape space in every way
+    fn ellipsoid_transform() -> Affine3 {
+        let rotation = Quaternion::from_axis_angle(Vec3::new(1.0, 1.0, 0.0), Degrees(40.0).into());
+
+        Affine3::from_scale(Vec3::new(2.0, 0.5, 1.0))
+            .then(Affine3::from_rotation(rotation))
+            .then(Affine3::from_translation(Vec3::new(0.0, 0.0, -4.0)))
+    }
+
+    #[test]
+    fn hit_matches_transformed_sphere() {
+        let transform = Affine3::from_scale(Vec3::new(2.0, 2.0, 2.0))
+            .then(Affine3::from_translation(Vec3::new(1.0, 2.0, 3.0)));
+        let (_, instance) = scene_with_instance(transform);
+
+        // same sphere placed directly
+        let sphere = Sphere {
+            center: Vec3::new(2.0, 2.0, 3.0),
+            radius: 2.0,
+        };
+
+        let ray = Ray::new(Vec3::new(-5.0, 1.0, 2.0), Vec3::new(1.0, 0.1, 0.05));
+
+        let a = instance.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        let b = sphere.hit(&ray, 0.001, f32::INFINITY).unwrap();
+
+        assert!((a.t - b.t).abs() < 1e-4, "{} != {}", a.t, b.t);
+        assert!((a.point - b.point).norm() < 1e-4);
+        assert!((a.normal - b.normal).norm() < 1e-4);
+        assert_eq!(a.is_front_face, b.is_front_face);
+
+        // far limit is in world distances
+        assert!(instance.hit(&ray, 0.001, b.t - 0.01).is_none());
+    }
+
+    #[test]
+    fn normals_are_perpendicular_to_stretched_surface() {
+        let (_, instance) = scene_with_instance(ellipsoid_transform());
+
+        let origin = Vec3::new(0.3, 0.2, 2.0);
+        let hit = instance
+            .hit(
+                &Ray::new(origin, Vec3::new(0.0, 0.0, -1.0)),
+                0.001,
+                f32::INFINITY,
+            )
+            .unwrap();
+
+        // nearby surface points in every direction around the hit lie in the tangent plane
+        let (u, v) = hit.normal.orthonormal_basis();
+        for (du, dv) in [(1.0, 0.0), (0.0, 1.0), (-0.7, 0.7)] {
+            let offset = 1e-2 * (du * u + dv * v);
+            let nearby = instance
+                .hit(
+                    &Ray::new(hit.point + offset + hit.normal, -hit.normal),
+                    0.001,
+                    f32::INFINITY,
+                )
+                .unwrap();
+
+            let along_normal = Vec3::dot(nearby.point - hit.point, hit.normal);
+            assert!(along_normal.abs() < 1e-3, "{}", along_normal);
+        }
+    }
+
+    #[test]
+    fn bounding_box_contains_hits() {
+        let (_, instance) = scene_with_instance(ellipsoid_transform());
+        let bounds = instance.bounding_box().unwrap();
+
+        let origin = Vec3::new(0.0, 0.0, 4.0);
+        for i in 0..64 {
+            let (x, y) = ((i % 8) as f32 / 7.0 - 0.5, (i / 8) as f32 / 7.0 - 0.5);
+            let ray = Ray::new(origin, Vec3::new(x, y, -1.0));
+            if let Some(hit) = instance.hit(&ray, 0.001, f32::INFINITY) {
+                let p = hit.point;
+                for axis in 0..3 {
+                    assert!(
+                        bounds.min[axis] - 1e-4 <= p[axis] && p[axis] <= bounds.max[axis] + 1e-4
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sample_pdf_matches_pdf_and_integrates_to_one() {
+        let (_, instance) = scene_with_instance(ellipsoid_transform());
+        let origin = Vec3::new(0.5, 1.0, 1.0);
+
+        let sample_count = 1 << 16;
+        let mut sampler = SamplerKind::Sobol.create(sample_count, 7);
+
+        // pdf over all directions, estimated with uniform directions, must integrate to one
+        let mut integral = 0.0;
+
+        for index in 0..sample_count {
+            sampler.start_sample(0, 0, index);
+
+            let sample = instance.sample(origin, 0.0, &mut *sampler).unwrap();
+            let direction = (sample.hit.point - origin).normalized();
+            let pdf = instance.pdf(origin, direction, 0.0);
+            assert!(
+                (pdf - sample.pdf).abs() <= 1e-3 * pdf,
+                "{} != {}",
+                pdf,
+                sample.pdf
+            );
+
+            let direction = sample_unit_sphere(sampler.get_2d());
+            integral += 4.0 * PI * instance.pdf(origin, direction, 0.0);
+        }
+
+        let integral = integral / sample_count as f32;
+        assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+    }
+}
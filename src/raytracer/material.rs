@@ -1,10 +1,15 @@
-use rand::Rng;
-
 use crate::cgmath::*;
 use crate::raytracer::color::*;
+use crate::raytracer::description::{ColorSource, MaterialDescription};
 use crate::raytracer::ray::*;
+use crate::raytracer::sampler::*;
+use crate::raytracer::texture::*;
 
-use std::fmt::Debug;
+use std::{
+    f32::consts::{FRAC_1_PI, PI},
+    fmt::Debug,
+    sync::Arc,
+};
 
 //
 //
@@ -18,30 +23,31 @@ fn reflect(
 }
 
 /*
- * Snell's law :  `eta * theta = eta' * theta'`
+ * Snell's law :  `eta * sin(theta) = eta' * sin(theta')`
  *   where:
  *     `eta`, `eta'`: refractive index for medium
- *     `theta`, `theta'`: angle between incoming array and surface normal
+ *     `theta`, `theta'`: angle between incoming ray and surface normal
  *
  * Example values of `eta`:
  *    air = 1.0
  *    glass = 1.3 to 1.7
  *    diamond = 2.4
  *
- *  `index` is `eta / eta'`
+ *  `refraction_ratio` is `eta / eta'`, `u` must be normalized and facing against `normal`.
+ *  Caller checks for total internal reflection, past the critical angle the result is only
+ *  the tangential part.
  */
 fn refract(
     u: Vec3,
     normal: Vec3,
     refraction_ratio: f32,
 ) -> Vec3 {
-    let cos_theta = Vec3::angle(-u, normal).0.min(1.0);
+    let cos_theta = Vec3::dot(-u, normal).min(1.0);
     let r_out_perp = refraction_ratio * (u + cos_theta * normal);
     let r_out_parallel = {
         let r = 1.0 - r_out_perp.norm_squared();
-        let r = -r.abs().sqrt();
-        let r = r * normal;
-        r
+        let r = -r.max(0.0).sqrt();
+        r * normal
     };
     r_out_perp + r_out_parallel
 }
@@ -55,8 +61,7 @@ fn reflectance(
 ) -> f32 {
     let r = (1.0 - refractive_index) / (1.0 + refractive_index);
     let r = r * r;
-    let r = r + (1.0 - r) * (1.0 - cosine).powi(5);
-    r
+    r + (1.0 - r) * (1.0 - cosine).powi(5)
 }
 
 //
@@ -66,6 +71,8 @@ fn reflectance(
 pub struct Scatter {
     pub attenuation: Color,
     pub ray: Ray,
+    // specular scatter has no density to sample lights against
+    pub is_specular: bool,
 }
 
 pub trait Material: Send + Sync + Debug {
@@ -73,7 +80,55 @@ pub trait Material: Send + Sync + Debug {
         &self,
         ray_in: &Ray,
         hit: &ShapeHit,
+        sampler: &mut dyn Sampler,
     ) -> Option<Scatter>;
+
+    // radiance emitted from `hit` towards ray origin
+    fn emitted(
+        &self,
+        _ray_in: &Ray,
+        _hit: &ShapeHit,
+    ) -> Color {
+        Color::from_rgb(0.0, 0.0, 0.0)
+    }
+
+    // objects made of emissive materials are sampled as lights
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // bsdf times cosine for scattering into `direction`, only for non specular materials
+    fn eval(
+        &self,
+        _ray_in: &Ray,
+        _hit: &ShapeHit,
+        _direction: Vec3,
+    ) -> Color {
+        Color::from_rgb(0.0, 0.0, 0.0)
+    }
+
+    // density with which `scatter` picks `direction`, only for non specular materials
+    fn pdf(
+        &self,
+        _ray_in: &Ray,
+        _hit: &ShapeHit,
+        _direction: Vec3,
+    ) -> f32 {
+        0.0
+    }
+
+    // how to write the material to a scene file, `None` if it can't be
+    fn description(&self) -> Option<MaterialDescription> {
+        None
+    }
+
+    // surface colour at `hit` for the albedo aov
+    fn albedo(
+        &self,
+        _hit: &ShapeHit,
+    ) -> Color {
+        Color::from_rgb(1.0, 1.0, 1.0)
+    }
 }
 
 //
@@ -81,11 +136,15 @@ pub trait Material: Send + Sync + Debug {
 //
 #[derive(Debug)]
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Lambertian {
+        Lambertian::textured(Arc::new(ConstantTexture::new(albedo)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Lambertian {
         Lambertian { albedo }
     }
 }
@@ -93,11 +152,12 @@ impl Lambertian {
 impl Material for Lambertian {
     fn scatter(
         &self,
-        _ray_in: &Ray,
+        ray_in: &Ray,
         hit: &ShapeHit,
+        sampler: &mut dyn Sampler,
     ) -> Option<Scatter> {
         let scatter_direction = {
-            let candidate = hit.normal + Vec3::random_unit_vector();
+            let candidate = hit.normal + sample_unit_sphere(sampler.get_2d());
             if candidate.near_zero() {
                 hit.normal
             } else {
@@ -105,11 +165,48 @@ impl Material for Lambertian {
             }
         };
 
-        let ray = Ray::new(hit.point, scatter_direction);
+        let ray = Ray::with_time(hit.point, scatter_direction, ray_in.time());
+
+        let attenuation = self.albedo.value(hit.u, hit.v, hit.point);
+
+        Some(Scatter {
+            attenuation,
+            ray,
+            is_specular: false,
+        })
+    }
+
+    fn eval(
+        &self,
+        ray_in: &Ray,
+        hit: &ShapeHit,
+        direction: Vec3,
+    ) -> Color {
+        self.pdf(ray_in, hit, direction) * self.albedo.value(hit.u, hit.v, hit.point)
+    }
+
+    // `normal + random_unit_vector` is cosine distributed
+    fn pdf(
+        &self,
+        _ray_in: &Ray,
+        hit: &ShapeHit,
+        direction: Vec3,
+    ) -> f32 {
+        let cosine = Vec3::dot(hit.normal, direction.normalized());
+        cosine.max(0.0) * FRAC_1_PI
+    }
 
-        let attenuation = self.albedo;
+    fn description(&self) -> Option<MaterialDescription> {
+        Some(MaterialDescription::Lambertian {
+            albedo: ColorSource::from_texture(&*self.albedo)?,
+        })
+    }
 
-        Some(Scatter { attenuation, ray })
+    fn albedo(
+        &self,
+        hit: &ShapeHit,
+    ) -> Color {
+        self.albedo.value(hit.u, hit.v, hit.point)
     }
 }
 
@@ -118,7 +215,7 @@ impl Material for Lambertian {
 //
 #[derive(Debug)]
 pub struct Metal {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
     fuzz: f32, // in (0.0, 1.0)
 }
 
@@ -126,6 +223,13 @@ impl Metal {
     pub fn new(
         albedo: Color,
         fuzz: f32,
+    ) -> Metal {
+        Metal::textured(Arc::new(ConstantTexture::new(albedo)), fuzz)
+    }
+
+    pub fn textured(
+        albedo: Arc<dyn Texture>,
+        fuzz: f32,
     ) -> Metal {
         let fuzz = fuzz.clamp(0.0, 1.0);
         Metal { albedo, fuzz }
@@ -137,16 +241,35 @@ impl Material for Metal {
         &self,
         ray_in: &Ray,
         hit: &ShapeHit,
+        sampler: &mut dyn Sampler,
     ) -> Option<Scatter> {
         let reflected = reflect(*ray_in.direction(), hit.normal);
-        let random = self.fuzz * Vec3::random_in_unit_sphere();
+        let random = self.fuzz * sample_unit_ball(sampler.get_2d(), sampler.get_1d());
         let scatter_direction = reflected + random;
 
-        let ray = Ray::new(hit.point, scatter_direction);
+        let ray = Ray::with_time(hit.point, scatter_direction, ray_in.time());
+
+        let attenuation = self.albedo.value(hit.u, hit.v, hit.point);
 
-        let attenuation = self.albedo;
+        Some(Scatter {
+            attenuation,
+            ray,
+            is_specular: true,
+        })
+    }
+
+    fn description(&self) -> Option<MaterialDescription> {
+        Some(MaterialDescription::Metal {
+            albedo: ColorSource::from_texture(&*self.albedo)?,
+            fuzz: self.fuzz,
+        })
+    }
 
-        Some(Scatter { attenuation, ray })
+    fn albedo(
+        &self,
+        hit: &ShapeHit,
+    ) -> Color {
+        self.albedo.value(hit.u, hit.v, hit.point)
     }
 }
 
@@ -169,6 +292,7 @@ impl Material for Dielectric {
         &self,
         ray_in: &Ray,
         hit: &ShapeHit,
+        sampler: &mut dyn Sampler,
     ) -> Option<Scatter> {
         let attenuation = Color::from_rgb(1.0, 1.0, 1.0);
 
@@ -182,17 +306,812 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let reflectance_too_high =
-            reflectance(cos_theta, self.refraction_index) > rand::thread_rng().gen_range(0.0..1.0);
 
-        let direction = if cannot_refract || reflectance_too_high {
+        let direction = if cannot_refract {
             reflect(*ray_in.direction(), hit.normal)
         } else {
-            refract(*ray_in.direction(), hit.normal, refraction_ratio)
+            // schlick takes the angle on the outside, so light going in and out along the same
+            // path is reflected equally
+            let cos_outside = if hit.is_front_face {
+                cos_theta
+            } else {
+                let sin_outside = refraction_ratio * sin_theta;
+                (1.0 - sin_outside * sin_outside).sqrt()
+            };
+
+            if reflectance(cos_outside, self.refraction_index) > sampler.get_1d() {
+                reflect(*ray_in.direction(), hit.normal)
+            } else {
+                refract(*ray_in.direction(), hit.normal, refraction_ratio)
+            }
+        };
+
+        let ray = Ray::with_time(hit.point, direction, ray_in.time());
+
+        Some(Scatter {
+            attenuation,
+            ray,
+            is_specular: true,
+        })
+    }
+
+    fn description(&self) -> Option<MaterialDescription> {
+        Some(MaterialDescription::Dielectric {
+            refraction_index: self.refraction_index,
+        })
+    }
+}
+
+//
+//
+//
+#[derive(Debug)]
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> DiffuseLight {
+        DiffuseLight::textured(Arc::new(ConstantTexture::new(emit)))
+    }
+
+    pub fn textured(emit: Arc<dyn Texture>) -> DiffuseLight {
+        DiffuseLight { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _hit: &ShapeHit,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<Scatter> {
+        None
+    }
+
+    // one sided, back of the light is black
+    fn emitted(
+        &self,
+        _ray_in: &Ray,
+        hit: &ShapeHit,
+    ) -> Color {
+        if hit.is_front_face {
+            self.emit.value(hit.u, hit.v, hit.point)
+        } else {
+            Color::from_rgb(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> Option<MaterialDescription> {
+        Some(MaterialDescription::DiffuseLight {
+            emit: ColorSource::from_texture(&*self.emit)?,
+        })
+    }
+}
+
+/*
+ * Phase function of a medium that scatters equally in every direction, `albedo` is the fraction
+ * of light scattered rather than absorbed
+ */
+#[derive(Debug)]
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Isotropic {
+        Isotropic::textured(Arc::new(ConstantTexture::new(albedo)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit: &ShapeHit,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Scatter> {
+        let direction = sample_unit_sphere(sampler.get_2d());
+
+        Some(Scatter {
+            attenuation: self.albedo.value(hit.u, hit.v, hit.point),
+            ray: Ray::with_time(hit.point, direction, ray_in.time()),
+            is_specular: false,
+        })
+    }
+
+    // no cosine, scattering in a medium isn't off a surface
+    fn eval(
+        &self,
+        ray_in: &Ray,
+        hit: &ShapeHit,
+        direction: Vec3,
+    ) -> Color {
+        self.pdf(ray_in, hit, direction) * self.albedo.value(hit.u, hit.v, hit.point)
+    }
+
+    fn pdf(
+        &self,
+        _ray_in: &Ray,
+        _hit: &ShapeHit,
+        _direction: Vec3,
+    ) -> f32 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn description(&self) -> Option<MaterialDescription> {
+        Some(MaterialDescription::Isotropic {
+            albedo: ColorSource::from_texture(&*self.albedo)?,
+        })
+    }
+}
+
+/*
+ * Phase function of a medium that scatters mostly forwards (`g` > 0) or backwards (`g` < 0),
+ * like clouds and haze. `g` is the mean cosine between incoming and scattered directions, zero
+ * is isotropic.
+ */
+#[derive(Debug)]
+pub struct HenyeyGreenstein {
+    albedo: Arc<dyn Texture>,
+    g: f32,
+}
+
+impl HenyeyGreenstein {
+    /*
+     * Panics unless `g` is in (-1, 1)
+     */
+    pub fn new(
+        albedo: Color,
+        g: f32,
+    ) -> HenyeyGreenstein {
+        HenyeyGreenstein::textured(Arc::new(ConstantTexture::new(albedo)), g)
+    }
+
+    pub fn textured(
+        albedo: Arc<dyn Texture>,
+        g: f32,
+    ) -> HenyeyGreenstein {
+        assert!(g > -1.0 && g < 1.0, "asymmetry must be between -1 and 1");
+        HenyeyGreenstein { albedo, g }
+    }
+
+    // density of scattering at `cos` from the incoming direction
+    fn phase(
+        &self,
+        cos: f32,
+    ) -> f32 {
+        let g = self.g;
+        let denominator = 1.0 + g * g - 2.0 * g * cos;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit: &ShapeHit,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Scatter> {
+        let (u1, u2) = sampler.get_2d();
+        let g = self.g;
+
+        // inverted cdf of the cosine
+        let cos = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u1
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
         };
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        let forward = *ray_in.direction();
+        let (tangent, bitangent) = forward.orthonormal_basis();
+        let direction = (sin * phi.cos()) * tangent + (sin * phi.sin()) * bitangent + cos * forward;
+
+        Some(Scatter {
+            attenuation: self.albedo.value(hit.u, hit.v, hit.point),
+            ray: Ray::with_time(hit.point, direction, ray_in.time()),
+            is_specular: false,
+        })
+    }
+
+    fn eval(
+        &self,
+        ray_in: &Ray,
+        hit: &ShapeHit,
+        direction: Vec3,
+    ) -> Color {
+        self.pdf(ray_in, hit, direction) * self.albedo.value(hit.u, hit.v, hit.point)
+    }
+
+    fn pdf(
+        &self,
+        ray_in: &Ray,
+        _hit: &ShapeHit,
+        direction: Vec3,
+    ) -> f32 {
+        self.phase(Vec3::dot(*ray_in.direction(), direction.normalized()))
+    }
+
+    fn description(&self) -> Option<MaterialDescription> {
+        Some(MaterialDescription::HenyeyGreenstein {
+            albedo: ColorSource::from_texture(&*self.albedo)?,
+            g: self.g,
+        })
+    }
+}
+
+//
+//
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracer::raytrace::*;
+    use crate::raytracer::scene::*;
+    use crate::raytracer::shape::Sphere;
+
+    use std::f32::consts::PI;
+
+    const SAMPLE_COUNT: usize = 100_000;
+
+    // chi-square bins in cos(theta) and phi around the axis of a distribution
+    const THETA_BINS: usize = 10;
+    const PHI_BINS: usize = 20;
+
+    // midpoint rule cells per bin side when integrating expected counts
+    const BIN_SUBDIVISIONS: usize = 16;
+
+    // normal quantile for a one sided significance level of 0.001
+    const SIGNIFICANCE_Z: f64 = 3.09;
+
+    //
+    // helpers
+    //
+    fn hit_at_origin(is_front_face: bool) -> ShapeHit {
+        ShapeHit {
+            point: Vec3::ZERO,
+            normal: Vec3::Z,
+            t: 1.0,
+            is_front_face,
+            u: 0.0,
+            v: 0.0,
+        }
+    }
+
+    // ray arriving at the origin at `theta` from the normal (z), in the xz plane
+    fn incoming(theta: f32) -> Ray {
+        let direction = Vec3::new(theta.sin(), 0.0, -theta.cos());
+        Ray::new(-direction, direction)
+    }
+
+    fn scatter_direction(
+        material: &dyn Material,
+        ray_in: &Ray,
+        hit: &ShapeHit,
+        sampler: &mut dyn Sampler,
+    ) -> Vec3 {
+        *material
+            .scatter(ray_in, hit, sampler)
+            .expect("material absorbed the ray")
+            .ray
+            .direction()
+    }
+
+    fn assert_close(
+        a: f32,
+        b: f32,
+        tolerance: f32,
+    ) {
+        assert!((a - b).abs() <= tolerance, "{} differs from {}", a, b);
+    }
+
+    fn assert_vec_close(
+        a: Vec3,
+        b: Vec3,
+        tolerance: f32,
+    ) {
+        assert!((a - b).norm() <= tolerance, "{:?} differs from {:?}", a, b);
+    }
+
+    /*
+     * Pearson's chi-square test of directions from `sample` against solid angle density `pdf`.
+     *
+     * Bins split `[min_cos, 1]` of cos(theta) around `axis` and the whole range of phi, every
+     * sample must fall inside. Expected counts are integrated numerically, bins expecting fewer
+     * than 5 samples are pooled and samples where the density is zero fail right away.
+     */
+    fn chi_square_test(
+        axis: Vec3,
+        min_cos: f32,
+        mut sample: impl FnMut(&mut dyn Sampler) -> Vec3,
+        pdf: impl Fn(Vec3) -> f32,
+    ) {
+        let (tangent, bitangent) = axis.orthonormal_basis();
+
+        let z_step = (1.0 - min_cos) / THETA_BINS as f32;
+        let phi_step = 2.0 * PI / PHI_BINS as f32;
+
+        let mut sampler = IndependentSampler::new(7);
+        let mut observed = vec![0.0f64; THETA_BINS * PHI_BINS];
+
+        for _ in 0..SAMPLE_COUNT {
+            let direction = sample(&mut sampler);
+
+            let z = Vec3::dot(direction, axis);
+            assert!(
+                z >= min_cos - 1e-5,
+                "{:?} outside of sampled range",
+                direction
+            );
+
+            let phi = Vec3::dot(direction, bitangent).atan2(Vec3::dot(direction, tangent)) + PI;
+
+            let i = (((z - min_cos) / z_step) as usize).min(THETA_BINS - 1);
+            let j = ((phi / phi_step) as usize).min(PHI_BINS - 1);
+
+            observed[i * PHI_BINS + j] += 1.0;
+        }
+
+        // dω = d(cos theta) dphi
+        let mut expected = vec![0.0f64; THETA_BINS * PHI_BINS];
+        for i in 0..THETA_BINS {
+            for j in 0..PHI_BINS {
+                let cell_z = z_step / BIN_SUBDIVISIONS as f32;
+                let cell_phi = phi_step / BIN_SUBDIVISIONS as f32;
+
+                let mut integral = 0.0f64;
+                for k in 0..BIN_SUBDIVISIONS {
+                    for l in 0..BIN_SUBDIVISIONS {
+                        let z = min_cos + i as f32 * z_step + (k as f32 + 0.5) * cell_z;
+                        let phi = j as f32 * phi_step + (l as f32 + 0.5) * cell_phi - PI;
+
+                        let r = (1.0 - z * z).max(0.0).sqrt();
+                        let direction =
+                            (r * phi.cos()) * tangent + (r * phi.sin()) * bitangent + z * axis;
+
+                        integral += (pdf(direction) * cell_z * cell_phi) as f64;
+                    }
+                }
+
+                expected[i * PHI_BINS + j] = integral * SAMPLE_COUNT as f64;
+            }
+        }
+
+        let mut bins: Vec<(f64, f64)> = expected.into_iter().zip(observed).collect();
+        bins.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let mut statistic = 0.0;
+        let mut degrees_of_freedom = 0;
+        let mut pooled = (0.0, 0.0);
+
+        for (expected, observed) in bins {
+            if expected == 0.0 {
+                assert!(
+                    observed == 0.0,
+                    "{} samples where density is zero",
+                    observed
+                );
+            } else if expected < 5.0 {
+                pooled.0 += expected;
+                pooled.1 += observed;
+            } else {
+                statistic += (observed - expected).powi(2) / expected;
+                degrees_of_freedom += 1;
+            }
+        }
+
+        if pooled.0 > 0.0 {
+            statistic += (pooled.1 - pooled.0).powi(2) / pooled.0;
+            degrees_of_freedom += 1;
+        }
+
+        // total count is fixed
+        let k = (degrees_of_freedom - 1) as f64;
+
+        // Wilson–Hilferty approximation of the chi-square quantile
+        let h = 2.0 / (9.0 * k);
+        let threshold = k * (1.0 - h + SIGNIFICANCE_Z * h.sqrt()).powi(3);
+
+        assert!(
+            statistic <= threshold,
+            "chi-square statistic {} exceeds {} with {} degrees of freedom",
+            statistic,
+            threshold,
+            k
+        );
+    }
+
+    /*
+     * Fraction of scattered rays reflected back to the side of the normal
+     */
+    fn reflected_fraction(
+        material: &dyn Material,
+        ray_in: &Ray,
+        hit: &ShapeHit,
+    ) -> f64 {
+        let mut sampler = IndependentSampler::new(11);
+
+        let reflected = (0..SAMPLE_COUNT)
+            .filter(|_| {
+                Vec3::dot(
+                    scatter_direction(material, ray_in, hit, &mut sampler),
+                    hit.normal,
+                ) > 0.0
+            })
+            .count();
+
+        reflected as f64 / SAMPLE_COUNT as f64
+    }
+
+    // binomial proportion within 5 standard deviations
+    fn assert_fraction(
+        fraction: f64,
+        probability: f64,
+    ) {
+        let sigma = (probability * (1.0 - probability) / SAMPLE_COUNT as f64).sqrt();
+        assert!(
+            (fraction - probability).abs() <= 5.0 * sigma + 1e-9,
+            "fraction {} is not consistent with probability {}",
+            fraction,
+            probability
+        );
+    }
+
+    /*
+     * Sphere of `material` in a uniformly white environment, average radiance reaching the
+     * camera
+     */
+    fn white_furnace<M: Material + 'static>(material: M) -> Vec<f32> {
+        let mut scene = Scene::new();
+        scene.set_background(Background::Solid(Color::from_rgb(1.0, 1.0, 1.0)));
+
+        let sphere = scene.insert_shape(Sphere {
+            center: Vec3::ZERO,
+            radius: 1.0,
+        });
+        let material = scene.insert_material(material);
+        scene.insert_object(sphere, material);
+
+        let options = RayCastOptions { max_depth: 64 };
+        let mut sampler = IndependentSampler::new(13);
+
+        (0..10_000)
+            .map(|i| {
+                sampler.start_sample(0, 0, i);
+
+                // aimed at random points of the sphere's silhouette disk
+                let target = sample_unit_disk(sampler.get_2d());
+                let ray = Ray::new(Vec3::new(target.x, target.y, 5.0), -Vec3::Z);
+
+                ray_color(&options, &scene, &ray, &mut sampler).r()
+            })
+            .collect()
+    }
+
+    //
+    // refraction
+    //
+    #[test]
+    fn refract_follows_snells_law() {
+        for &ratio in &[1.0 / 1.5, 1.0 / 1.33, 1.2, 1.5] {
+            for step in 0..18 {
+                let theta_in = step as f32 * (PI / 36.0);
+                let sin_out = ratio * theta_in.sin();
+                if sin_out >= 1.0 {
+                    continue;
+                }
+
+                let u = *incoming(theta_in).direction();
+                let t = refract(u, Vec3::Z, ratio);
+
+                assert_close(t.norm(), 1.0, 1e-5);
+                // through the surface, in the plane of incidence, same tangential direction
+                assert!(t.z < 0.0);
+                assert_close(t.y, 0.0, 1e-6);
+                assert_close(t.x, sin_out, 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn refract_at_normal_incidence_goes_straight() {
+        let u = -Vec3::Z;
+        assert_vec_close(refract(u, Vec3::Z, 1.0 / 1.5), u, 1e-6);
+        assert_vec_close(refract(u, Vec3::Z, 1.5), u, 1e-6);
+    }
+
+    #[test]
+    fn refract_is_reversible() {
+        for step in 1..16 {
+            let u = *incoming(step as f32 * (PI / 36.0)).direction();
+
+            let inside = refract(u, Vec3::Z, 1.0 / 1.5);
+            let back = refract(-inside, -Vec3::Z, 1.5);
+
+            assert_vec_close(back, -u, 1e-5);
+        }
+    }
+
+    #[test]
+    fn reflectance_limits() {
+        // ((n - 1) / (n + 1))^2 at normal incidence, everything is reflected at grazing angles
+        assert_close(reflectance(1.0, 1.5), 0.04, 1e-6);
+        assert_close(reflectance(0.0, 1.5), 1.0, 1e-6);
+        assert_close(reflectance(1.0, 1.0), 0.0, 1e-6);
+
+        let mut previous = reflectance(0.0, 1.5);
+        for step in 1..=100 {
+            let current = reflectance(step as f32 / 100.0, 1.5);
+            assert!(current <= previous);
+            previous = current;
+        }
+    }
+
+    #[test]
+    fn dielectric_reflects_with_schlick_probability() {
+        let glass = Dielectric::new(1.5);
+
+        for &theta in &[0.0, PI / 6.0, PI / 3.0, 0.45 * PI] {
+            let fraction = reflected_fraction(&glass, &incoming(theta), &hit_at_origin(true));
+            assert_fraction(fraction, reflectance(theta.cos(), 1.5) as f64);
+        }
+    }
+
+    #[test]
+    fn dielectric_total_internal_reflection() {
+        let glass = Dielectric::new(1.5);
+        let hit = hit_at_origin(false);
+
+        let critical = (1.0f32 / 1.5).asin();
+
+        // past the critical angle everything is mirrored
+        for &theta in &[critical + 0.01, PI / 3.0, 0.49 * PI] {
+            let ray_in = incoming(theta);
+            let mirrored = reflect(*ray_in.direction(), hit.normal);
+
+            let mut sampler = IndependentSampler::new(5);
+            for _ in 0..1000 {
+                let direction = scatter_direction(&glass, &ray_in, &hit, &mut sampler);
+                assert_vec_close(direction, mirrored, 1e-5);
+            }
+        }
+
+        // just below it most light still gets out
+        let fraction = reflected_fraction(&glass, &incoming(critical - 0.05), &hit);
+        assert!(fraction < 0.9, "reflected fraction {}", fraction);
+
+        // and light from outside is never trapped
+        let fraction = reflected_fraction(&glass, &incoming(0.49 * PI), &hit_at_origin(true));
+        assert!(fraction < 1.0);
+    }
+
+    #[test]
+    fn dielectric_reflectance_is_reciprocal() {
+        let glass = Dielectric::new(1.5);
+
+        for &theta_outside in &[PI / 8.0, PI / 4.0, PI / 3.0, 0.45 * PI] {
+            let theta_inside = (theta_outside.sin() / 1.5).asin();
+            let probability = reflectance(theta_outside.cos(), 1.5) as f64;
+
+            let entering =
+                reflected_fraction(&glass, &incoming(theta_outside), &hit_at_origin(true));
+            let leaving =
+                reflected_fraction(&glass, &incoming(theta_inside), &hit_at_origin(false));
+
+            assert_fraction(entering, probability);
+            assert_fraction(leaving, probability);
+        }
+    }
+
+    //
+    // lambertian
+    //
+    #[test]
+    fn lambertian_scatter_is_cosine_distributed() {
+        let material = Lambertian::new(Color::from_rgb(0.5, 0.5, 0.5));
+        let hit = hit_at_origin(true);
+        let ray_in = incoming(PI / 5.0);
+
+        chi_square_test(
+            Vec3::Z,
+            -1.0,
+            |sampler| scatter_direction(&material, &ray_in, &hit, sampler),
+            |direction| direction.z.max(0.0) * FRAC_1_PI,
+        );
+    }
+
+    #[test]
+    fn lambertian_pdf_matches_scatter() {
+        let material = Lambertian::new(Color::from_rgb(0.5, 0.5, 0.5));
+        let hit = hit_at_origin(true);
+        let ray_in = incoming(PI / 5.0);
+
+        chi_square_test(
+            Vec3::Z,
+            -1.0,
+            |sampler| scatter_direction(&material, &ray_in, &hit, sampler),
+            |direction| material.pdf(&ray_in, &hit, direction),
+        );
+    }
+
+    #[test]
+    fn lambertian_is_reciprocal() {
+        let material = Lambertian::new(Color::from_rgb(0.8, 0.4, 0.2));
+        let hit = hit_at_origin(true);
+
+        // bsdf without the cosine of the outgoing direction
+        let bsdf = |from: Vec3, to: Vec3| {
+            let ray_in = Ray::new(from, -from);
+            material.eval(&ray_in, &hit, to) / Vec3::dot(to, hit.normal)
+        };
+
+        let mut sampler = IndependentSampler::new(3);
+        for _ in 0..1000 {
+            let mut upper = || {
+                let d = sample_unit_sphere(sampler.get_2d());
+                Vec3::new(d.x, d.y, d.z.abs().max(0.01)).normalized()
+            };
+            let (a, b) = (upper(), upper());
+
+            let forward = bsdf(a, b);
+            let backward = bsdf(b, a);
+
+            assert_close(forward.r(), backward.r(), 1e-5);
+            assert_close(forward.g(), backward.g(), 1e-5);
+            assert_close(forward.b(), backward.b(), 1e-5);
+        }
+    }
+
+    #[test]
+    fn lambertian_conserves_energy() {
+        let material = Lambertian::new(Color::from_rgb(1.0, 1.0, 1.0));
+        let hit = hit_at_origin(true);
+        let ray_in = incoming(PI / 3.0);
+
+        // integral of bsdf times cosine over the sphere, uniform directions
+        let mut sampler = IndependentSampler::new(17);
+        let mut sum = 0.0f64;
+        for _ in 0..SAMPLE_COUNT {
+            let direction = sample_unit_sphere(sampler.get_2d());
+            sum += (material.eval(&ray_in, &hit, direction).r() * 4.0 * PI) as f64;
+        }
+
+        assert_close((sum / SAMPLE_COUNT as f64) as f32, 1.0, 0.01);
+    }
+
+    //
+    // metal
+    //
+    #[test]
+    fn metal_without_fuzz_is_a_mirror() {
+        let material = Metal::new(Color::from_rgb(0.9, 0.9, 0.9), 0.0);
+        let hit = hit_at_origin(true);
+        let mut sampler = IndependentSampler::new(1);
+
+        for step in 0..18 {
+            let ray_in = incoming(step as f32 * (PI / 36.0));
+            let direction = scatter_direction(&material, &ray_in, &hit, &mut sampler);
+
+            let d = *ray_in.direction();
+            assert_vec_close(direction, Vec3::new(d.x, d.y, -d.z), 1e-6);
+        }
+    }
+
+    /*
+     * Reflection plus a uniform point in a ball of radius `fuzz`. Along a direction at angle
+     * `alpha` from the reflection the ball covers `t` in `cos(alpha) -+ sqrt(fuzz^2 - sin^2)`, so
+     * density is `(t2^3 - t1^3) / (4 pi fuzz^3)`.
+     */
+    #[test]
+    fn metal_fuzz_is_uniform_in_a_ball() {
+        let fuzz = 0.5;
+        let material = Metal::new(Color::from_rgb(0.9, 0.9, 0.9), fuzz);
+        let hit = hit_at_origin(true);
+        let ray_in = incoming(PI / 6.0);
+
+        let reflected = reflect(*ray_in.direction(), hit.normal);
+
+        chi_square_test(
+            reflected,
+            (1.0 - fuzz * fuzz).sqrt(),
+            |sampler| scatter_direction(&material, &ray_in, &hit, sampler),
+            |direction| {
+                let cos_alpha = Vec3::dot(direction, reflected);
+                let sin2_alpha = 1.0 - cos_alpha * cos_alpha;
+                if cos_alpha <= 0.0 || sin2_alpha >= fuzz * fuzz {
+                    return 0.0;
+                }
+
+                let half_chord = (fuzz * fuzz - sin2_alpha).sqrt();
+                let t1 = (cos_alpha - half_chord).max(0.0);
+                let t2 = cos_alpha + half_chord;
+
+                (t2.powi(3) - t1.powi(3)) / (4.0 * PI * fuzz.powi(3))
+            },
+        );
+    }
+
+    //
+    // isotropic
+    //
+    #[test]
+    fn isotropic_scatter_is_uniform() {
+        let material = Isotropic::new(Color::from_rgb(0.5, 0.5, 0.5));
+        let hit = hit_at_origin(true);
+        let ray_in = incoming(PI / 4.0);
+
+        chi_square_test(
+            Vec3::Z,
+            -1.0,
+            |sampler| scatter_direction(&material, &ray_in, &hit, sampler),
+            |direction| material.pdf(&ray_in, &hit, direction),
+        );
+    }
+
+    #[test]
+    fn henyey_greenstein_matches_its_density() {
+        let hit = hit_at_origin(true);
+        let ray_in = incoming(PI / 3.0);
+        let forward = *ray_in.direction();
+
+        for g in [0.8, 0.3, 0.0, -0.6] {
+            let material = HenyeyGreenstein::new(Color::from_rgb(0.5, 0.5, 0.5), g);
+
+            chi_square_test(
+                forward,
+                -1.0,
+                |sampler| scatter_direction(&material, &ray_in, &hit, sampler),
+                |direction| material.pdf(&ray_in, &hit, direction),
+            );
+
+            // mean cosine is the asymmetry
+            let mut sampler = IndependentSampler::new(3);
+            let mean = (0..SAMPLE_COUNT)
+                .map(|_| {
+                    let direction = scatter_direction(&material, &ray_in, &hit, &mut sampler);
+                    Vec3::dot(direction, forward) as f64
+                })
+                .sum::<f64>()
+                / SAMPLE_COUNT as f64;
+            assert_close(mean as f32, g, 0.01);
+        }
+    }
+
+    //
+    // white furnace, nothing in a uniformly lit world should gain or lose energy
+    //
+    #[test]
+    fn white_furnace_lambertian() {
+        let values = white_furnace(Lambertian::new(Color::from_rgb(1.0, 1.0, 1.0)));
+        assert!(values.iter().all(|&v| (v - 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn white_furnace_metal() {
+        let values = white_furnace(Metal::new(Color::from_rgb(1.0, 1.0, 1.0), 0.0));
+        assert!(values.iter().all(|&v| (v - 1.0).abs() < 1e-5));
+    }
 
-        let ray = Ray::new(hit.point, direction);
+    #[test]
+    fn white_furnace_dielectric() {
+        // paths bouncing inside for longer than max depth are lost
+        let values = white_furnace(Dielectric::new(1.5));
+        assert!(values.iter().all(|&v| v <= 1.0 + 1e-5));
 
-        Some(Scatter { attenuation, ray })
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        assert_close(mean, 1.0, 1e-3);
     }
 }
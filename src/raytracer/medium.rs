@@ -0,0 +1,911 @@
+use crate::cgmath::*;
+use crate::raytracer::aabb::*;
+use crate::raytracer::description::{ShapeDescription, ShapeRef};
+use crate::raytracer::ray::*;
+use crate::raytracer::sampler::*;
+use crate::raytracer::scene::*;
+use crate::raytracer::texture::*;
+
+use anyhow::{bail, Context};
+
+use rand::{Rng, SeedableRng};
+
+use rand_pcg::Pcg32;
+
+use std::{
+    convert::TryInto,
+    fmt::{self, Debug},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/*
+ * Participating medium filling the inside of a closed shape, see `HittableShape::medium`.
+ *
+ * Every collision with the medium scatters, the material of the object is its phase function
+ * and its albedo the fraction of light that isn't absorbed.
+ */
+pub trait Medium: Sync + Send + Debug {
+    // distance of the first scattering along `ray` between `near` and `far`, `None` if the ray
+    // passes through
+    fn sample_scattering(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<f32>;
+
+    // fraction of light that travels along `ray` from `near` to `far` without scattering
+    fn transmittance(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+        sampler: &mut dyn Sampler,
+    ) -> f32;
+}
+
+//
+//
+//
+
+/*
+ * Medium of the same density everywhere inside `boundary`, like fog or smoke. Density is the
+ * chance of scattering per unit length, in the space of the boundary.
+ */
+#[derive(Debug)]
+pub struct ConstantMedium {
+    boundary_id: ShapeId,
+    boundary: Arc<dyn HittableShape>,
+    density: f32,
+}
+
+impl ConstantMedium {
+    /*
+     * Panics if `density` is negative
+     */
+    pub fn new(
+        scene: &Scene,
+        boundary: ShapeId,
+        density: f32,
+    ) -> ConstantMedium {
+        assert!(density >= 0.0, "medium density must not be negative");
+
+        ConstantMedium {
+            boundary_id: boundary,
+            boundary: scene.shared_shape(boundary),
+            density,
+        }
+    }
+
+    pub fn density(&self) -> f32 {
+        self.density
+    }
+}
+
+impl HittableShape for ConstantMedium {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        self.boundary.hit(ray, near, far)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+
+    fn medium(&self) -> Option<&dyn Medium> {
+        Some(self)
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::ConstantMedium {
+            boundary: Box::new(ShapeRef::Id(self.boundary_id)),
+            density: self.density,
+        })
+    }
+}
+
+/*
+ * Free path lengths are exponentially distributed
+ */
+impl Medium for ConstantMedium {
+    fn sample_scattering(
+        &self,
+        _ray: &Ray,
+        near: f32,
+        far: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<f32> {
+        let u = sampler.get_1d();
+        if self.density == 0.0 {
+            return None;
+        }
+
+        let t = near - (1.0 - u).ln() / self.density;
+        (t < far).then_some(t)
+    }
+
+    fn transmittance(
+        &self,
+        _ray: &Ray,
+        near: f32,
+        far: f32,
+        _sampler: &mut dyn Sampler,
+    ) -> f32 {
+        (-self.density * (far - near)).exp()
+    }
+}
+
+//
+//
+//
+
+/*
+ * Dense grid of densities, from a little endian binary file: three u32 sizes along x, y and z,
+ * then one f32 per voxel with x varying fastest and z slowest
+ */
+#[derive(Clone)]
+pub struct DensityGrid {
+    size: [usize; 3],
+    values: Vec<f32>,
+    max: f32,
+    // file it was loaded from, for writing scene files
+    path: Option<PathBuf>,
+}
+
+impl DensityGrid {
+    /*
+     * Panics if a size is zero, `values` doesn't have one density per voxel or one is negative
+     */
+    pub fn new(
+        size: [usize; 3],
+        values: Vec<f32>,
+    ) -> DensityGrid {
+        assert!(size.iter().all(|&n| n > 0), "grid must not be empty");
+        assert_eq!(values.len(), size[0] * size[1] * size[2]);
+        assert!(
+            values.iter().all(|&value| value >= 0.0),
+            "grid densities must not be negative"
+        );
+
+        let max = values.iter().copied().fold(0.0, f32::max);
+
+        DensityGrid {
+            size,
+            values,
+            max,
+            path: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<DensityGrid> {
+        let bytes =
+            fs::read(path).with_context(|| format!("cannot load grid {}", path.display()))?;
+
+        let word = |index: usize| -> Option<[u8; 4]> {
+            bytes.get(4 * index..4 * index + 4)?.try_into().ok()
+        };
+
+        let mut size = [0; 3];
+        for (axis, n) in size.iter_mut().enumerate() {
+            match word(axis) {
+                Some(word) => *n = u32::from_le_bytes(word) as usize,
+                None => bail!("{} is too short for a grid header", path.display()),
+            }
+        }
+
+        // sizes come from the file, so the byte count may not even fit
+        let count = size[0] as u64 * size[1] as u64 * size[2] as u64;
+        let expected = 4 * (3 + count as u128);
+        if count == 0 {
+            bail!("{} is an empty grid", path.display());
+        }
+        if bytes.len() as u128 != expected {
+            bail!(
+                "{} has {} bytes, a {}x{}x{} grid needs {}",
+                path.display(),
+                bytes.len(),
+                size[0],
+                size[1],
+                size[2],
+                expected
+            );
+        }
+        let count = count as usize;
+
+        let values: Vec<f32> = (3..3 + count)
+            .map(|index| f32::from_le_bytes(word(index).unwrap()))
+            .collect();
+
+        // also rejects nan
+        if !values
+            .iter()
+            .all(|&value| value >= 0.0 && value.is_finite())
+        {
+            bail!("{} has negative or invalid densities", path.display());
+        }
+
+        Ok(DensityGrid {
+            path: Some(path.to_path_buf()),
+            ..DensityGrid::new(size, values)
+        })
+    }
+
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    // largest density, interpolation never goes above it
+    pub fn max(&self) -> f32 {
+        self.max
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // clamped to the edges
+    fn voxel(
+        &self,
+        index: [i64; 3],
+    ) -> f32 {
+        let [x, y, z] = [0, 1, 2].map(|axis| index[axis].clamp(0, self.size[axis] as i64 - 1));
+        let [nx, ny, _] = self.size.map(|n| n as i64);
+        self.values[(x + nx * (y + ny * z)) as usize]
+    }
+
+    /*
+     * Trilinear between voxel centers, `p` goes from 0 to 1 across the grid on every axis.
+     * Zero outside of it.
+     */
+    pub fn density(
+        &self,
+        p: Vec3,
+    ) -> f32 {
+        if (0..3).any(|axis| !(0.0..=1.0).contains(&p[axis])) {
+            return 0.0;
+        }
+
+        let position = [0, 1, 2].map(|axis| p[axis] * self.size[axis] as f32 - 0.5);
+        let base = position.map(|x| x.floor());
+        let weight = [0, 1, 2].map(|axis| position[axis] - base[axis]);
+        let base = base.map(|x| x as i64);
+
+        let mut sum = 0.0;
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, corner >> 2];
+
+            let mut corner_weight = 1.0;
+            for axis in 0..3 {
+                corner_weight *= match offset[axis] {
+                    0 => 1.0 - weight[axis],
+                    _ => weight[axis],
+                };
+            }
+
+            if corner_weight > 0.0 {
+                let index = [0, 1, 2].map(|axis| base[axis] + offset[axis]);
+                sum += corner_weight * self.voxel(index);
+            }
+        }
+
+        sum
+    }
+}
+
+impl Debug for DensityGrid {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        f.debug_struct("DensityGrid")
+            .field("size", &self.size)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+/*
+ * Medium with its density from a grid stretched over the bounding box of `boundary`, like
+ * simulated smoke. Grid values are multiplied by `density`.
+ */
+#[derive(Debug)]
+pub struct GridMedium {
+    boundary_id: ShapeId,
+    boundary: Arc<dyn HittableShape>,
+    bounds: Aabb,
+    grid: DensityGrid,
+    density: f32,
+}
+
+impl GridMedium {
+    /*
+     * Panics if `density` is negative or `boundary` has no bounding box
+     */
+    pub fn new(
+        scene: &Scene,
+        boundary: ShapeId,
+        grid: DensityGrid,
+        density: f32,
+    ) -> GridMedium {
+        assert!(density >= 0.0, "medium density must not be negative");
+
+        let boundary_shape = scene.shared_shape(boundary);
+        let bounds = boundary_shape
+            .bounding_box()
+            .expect("grid medium boundary must be bounded");
+
+        GridMedium {
+            boundary_id: boundary,
+            boundary: boundary_shape,
+            bounds,
+            grid,
+            density,
+        }
+    }
+
+    pub fn grid(&self) -> &DensityGrid {
+        &self.grid
+    }
+
+    fn density_at(
+        &self,
+        point: Vec3,
+    ) -> f32 {
+        let (min, extent) = (self.bounds.min, self.bounds.extent());
+        let p = Vec3::new(
+            (point.x - min.x) / extent.x,
+            (point.y - min.y) / extent.y,
+            (point.z - min.z) / extent.z,
+        );
+
+        self.density * self.grid.density(p)
+    }
+}
+
+impl HittableShape for GridMedium {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        self.boundary.hit(ray, near, far)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn medium(&self) -> Option<&dyn Medium> {
+        Some(self)
+    }
+
+    // grids built in code have nowhere to be written
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::GridMedium {
+            boundary: Box::new(ShapeRef::Id(self.boundary_id)),
+            path: self.grid.path.clone()?,
+            density: self.density,
+        })
+    }
+}
+
+impl Medium for GridMedium {
+    fn sample_scattering(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<f32> {
+        let majorant = self.density * self.grid.max();
+        delta_tracking(ray, near, far, majorant, |p| self.density_at(p), sampler)
+    }
+
+    fn transmittance(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+        _sampler: &mut dyn Sampler,
+    ) -> f32 {
+        let majorant = self.density * self.grid.max();
+        ratio_tracking(ray, near, far, majorant, |p| self.density_at(p))
+    }
+}
+
+/*
+ * Medium with its density from noise, like clouds. Noise values are clamped to [0, 1] and
+ * multiplied by `density`.
+ */
+#[derive(Debug)]
+pub struct NoiseMedium {
+    boundary_id: ShapeId,
+    boundary: Arc<dyn HittableShape>,
+    noise: NoiseTexture,
+    density: f32,
+}
+
+impl NoiseMedium {
+    /*
+     * Panics if `density` is negative
+     */
+    pub fn new(
+        scene: &Scene,
+        boundary: ShapeId,
+        noise: NoiseTexture,
+        density: f32,
+    ) -> NoiseMedium {
+        assert!(density >= 0.0, "medium density must not be negative");
+
+        NoiseMedium {
+            boundary_id: boundary,
+            boundary: scene.shared_shape(boundary),
+            noise,
+            density,
+        }
+    }
+
+    fn density_at(
+        &self,
+        point: Vec3,
+    ) -> f32 {
+        let value = self.noise.value(0.0, 0.0, point).r();
+        self.density * value.clamp(0.0, 1.0)
+    }
+}
+
+impl HittableShape for NoiseMedium {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        self.boundary.hit(ray, near, far)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+
+    fn medium(&self) -> Option<&dyn Medium> {
+        Some(self)
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::NoiseMedium {
+            boundary: Box::new(ShapeRef::Id(self.boundary_id)),
+            density: self.density,
+            noise: self.noise.kind(),
+            scale: self.noise.scale(),
+            seed: self.noise.seed(),
+        })
+    }
+}
+
+impl Medium for NoiseMedium {
+    fn sample_scattering(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<f32> {
+        delta_tracking(
+            ray,
+            near,
+            far,
+            self.density,
+            |p| self.density_at(p),
+            sampler,
+        )
+    }
+
+    fn transmittance(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+        _sampler: &mut dyn Sampler,
+    ) -> f32 {
+        ratio_tracking(ray, near, far, self.density, |p| self.density_at(p))
+    }
+}
+
+//
+//
+//
+
+/*
+ * Delta tracking (Woodcock tracking) of the first scattering in a medium whose density never
+ * exceeds `majorant`. Steps are free paths of a medium of majorant density everywhere, and a
+ * collision is real with probability `density / majorant`, null ones are passed through. The
+ * distance is exact whatever the density does between steps.
+ *
+ * The first step takes the sample dimension a constant medium would, the rest come from
+ * `tracking_rng`.
+ */
+fn delta_tracking(
+    ray: &Ray,
+    near: f32,
+    far: f32,
+    majorant: f32,
+    density: impl Fn(Vec3) -> f32,
+    sampler: &mut dyn Sampler,
+) -> Option<f32> {
+    let mut u = sampler.get_1d();
+    if majorant <= 0.0 {
+        return None;
+    }
+
+    let mut rng = tracking_rng(ray, u.to_bits() as u64);
+    let mut t = near;
+
+    loop {
+        t -= (1.0 - u).ln() / majorant;
+        if t >= far {
+            return None;
+        }
+
+        if rng.gen::<f32>() * majorant < density(ray.at(t)) {
+            return Some(t);
+        }
+
+        u = rng.gen();
+    }
+}
+
+/*
+ * Ratio tracking estimate of transmittance, the same steps as delta tracking but every collision
+ * is passed through, weighted by its chance of being null. Unbiased and lower variance than
+ * counting how often delta tracking gets through. Russian roulette ends rays that carry little.
+ */
+fn ratio_tracking(
+    ray: &Ray,
+    near: f32,
+    far: f32,
+    majorant: f32,
+    density: impl Fn(Vec3) -> f32,
+) -> f32 {
+    if majorant <= 0.0 {
+        return 1.0;
+    }
+
+    let mut rng = tracking_rng(ray, near.to_bits() as u64);
+    let mut t = near;
+    let mut transmittance = 1.0;
+
+    loop {
+        t -= (1.0 - rng.gen::<f32>()).ln() / majorant;
+        if t >= far {
+            return transmittance;
+        }
+
+        transmittance *= 1.0 - density(ray.at(t)) / majorant;
+
+        if transmittance < 0.1 {
+            if rng.gen::<f32>() < 0.5 {
+                return 0.0;
+            }
+            transmittance *= 2.0;
+        }
+    }
+}
+
+/*
+ * Tracking takes any number of steps while a bounce has a fixed number of sample dimensions,
+ * so steps draw from a generator seeded by the ray instead. Renders stay reproducible.
+ */
+fn tracking_rng(
+    ray: &Ray,
+    seed: u64,
+) -> Pcg32 {
+    let (o, d) = (ray.origin(), ray.direction());
+    let bits = [o.x, o.y, o.z, d.x, d.y, d.z, ray.time()].map(|x| x.to_bits() as u64);
+    Pcg32::seed_from_u64(hash(seed, &bits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracer::color::*;
+    use crate::raytracer::material::*;
+    use crate::raytracer::raytrace::*;
+    use crate::raytracer::shape::*;
+
+    fn sphere_of_fog(density: f32) -> (Scene, ConstantMedium) {
+        let mut scene = Scene::new();
+        let sphere = scene.insert_shape(Sphere {
+            center: Vec3::ZERO,
+            radius: 1.0,
+        });
+        let medium = ConstantMedium::new(&scene, sphere, density);
+        (scene, medium)
+    }
+
+    #[test]
+    fn scattering_matches_transmittance() {
+        let (_, medium) = sphere_of_fog(0.7);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::Z);
+
+        let sample_count = 1 << 16;
+        let mut sampler = SamplerKind::Sobol.create(sample_count, 5);
+
+        // fraction of rays passing through a segment is its transmittance
+        let (near, far) = (4.0, 6.0);
+        let passed = (0..sample_count)
+            .filter(|&index| {
+                sampler.start_sample(0, 0, index);
+                medium
+                    .sample_scattering(&ray, near, far, &mut *sampler)
+                    .is_none()
+            })
+            .count();
+
+        let fraction = passed as f32 / sample_count as f32;
+        let expected = medium.transmittance(&ray, near, far, &mut *sampler);
+        assert!(
+            (fraction - expected).abs() < 1e-3,
+            "{} != {}",
+            fraction,
+            expected
+        );
+        assert!((expected - (-1.4f32).exp()).abs() < 1e-6);
+    }
+
+    /*
+     * Medium that scatters everything in a uniformly white world stays white, however many
+     * times light scatters inside
+     */
+    #[test]
+    fn white_furnace_isotropic_medium() {
+        let (mut scene, medium) = sphere_of_fog(2.0);
+        scene.set_background(Background::Solid(Color::from_rgb(1.0, 1.0, 1.0)));
+
+        let fog = scene.insert_shape(medium);
+        let material = scene.insert_material(Isotropic::new(Color::from_rgb(1.0, 1.0, 1.0)));
+        scene.insert_object(fog, material);
+
+        let options = RayCastOptions { max_depth: 256 };
+        let mut sampler = IndependentSampler::new(19);
+
+        for i in 0..10_000 {
+            sampler.start_sample(0, 0, i);
+
+            let target = sample_unit_disk(sampler.get_2d());
+            let ray = Ray::new(Vec3::new(target.x, target.y, 5.0), -Vec3::Z);
+
+            let value = ray_color(&options, &scene, &ray, &mut sampler).r();
+            assert!((value - 1.0).abs() < 1e-4, "{}", value);
+        }
+    }
+
+    #[test]
+    fn grid_interpolates_between_voxel_centers() {
+        let grid = DensityGrid::new([2, 1, 1], vec![0.0, 1.0]);
+        assert_eq!(grid.max(), 1.0);
+
+        let at = |x: f32| grid.density(Vec3::new(x, 0.5, 0.5));
+        assert!(at(0.25).abs() < 1e-6);
+        assert!((at(0.75) - 1.0).abs() < 1e-6);
+        assert!((at(0.5) - 0.5).abs() < 1e-6);
+
+        // clamped to the edge voxels inside, nothing outside
+        assert!((at(1.0) - 1.0).abs() < 1e-6);
+        assert!(at(0.0).abs() < 1e-6);
+        assert_eq!(at(1.5), 0.0);
+    }
+
+    #[test]
+    fn grid_loads_from_file() {
+        let path = std::env::temp_dir().join(format!("grid_test_{}.grid", std::process::id()));
+
+        let mut bytes = Vec::new();
+        for n in [2u32, 1, 3] {
+            bytes.extend_from_slice(&n.to_le_bytes());
+        }
+        for value in [0.0f32, 1.0, 2.0, 3.0, 4.0, 5.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        fs::write(&path, &bytes).unwrap();
+        let grid = DensityGrid::load(&path).unwrap();
+        assert_eq!(grid.size(), [2, 1, 3]);
+        assert_eq!(grid.max(), 5.0);
+        assert_eq!(grid.path(), Some(path.as_path()));
+
+        // voxel centers along z are 1/6, 1/2 and 5/6
+        assert!((grid.density(Vec3::new(0.75, 0.5, 0.5)) - 3.0).abs() < 1e-5);
+
+        fs::write(&path, &bytes[..bytes.len() - 4]).unwrap();
+        let error = DensityGrid::load(&path).unwrap_err().to_string();
+        assert!(error.contains("needs 36"), "{}", error);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    /*
+     * Fraction of rays delta tracking lets through and the mean of ratio tracking estimate the
+     * same transmittance, over rays crossing `shape` at many places
+     */
+    fn tracked_transmittance(shape: &dyn HittableShape) -> (f32, f32) {
+        let medium = shape.medium().unwrap();
+
+        let sample_count = 1 << 14;
+        let mut sampler = SamplerKind::Sobol.create(sample_count, 13);
+
+        let (mut passed, mut ratio) = (0, 0.0);
+        for index in 0..sample_count {
+            sampler.start_sample(0, 0, index);
+
+            let offset = 0.8 * sample_unit_disk(sampler.get_2d());
+            let ray = Ray::new(Vec3::new(offset.x, offset.y, -5.0), Vec3::Z);
+            let near = shape.hit(&ray, 0.0, f32::INFINITY).unwrap().t;
+            let far = shape.hit(&ray, near, f32::INFINITY).unwrap().t;
+
+            if medium
+                .sample_scattering(&ray, near, far, &mut *sampler)
+                .is_none()
+            {
+                passed += 1;
+            }
+            ratio += medium.transmittance(&ray, near, far, &mut *sampler);
+        }
+
+        (
+            passed as f32 / sample_count as f32,
+            ratio / sample_count as f32,
+        )
+    }
+
+    #[test]
+    fn uniform_grid_matches_constant_medium() {
+        let mut scene = Scene::new();
+        let cube = scene.insert_shape(Cuboid::new(
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+        ));
+        let grid = DensityGrid::new([2, 3, 4], vec![0.5; 24]);
+        let medium = GridMedium::new(&scene, cube, grid, 0.8);
+
+        // rays cross the cube along z
+        let (delta, ratio) = tracked_transmittance(&medium);
+        let expected = (-0.4f32 * 2.0).exp();
+        assert!((delta - expected).abs() < 0.01, "{} != {}", delta, expected);
+        assert!((ratio - expected).abs() < 0.01, "{} != {}", ratio, expected);
+    }
+
+    #[test]
+    fn delta_tracking_matches_ratio_tracking() {
+        let mut scene = Scene::new();
+        let sphere = scene.insert_shape(Sphere {
+            center: Vec3::ZERO,
+            radius: 1.0,
+        });
+        let noise = NoiseTexture::new(NoiseKind::Turbulence, 3.0, 2);
+        let medium = NoiseMedium::new(&scene, sphere, noise, 3.0);
+
+        let (delta, ratio) = tracked_transmittance(&medium);
+        assert!(0.05 < ratio && ratio < 0.95, "{}", ratio);
+        assert!((delta - ratio).abs() < 0.015, "{} != {}", delta, ratio);
+    }
+
+    #[test]
+    fn white_furnace_heterogeneous_medium() {
+        let mut scene = Scene::new();
+        scene.set_background(Background::Solid(Color::from_rgb(1.0, 1.0, 1.0)));
+
+        let sphere = scene.insert_shape(Sphere {
+            center: Vec3::ZERO,
+            radius: 1.0,
+        });
+        let noise = NoiseTexture::new(NoiseKind::Perlin, 2.0, 5);
+        let cloud = scene.insert_shape(NoiseMedium::new(&scene, sphere, noise, 4.0));
+        let white =
+            scene.insert_material(HenyeyGreenstein::new(Color::from_rgb(1.0, 1.0, 1.0), 0.6));
+        scene.insert_object(cloud, white);
+
+        let options = RayCastOptions { max_depth: 256 };
+        let mut sampler = IndependentSampler::new(29);
+
+        for i in 0..10_000 {
+            sampler.start_sample(0, 0, i);
+
+            let target = sample_unit_disk(sampler.get_2d());
+            let ray = Ray::new(Vec3::new(target.x, target.y, 5.0), -Vec3::Z);
+
+            let value = ray_color(&options, &scene, &ray, &mut sampler).r();
+            assert!((value - 1.0).abs() < 1e-4, "{}", value);
+        }
+    }
+
+    // infinite slab of black fog between `y = low` and `y = high`
+    fn insert_fog_slab(
+        scene: &mut Scene,
+        low: f32,
+        high: f32,
+        density: f32,
+    ) {
+        let slab = scene.insert_shape(Cuboid::new(
+            Vec3::new(-1000.0, low, -1000.0),
+            Vec3::new(1000.0, high, 1000.0),
+        ));
+        let fog = scene.insert_shape(ConstantMedium::new(scene, slab, density));
+        let black = scene.insert_material(Isotropic::new(Color::from_rgb(0.0, 0.0, 0.0)));
+        scene.insert_object(fog, black);
+    }
+
+    fn mean_radiance(
+        scene: &Scene,
+        ray: &Ray,
+        max_depth: usize,
+    ) -> f32 {
+        let options = RayCastOptions { max_depth };
+        let sample_count = 1 << 14;
+        let mut sampler = SamplerKind::Sobol.create(sample_count, 23);
+
+        let mut sum = 0.0;
+        for index in 0..sample_count {
+            sampler.start_sample(0, 0, index);
+            sum += ray_color(&options, scene, ray, &mut *sampler).r();
+        }
+
+        sum / sample_count as f32
+    }
+
+    #[test]
+    fn light_seen_through_fog_is_attenuated() {
+        let mut scene = Scene::new();
+        scene.set_background(Background::Solid(Color::from_rgb(0.0, 0.0, 0.0)));
+
+        let light = scene.insert_material(DiffuseLight::new(Color::from_rgb(1.0, 1.0, 1.0)));
+        let floor = scene.insert_shape(Plane::new(Vec3::new(0.0, -10.0, 0.0), Vec3::Y));
+        scene.insert_object(floor, light);
+
+        insert_fog_slab(&mut scene, -2.0, -1.0, 0.5);
+
+        let mean = mean_radiance(&scene, &Ray::new(Vec3::ZERO, -Vec3::Y), 0);
+        assert!((mean - (-0.5f32).exp()).abs() < 0.01, "{}", mean);
+    }
+
+    /*
+     * White floor lit by a small sphere straight above, through fog. A sphere light of radiance
+     * `L` subtending `sin(theta_max)` gives the floor below it radiance `L sin^2(theta_max)`.
+     */
+    #[test]
+    fn shadow_rays_are_attenuated_by_transmittance() {
+        let mut scene = Scene::new();
+        scene.set_background(Background::Solid(Color::from_rgb(0.0, 0.0, 0.0)));
+
+        let white = scene.insert_material(Lambertian::new(Color::from_rgb(1.0, 1.0, 1.0)));
+        let floor = scene.insert_shape(Plane::new(Vec3::ZERO, Vec3::Y));
+        scene.insert_object(floor, white);
+
+        let light = scene.insert_material(DiffuseLight::new(Color::from_rgb(100.0, 100.0, 100.0)));
+        let sphere = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, 5.0, 0.0),
+            radius: 0.5,
+        });
+        scene.insert_object(sphere, light);
+
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), -Vec3::Y);
+
+        // light sampling and the scattered ray reaching the light both count through mis
+        let clear = mean_radiance(&scene, &ray, 1);
+        assert!((clear - 1.0).abs() < 0.01, "{}", clear);
+
+        insert_fog_slab(&mut scene, 2.0, 3.0, 0.5);
+
+        // the light is nearly straight above, paths through the fog are about 1 long
+        let foggy = mean_radiance(&scene, &ray, 1);
+        assert!((foggy - (-0.5f32).exp()).abs() < 0.01, "{}", foggy);
+    }
+}
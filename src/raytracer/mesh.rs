@@ -0,0 +1,636 @@
+use crate::cgmath::*;
+use crate::raytracer::aabb::*;
+use crate::raytracer::bvh::*;
+use crate::raytracer::description::ShapeDescription;
+use crate::raytracer::ray::*;
+use crate::raytracer::sampler::*;
+
+//
+//
+//
+
+/*
+ * Möller–Trumbore ray/triangle intersection.
+ *
+ * Returns `t` and barycentric coordinates of `b` and `c`.
+ */
+fn intersect_triangle(
+    ray: &Ray,
+    a: Vec3,
+    b: Vec3,
+    c: Vec3,
+    near: f32,
+    far: f32,
+) -> Option<(f32, f32, f32)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+
+    let p = Vec3::cross(*ray.direction(), edge2);
+    let det = Vec3::dot(edge1, p);
+
+    if det.abs() < 1e-10 {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+
+    let s = *ray.origin() - a;
+    let u = Vec3::dot(s, p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = Vec3::cross(s, edge1);
+    let v = Vec3::dot(*ray.direction(), q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = Vec3::dot(edge2, q) * inv_det;
+    if !(near < t && t < far) {
+        return None;
+    }
+
+    Some((t, u, v))
+}
+
+fn interpolate(
+    b1: f32,
+    b2: f32,
+    values: [Vec3; 3],
+) -> Vec3 {
+    let [a, b, c] = values;
+    (1.0 - b1 - b2) * a + b1 * b + b2 * c
+}
+
+/*
+ * Builds hit from barycentric coordinates, front face is decided by the geometric normal and
+ * the interpolated normal is flipped to the same side.
+ */
+fn triangle_hit(
+    ray: &Ray,
+    t: f32,
+    b1: f32,
+    b2: f32,
+    positions: [Vec3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f32, f32); 3]>,
+) -> ShapeHit {
+    let point = ray.at(t);
+
+    let geometric_normal =
+        Vec3::cross(positions[1] - positions[0], positions[2] - positions[0]).normalized();
+
+    let is_front_face = Vec3::dot(geometric_normal, *ray.direction()) < 0.0;
+
+    let normal = match normals {
+        Some(normals) => {
+            let n = interpolate(b1, b2, normals).normalized();
+            if Vec3::dot(n, geometric_normal) < 0.0 {
+                -n
+            } else {
+                n
+            }
+        }
+        None => geometric_normal,
+    };
+
+    let normal = if is_front_face { normal } else { -normal };
+
+    let (u, v) = match uvs {
+        Some([uv0, uv1, uv2]) => {
+            let w = 1.0 - b1 - b2;
+            (
+                w * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+                w * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+            )
+        }
+        None => (b1, b2),
+    };
+
+    ShapeHit {
+        point,
+        normal,
+        t,
+        is_front_face,
+        u,
+        v,
+    }
+}
+
+fn triangle_bounds(positions: [Vec3; 3]) -> Aabb {
+    Aabb::new(positions[0], positions[1]).grow(positions[2])
+}
+
+fn triangle_area(positions: [Vec3; 3]) -> f32 {
+    0.5 * Vec3::cross(positions[1] - positions[0], positions[2] - positions[0]).norm()
+}
+
+/*
+ * Converts area density `1 / area` at distance `t` along `direction` to solid angle density
+ */
+fn triangle_solid_angle_pdf(
+    positions: [Vec3; 3],
+    direction: Vec3,
+    t: f32,
+    area: f32,
+) -> f32 {
+    let geometric_normal =
+        Vec3::cross(positions[1] - positions[0], positions[2] - positions[0]).normalized();
+    let cosine = Vec3::dot(geometric_normal, direction).abs();
+
+    if cosine < 1e-6 {
+        return 0.0;
+    }
+
+    t * t / (cosine * area)
+}
+
+/*
+ * Uniformly samples a point on the triangle, `area` is the area the pdf is relative to
+ */
+fn sample_triangle(
+    origin: Vec3,
+    time: f32,
+    triangle: &Triangle,
+    area: f32,
+    sampler: &mut dyn Sampler,
+) -> Option<ShapeSample> {
+    let (u1, u2) = sampler.get_2d();
+
+    let su = u1.sqrt();
+    let b1 = u2 * su;
+    let b2 = 1.0 - su;
+
+    let point = interpolate(b1, b2, triangle.positions);
+
+    let ray = Ray::with_time(origin, point - origin, time);
+    let t = (point - origin).norm();
+
+    let pdf = triangle_solid_angle_pdf(triangle.positions, *ray.direction(), t, area);
+    if pdf == 0.0 || !pdf.is_finite() {
+        return None;
+    }
+
+    let hit = triangle_hit(
+        &ray,
+        t,
+        b1,
+        b2,
+        triangle.positions,
+        triangle.normals,
+        triangle.uvs,
+    );
+
+    Some(ShapeSample { hit, pdf })
+}
+
+//
+//
+//
+#[derive(Debug, Clone)]
+pub struct Triangle {
+    pub positions: [Vec3; 3],
+    pub normals: Option<[Vec3; 3]>,
+    pub uvs: Option<[(f32, f32); 3]>,
+}
+
+impl Triangle {
+    pub fn new(
+        a: Vec3,
+        b: Vec3,
+        c: Vec3,
+    ) -> Triangle {
+        Triangle {
+            positions: [a, b, c],
+            normals: None,
+            uvs: None,
+        }
+    }
+}
+
+impl HittableShape for Triangle {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        let [a, b, c] = self.positions;
+        let (t, b1, b2) = intersect_triangle(ray, a, b, c, near, far)?;
+
+        Some(triangle_hit(
+            ray,
+            t,
+            b1,
+            b2,
+            self.positions,
+            self.normals,
+            self.uvs,
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(triangle_bounds(self.positions))
+    }
+
+    fn sample(
+        &self,
+        origin: Vec3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ShapeSample> {
+        sample_triangle(origin, time, self, triangle_area(self.positions), sampler)
+    }
+
+    fn pdf(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        time: f32,
+    ) -> f32 {
+        let ray = Ray::with_time(origin, direction, time);
+        let [a, b, c] = self.positions;
+
+        match intersect_triangle(&ray, a, b, c, 0.0, f32::INFINITY) {
+            Some((t, _, _)) => triangle_solid_angle_pdf(
+                self.positions,
+                *ray.direction(),
+                t,
+                triangle_area(self.positions),
+            ),
+            None => 0.0,
+        }
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Triangle {
+            positions: self.positions.map(Into::into),
+            normals: self.normals.map(|normals| normals.map(Into::into)),
+            uvs: self.uvs.map(|uvs| uvs.map(|(u, v)| [u, v])),
+        })
+    }
+}
+
+//
+//
+//
+
+/*
+ * Indexed triangle mesh, normals and uvs (if present) are indexed the same as positions.
+ *
+ * Mesh keeps its own bvh over triangles so in a scene it is a single object.
+ */
+#[derive(Debug, Clone)]
+pub struct TriangleMesh {
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<(f32, f32)>>,
+    indices: Vec<[usize; 3]>,
+    bvh: Bvh,
+    // running sum of triangle areas, for picking triangles proportional to area
+    cumulative_areas: Vec<f32>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<(f32, f32)>>,
+        indices: Vec<[usize; 3]>,
+    ) -> TriangleMesh {
+        if let Some(normals) = &normals {
+            assert_eq!(normals.len(), positions.len());
+        }
+        if let Some(uvs) = &uvs {
+            assert_eq!(uvs.len(), positions.len());
+        }
+        assert!(indices.iter().flatten().all(|&i| i < positions.len()));
+
+        let bounds: Vec<Aabb> = indices
+            .iter()
+            .map(|&[a, b, c]| triangle_bounds([positions[a], positions[b], positions[c]]))
+            .collect();
+
+        let bvh = Bvh::build(&bounds);
+
+        let cumulative_areas = indices
+            .iter()
+            .scan(0.0, |sum, &[a, b, c]| {
+                *sum += triangle_area([positions[a], positions[b], positions[c]]);
+                Some(*sum)
+            })
+            .collect();
+
+        TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            indices,
+            bvh,
+            cumulative_areas,
+        }
+    }
+
+    pub fn area(&self) -> f32 {
+        self.cumulative_areas.last().copied().unwrap_or(0.0)
+    }
+
+    fn nearest_triangle(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<MeshHit> {
+        self.bvh.nearest_hit(ray, near, far, |index, near, far| {
+            let [a, b, c] = self.indices[index];
+            intersect_triangle(
+                ray,
+                self.positions[a],
+                self.positions[b],
+                self.positions[c],
+                near,
+                far,
+            )
+            .map(|(t, b1, b2)| MeshHit { index, t, b1, b2 })
+        })
+    }
+
+    pub fn triangle(
+        &self,
+        index: usize,
+    ) -> Triangle {
+        let [a, b, c] = self.indices[index];
+
+        Triangle {
+            positions: [self.positions[a], self.positions[b], self.positions[c]],
+            normals: self.normals.as_ref().map(|n| [n[a], n[b], n[c]]),
+            uvs: self.uvs.as_ref().map(|uv| [uv[a], uv[b], uv[c]]),
+        }
+    }
+}
+
+impl HittableShape for TriangleMesh {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        let nearest = self.nearest_triangle(ray, near, far)?;
+
+        // shading is done only for the nearest triangle
+        let triangle = self.triangle(nearest.index);
+
+        Some(triangle_hit(
+            ray,
+            nearest.t,
+            nearest.b1,
+            nearest.b2,
+            triangle.positions,
+            triangle.normals,
+            triangle.uvs,
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+
+    /*
+     * Uniform over the whole surface, triangle is picked proportional to its area
+     */
+    fn sample(
+        &self,
+        origin: Vec3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ShapeSample> {
+        let area = self.area();
+        if area <= 0.0 {
+            return None;
+        }
+
+        let target = sampler.get_1d() * area;
+        let index = self
+            .cumulative_areas
+            .partition_point(|&sum| sum <= target)
+            .min(self.indices.len() - 1);
+
+        sample_triangle(origin, time, &self.triangle(index), area, sampler)
+    }
+
+    fn pdf(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        time: f32,
+    ) -> f32 {
+        let ray = Ray::with_time(origin, direction, time);
+
+        match self.nearest_triangle(&ray, 0.0, f32::INFINITY) {
+            Some(hit) => triangle_solid_angle_pdf(
+                self.triangle(hit.index).positions,
+                *ray.direction(),
+                hit.t,
+                self.area(),
+            ),
+            None => 0.0,
+        }
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Mesh {
+            positions: self.positions.iter().map(|&p| p.into()).collect(),
+            normals: self
+                .normals
+                .as_ref()
+                .map(|normals| normals.iter().map(|&n| n.into()).collect()),
+            uvs: self
+                .uvs
+                .as_ref()
+                .map(|uvs| uvs.iter().map(|&(u, v)| [u, v]).collect()),
+            indices: self.indices.clone(),
+        })
+    }
+}
+
+struct MeshHit {
+    index: usize,
+    t: f32,
+    b1: f32,
+    b2: f32,
+}
+
+impl BvhHit for MeshHit {
+    fn t(&self) -> f32 {
+        self.t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg32;
+
+    fn unit_triangle() -> Triangle {
+        Triangle::new(Vec3::ZERO, Vec3::X, Vec3::Y)
+    }
+
+    fn down_at(
+        x: f32,
+        y: f32,
+    ) -> Ray {
+        Ray::new(Vec3::new(x, y, 1.0), -Vec3::Z)
+    }
+
+    fn assert_vec_close(
+        a: Vec3,
+        b: Vec3,
+    ) {
+        assert!((a - b).norm() < 1e-5, "{:?} differs from {:?}", a, b);
+    }
+
+    #[test]
+    fn intersect_triangle_includes_edges_and_vertices() {
+        let [a, b, c] = unit_triangle().positions;
+
+        // interior, on each edge and on a vertex
+        for (x, y) in [(0.25, 0.5), (0.5, 0.0), (0.0, 0.5), (0.5, 0.5), (0.0, 0.0)] {
+            let (t, b1, b2) = intersect_triangle(&down_at(x, y), a, b, c, 0.001, f32::INFINITY)
+                .unwrap_or_else(|| panic!("missed ({}, {})", x, y));
+            assert!((t - 1.0).abs() < 1e-6);
+            assert!((b1 - x).abs() < 1e-6 && (b2 - y).abs() < 1e-6);
+        }
+
+        // just outside each edge
+        for (x, y) in [(0.5, -1e-3), (-1e-3, 0.5), (0.501, 0.5)] {
+            assert!(intersect_triangle(&down_at(x, y), a, b, c, 0.001, f32::INFINITY).is_none());
+        }
+
+        // outside the search interval
+        assert!(intersect_triangle(&down_at(0.25, 0.25), a, b, c, 0.001, 0.5).is_none());
+    }
+
+    #[test]
+    fn intersect_triangle_misses_parallel_rays() {
+        let [a, b, c] = unit_triangle().positions;
+
+        for origin in [Vec3::new(-1.0, 0.25, 0.0), Vec3::new(-1.0, 0.25, 0.5)] {
+            let ray = Ray::new(origin, Vec3::X);
+            assert!(intersect_triangle(&ray, a, b, c, 0.001, f32::INFINITY).is_none());
+        }
+    }
+
+    #[test]
+    fn triangle_back_face_hit_flips_normal() {
+        let triangle = unit_triangle();
+
+        let front = triangle
+            .hit(&down_at(0.25, 0.25), 0.001, f32::INFINITY)
+            .unwrap();
+        assert!(front.is_front_face);
+        assert_vec_close(front.normal, Vec3::Z);
+
+        let back = triangle
+            .hit(
+                &Ray::new(Vec3::new(0.25, 0.25, -1.0), Vec3::Z),
+                0.001,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert!(!back.is_front_face);
+        assert_vec_close(back.normal, -Vec3::Z);
+    }
+
+    #[test]
+    fn triangle_interpolates_normals_and_uvs() {
+        let triangle = Triangle {
+            normals: Some([Vec3::Z, Vec3::X, Vec3::Y]),
+            uvs: Some([(0.2, 0.4), (1.0, 0.4), (0.2, 1.0)]),
+            ..unit_triangle()
+        };
+
+        // barycentric coordinates of b and c are 0.25 and 0.5
+        let expected_normal = (0.25 * Vec3::Z + 0.25 * Vec3::X + 0.5 * Vec3::Y).normalized();
+
+        let hit = triangle
+            .hit(&down_at(0.25, 0.5), 0.001, f32::INFINITY)
+            .unwrap();
+        assert_vec_close(hit.point, Vec3::new(0.25, 0.5, 0.0));
+        assert_vec_close(hit.normal, expected_normal);
+        assert!((hit.u - 0.4).abs() < 1e-6 && (hit.v - 0.7).abs() < 1e-6);
+
+        // from behind the interpolated normal is flipped with the geometric one
+        let hit = triangle
+            .hit(
+                &Ray::new(Vec3::new(0.25, 0.5, -1.0), Vec3::Z),
+                0.001,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert_vec_close(hit.normal, -expected_normal);
+
+        // without uvs they are the barycentric coordinates
+        let hit = unit_triangle()
+            .hit(&down_at(0.25, 0.5), 0.001, f32::INFINITY)
+            .unwrap();
+        assert!((hit.u - 0.25).abs() < 1e-6 && (hit.v - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mesh_hit_matches_brute_force_over_triangles() {
+        let mut rng = Pcg32::seed_from_u64(11);
+
+        let mut random_point = |extent: f32| {
+            Vec3::new(
+                rng.gen_range(-extent..extent),
+                rng.gen_range(-extent..extent),
+                rng.gen_range(-extent..extent),
+            )
+        };
+
+        // triangle soup of small and large, overlapping triangles
+        let mut positions = Vec::new();
+        for _ in 0..500 {
+            let corner = random_point(5.0);
+            let size = if positions.len() % 5 == 0 { 3.0 } else { 0.5 };
+            positions.push(corner);
+            positions.push(corner + random_point(size));
+            positions.push(corner + random_point(size));
+        }
+        let indices = (0..positions.len() / 3)
+            .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+            .collect();
+        let mesh = TriangleMesh::new(positions, None, None, indices);
+        let triangles: Vec<Triangle> = (0..mesh.indices.len()).map(|i| mesh.triangle(i)).collect();
+
+        for _ in 0..5_000 {
+            let ray = Ray::new(random_point(8.0), random_point(1.0));
+
+            let mut far = f32::INFINITY;
+            let mut expected = None;
+            for triangle in &triangles {
+                if let Some(hit) = triangle.hit(&ray, 0.001, far) {
+                    far = hit.t;
+                    expected = Some(hit);
+                }
+            }
+
+            let actual = mesh.hit(&ray, 0.001, f32::INFINITY);
+
+            match (expected, actual) {
+                (None, None) => {}
+                (Some(expected), Some(actual)) => {
+                    assert_eq!(expected.t, actual.t, "{:?}", ray);
+                    assert_vec_close(expected.normal, actual.normal);
+                }
+                (expected, actual) => {
+                    panic!("{:?}: brute force {:?}, mesh {:?}", ray, expected, actual)
+                }
+            }
+        }
+    }
+}
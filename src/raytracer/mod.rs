@@ -1,7 +1,21 @@
+pub mod aabb;
+pub mod bvh;
 pub mod camera;
 pub mod color;
+pub mod csg;
+pub mod description;
+pub mod framebuffer;
+pub mod instance;
 pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod obj;
+pub mod output;
 pub mod ray;
 pub mod raytrace;
+pub mod render;
+pub mod sampler;
 pub mod scene;
+pub mod scene_file;
 pub mod shape;
+pub mod texture;
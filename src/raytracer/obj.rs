@@ -0,0 +1,655 @@
+use crate::cgmath::*;
+use crate::raytracer::color::*;
+use crate::raytracer::material::*;
+use crate::raytracer::mesh::*;
+use crate::raytracer::scene::*;
+use crate::raytracer::texture::*;
+
+use anyhow::{anyhow, bail, Context};
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    str::SplitWhitespace,
+    sync::Arc,
+};
+
+//
+//
+//
+
+/*
+ * Loads Wavefront `.obj` file and its material libraries into `scene`.
+ *
+ * Every group/material combination becomes one `TriangleMesh` object. Faces are triangulated as
+ * fans. Materials are mapped from `.mtl` parameters:
+ *   - `d < 1` or `illum` 4, 6, 7 (refraction) -> `Dielectric` with `Ni`
+ *   - `illum` 3, 5 (reflection) -> `Metal` with `Ks`, fuzz derived from `Ns`
+ *   - anything else -> `Lambertian` with `Kd`, or the `map_Kd` image if there is one
+ */
+pub fn load_obj(
+    scene: &mut Scene,
+    path: &Path,
+) -> anyhow::Result<Vec<ObjectId>> {
+    let file = File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+    let obj = parse_obj(BufReader::new(file), path)?;
+
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut mtl_materials = HashMap::new();
+    for library in &obj.material_libraries {
+        let library_path = directory.join(library);
+        let file = File::open(&library_path)
+            .with_context(|| format!("cannot open {}", library_path.display()))?;
+        let library_directory = library_path.parent().unwrap_or_else(|| Path::new(""));
+        mtl_materials.extend(parse_mtl(
+            BufReader::new(file),
+            &library_path,
+            library_directory,
+        )?);
+    }
+
+    let mut material_ids = HashMap::new();
+    let mut objects = Vec::new();
+
+    for mesh in obj.meshes {
+        if mesh.indices.is_empty() {
+            continue;
+        }
+
+        let material = match material_ids.get(&mesh.material) {
+            Some(id) => *id,
+            None => {
+                let id = match &mesh.material {
+                    Some(name) => match mtl_materials.get(name) {
+                        Some(mtl) => insert_mtl_material(scene, mtl)
+                            .with_context(|| format!("material '{}'", name))?,
+                        None => bail!(
+                            "{}:{}: unknown material '{}'",
+                            path.display(),
+                            mesh.material_line,
+                            name
+                        ),
+                    },
+                    None => scene.insert_material(Lambertian::new(Color::from_rgb(0.8, 0.8, 0.8))),
+                };
+                material_ids.insert(mesh.material.clone(), id);
+                id
+            }
+        };
+
+        log::debug!(
+            "{}: group '{}' with {} triangles",
+            path.display(),
+            mesh.group,
+            mesh.indices.len()
+        );
+
+        let shape = scene.insert_shape(mesh.into_triangle_mesh());
+        objects.push(scene.insert_object(shape, material));
+    }
+
+    Ok(objects)
+}
+
+//
+//
+//
+#[derive(Debug, Default)]
+struct ObjData {
+    material_libraries: Vec<String>,
+    meshes: Vec<ObjMesh>,
+}
+
+// (position, uv, normal) indices into obj arrays, already zero based
+type VertexKey = (usize, Option<usize>, Option<usize>);
+
+#[derive(Debug, Default)]
+struct ObjMesh {
+    group: String,
+    material: Option<String>,
+    // line of `usemtl`, for error reporting
+    material_line: usize,
+    positions: Vec<Vec3>,
+    normals: Vec<Option<Vec3>>,
+    uvs: Vec<Option<(f32, f32)>>,
+    indices: Vec<[usize; 3]>,
+    vertices: HashMap<VertexKey, usize>,
+}
+
+impl ObjMesh {
+    fn new(
+        group: String,
+        material: Option<String>,
+    ) -> ObjMesh {
+        ObjMesh {
+            group,
+            material,
+            ..Default::default()
+        }
+    }
+
+    fn vertex(
+        &mut self,
+        key: VertexKey,
+        positions: &[Vec3],
+        uvs: &[(f32, f32)],
+        normals: &[Vec3],
+    ) -> usize {
+        let next = self.positions.len();
+        let index = *self.vertices.entry(key).or_insert(next);
+
+        if index == next {
+            let (p, uv, n) = key;
+            self.positions.push(positions[p]);
+            self.uvs.push(uv.map(|i| uvs[i]));
+            self.normals.push(n.map(|i| normals[i]));
+        }
+
+        index
+    }
+
+    /*
+     * Normals and uvs are kept only if every vertex has them
+     */
+    fn into_triangle_mesh(self) -> TriangleMesh {
+        let normals = self.normals.into_iter().collect::<Option<Vec<_>>>();
+        let uvs = self.uvs.into_iter().collect::<Option<Vec<_>>>();
+
+        TriangleMesh::new(self.positions, normals, uvs, self.indices)
+    }
+}
+
+fn parse_obj<R>(
+    reader: R,
+    path: &Path,
+) -> anyhow::Result<ObjData>
+where
+    R: BufRead,
+{
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+
+    let mut obj = ObjData::default();
+    let mut mesh = ObjMesh::new("default".to_string(), None);
+
+    for (line_index, line) in reader.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = line.with_context(|| format!("{}:{}", path.display(), line_number))?;
+        let error_context = || format!("{}:{}", path.display(), line_number);
+
+        let line = strip_comment(&line);
+        let mut tokens = line.split_whitespace();
+
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&mut tokens).with_context(error_context)?),
+            "vn" => normals.push(parse_vec3(&mut tokens).with_context(error_context)?),
+            "vt" => {
+                let u = parse_f32(tokens.next()).with_context(error_context)?;
+                // `v` is optional
+                let v = match tokens.next() {
+                    Some(token) => parse_f32(Some(token)).with_context(error_context)?,
+                    None => 0.0,
+                };
+                uvs.push((u, v));
+            }
+            "f" => {
+                let mut face = Vec::with_capacity(4);
+                for token in tokens {
+                    let key = parse_face_vertex(token, positions.len(), uvs.len(), normals.len())
+                        .with_context(error_context)?;
+                    face.push(mesh.vertex(key, &positions, &uvs, &normals));
+                }
+
+                if face.len() < 3 {
+                    bail!("{}: face needs at least 3 vertices", error_context());
+                }
+
+                // fan triangulation
+                for i in 1..face.len() - 1 {
+                    mesh.indices.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+            "g" | "o" => {
+                let group = tokens.collect::<Vec<_>>().join(" ");
+                let material = mesh.material.clone();
+                let material_line = mesh.material_line;
+                let previous = std::mem::replace(&mut mesh, ObjMesh::new(group, material));
+                mesh.material_line = material_line;
+                obj.meshes.push(previous);
+            }
+            "usemtl" => {
+                let material = tokens.collect::<Vec<_>>().join(" ");
+                if material.is_empty() {
+                    bail!("{}: missing material name", error_context());
+                }
+                let group = mesh.group.clone();
+                let previous = std::mem::replace(&mut mesh, ObjMesh::new(group, Some(material)));
+                mesh.material_line = line_number;
+                obj.meshes.push(previous);
+            }
+            "mtllib" => obj
+                .material_libraries
+                .extend(tokens.map(|token| token.to_string())),
+            _ => log::debug!("{}: ignoring '{}'", error_context(), keyword),
+        }
+    }
+
+    obj.meshes.push(mesh);
+
+    Ok(obj)
+}
+
+/*
+ * Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`. Indices are one based, negative ones are relative
+ * to the end of the arrays read so far.
+ */
+fn parse_face_vertex(
+    token: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+) -> anyhow::Result<VertexKey> {
+    let mut parts = token.split('/');
+
+    let position = match parts.next() {
+        Some(part) => resolve_index(part, position_count, "vertex")?,
+        None => bail!("empty face vertex"),
+    };
+
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(part) => Some(resolve_index(part, uv_count, "texture coordinate")?),
+    };
+
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(part) => Some(resolve_index(part, normal_count, "normal")?),
+    };
+
+    if parts.next().is_some() {
+        bail!("invalid face vertex '{}'", token);
+    }
+
+    Ok((position, uv, normal))
+}
+
+fn resolve_index(
+    token: &str,
+    count: usize,
+    what: &str,
+) -> anyhow::Result<usize> {
+    let index: i64 = token
+        .parse()
+        .map_err(|_| anyhow!("invalid {} index '{}'", what, token))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        bail!("{} index {} out of range (have {})", what, index, count);
+    }
+
+    Ok(resolved as usize)
+}
+
+//
+//
+//
+#[derive(Debug, Clone)]
+struct MtlMaterial {
+    diffuse: Vec3,
+    // resolved against the directory of the mtl file
+    diffuse_map: Option<PathBuf>,
+    specular: Vec3,
+    specular_exponent: f32,
+    refraction_index: f32,
+    dissolve: f32,
+    illumination: u32,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> MtlMaterial {
+        MtlMaterial {
+            diffuse: Vec3::new(0.8, 0.8, 0.8),
+            diffuse_map: None,
+            specular: Vec3::ZERO,
+            specular_exponent: 0.0,
+            refraction_index: 1.0,
+            dissolve: 1.0,
+            illumination: 2,
+        }
+    }
+}
+
+fn parse_mtl<R>(
+    reader: R,
+    path: &Path,
+    directory: &Path,
+) -> anyhow::Result<HashMap<String, MtlMaterial>>
+where
+    R: BufRead,
+{
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (line_index, line) in reader.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = line.with_context(|| format!("{}:{}", path.display(), line_number))?;
+        let error_context = || format!("{}:{}", path.display(), line_number);
+
+        let line = strip_comment(&line);
+        let mut tokens = line.split_whitespace();
+
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = tokens.collect::<Vec<_>>().join(" ");
+            if name.is_empty() {
+                bail!("{}: missing material name", error_context());
+            }
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+            current = Some((name, MtlMaterial::default()));
+            continue;
+        }
+
+        let material = match &mut current {
+            Some((_, material)) => material,
+            None => bail!("{}: '{}' before 'newmtl'", error_context(), keyword),
+        };
+
+        match keyword {
+            "Kd" => material.diffuse = parse_vec3(&mut tokens).with_context(error_context)?,
+            "map_Kd" => {
+                // options like `-s` aren't supported, the file name is the last token
+                let file_name = tokens
+                    .last()
+                    .ok_or_else(|| anyhow!("{}: missing file name", error_context()))?;
+                material.diffuse_map = Some(directory.join(file_name));
+            }
+            "Ks" => material.specular = parse_vec3(&mut tokens).with_context(error_context)?,
+            "Ns" => {
+                material.specular_exponent = parse_f32(tokens.next()).with_context(error_context)?
+            }
+            "Ni" => {
+                material.refraction_index = parse_f32(tokens.next()).with_context(error_context)?
+            }
+            "d" => material.dissolve = parse_f32(tokens.next()).with_context(error_context)?,
+            "Tr" => {
+                material.dissolve = 1.0 - parse_f32(tokens.next()).with_context(error_context)?
+            }
+            "illum" => {
+                material.illumination = parse_f32(tokens.next()).with_context(error_context)? as u32
+            }
+            _ => log::debug!("{}: ignoring '{}'", error_context(), keyword),
+        }
+    }
+
+    if let Some((name, material)) = current.take() {
+        materials.insert(name, material);
+    }
+
+    Ok(materials)
+}
+
+fn insert_mtl_material(
+    scene: &mut Scene,
+    mtl: &MtlMaterial,
+) -> anyhow::Result<MaterialId> {
+    let is_refractive = mtl.dissolve < 1.0 || matches!(mtl.illumination, 4 | 6 | 7);
+    let is_reflective = matches!(mtl.illumination, 3 | 5);
+
+    let id = if is_refractive {
+        scene.insert_material(Dielectric::new(mtl.refraction_index.max(1.0)))
+    } else if is_reflective {
+        // higher exponent means sharper highlight, map it to smaller fuzz
+        let fuzz = (2.0 / (mtl.specular_exponent + 2.0)).sqrt();
+        scene.insert_material(Metal::new(Color::from(mtl.specular).clamped(), fuzz))
+    } else if let Some(path) = &mtl.diffuse_map {
+        let texture = ImageTexture::load(path, WrapMode::Repeat, TextureFilter::Bilinear)?;
+        scene.insert_material(Lambertian::textured(Arc::new(texture)))
+    } else {
+        scene.insert_material(Lambertian::new(Color::from(mtl.diffuse).clamped()))
+    };
+
+    Ok(id)
+}
+
+//
+//
+//
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(index) => &line[..index],
+        None => line,
+    }
+}
+
+fn parse_f32(token: Option<&str>) -> anyhow::Result<f32> {
+    match token {
+        Some(token) => token
+            .parse()
+            .map_err(|_| anyhow!("invalid number '{}'", token)),
+        None => bail!("missing number"),
+    }
+}
+
+fn parse_vec3(tokens: &mut SplitWhitespace) -> anyhow::Result<Vec3> {
+    let x = parse_f32(tokens.next())?;
+    let y = parse_f32(tokens.next())?;
+    let z = parse_f32(tokens.next())?;
+    Ok(Vec3::new(x, y, z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracer::description::MaterialDescription;
+
+    use std::fs;
+
+    fn parse(source: &str) -> anyhow::Result<ObjData> {
+        parse_obj(source.as_bytes(), Path::new("test.obj"))
+    }
+
+    // the only mesh with faces
+    fn parse_mesh(source: &str) -> ObjMesh {
+        let mut meshes: Vec<ObjMesh> = parse(source)
+            .unwrap()
+            .meshes
+            .into_iter()
+            .filter(|mesh| !mesh.indices.is_empty())
+            .collect();
+        assert_eq!(meshes.len(), 1);
+        meshes.pop().unwrap()
+    }
+
+    fn parse_error(source: &str) -> String {
+        format!("{:#}", parse(source).unwrap_err())
+    }
+
+    const SQUARE: &str = "
+        v 0 0 0
+        v 1 0 0
+        v 1 1 0
+        v 0 1 0
+    ";
+
+    #[test]
+    fn negative_indices_are_relative_to_vertices_read_so_far() {
+        let mesh = parse_mesh(&format!("{}\nf -4 -3 -2\nv 5 5 5\nf -1 1 2", SQUARE));
+
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [3, 0, 1]]);
+        assert_eq!(mesh.positions[2].x, 1.0);
+        assert_eq!(mesh.positions[3].x, 5.0);
+    }
+
+    #[test]
+    fn polygons_are_fan_triangulated() {
+        let quad = parse_mesh(&format!("{}\nf 1 2 3 4", SQUARE));
+        assert_eq!(quad.indices, vec![[0, 1, 2], [0, 2, 3]]);
+
+        let pentagon = parse_mesh(&format!("{}\nv 0.5 2 0\nf 1 2 3 5 4", SQUARE));
+        assert_eq!(pentagon.indices, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+
+        assert!(parse_error(&format!("{}\nf 1 2", SQUARE)).contains("at least 3 vertices"));
+    }
+
+    #[test]
+    fn vertex_forms_with_normals_or_uvs() {
+        let mesh = parse_mesh(&format!("{}\nvn 0 0 1\nf 1//1 2//1 3//1", SQUARE));
+        assert!(mesh.uvs.iter().all(Option::is_none));
+        assert!(mesh.normals.iter().all(|n| n.map(|n| n.z) == Some(1.0)));
+
+        let mesh = parse_mesh(&format!(
+            "{}\nvt 0 0\nvt 1 0\nvt 1 1\nf 1/1 2/2 3/3",
+            SQUARE
+        ));
+        assert!(mesh.normals.iter().all(Option::is_none));
+        assert_eq!(
+            mesh.uvs,
+            vec![Some((0.0, 0.0)), Some((1.0, 0.0)), Some((1.0, 1.0))]
+        );
+
+        let triangle_mesh = mesh.into_triangle_mesh();
+        assert!(triangle_mesh.triangle(0).uvs.is_some());
+        assert!(triangle_mesh.triangle(0).normals.is_none());
+
+        // same position with different uvs is a different vertex
+        let mesh = parse_mesh(&format!(
+            "{}\nvt 0 0\nvt 1 0\nf 1/1 2/1 3/1\nf 1/2 3/1 4/1",
+            SQUARE
+        ));
+        assert_eq!(mesh.positions.len(), 5);
+    }
+
+    #[test]
+    fn invalid_indices_report_path_and_line() {
+        let error = parse_error(&format!("{}\nf 0 1 2", SQUARE));
+        assert!(error.starts_with("test.obj:7:"), "{}", error);
+        assert!(error.contains("vertex index 0 out of range"), "{}", error);
+
+        let error = parse_error(&format!("{}\nf 1 2 5", SQUARE));
+        assert!(error.starts_with("test.obj:7:"), "{}", error);
+        assert!(
+            error.contains("vertex index 5 out of range (have 4)"),
+            "{}",
+            error
+        );
+
+        let error = parse_error(&format!("{}\nf 1 2 -5", SQUARE));
+        assert!(error.contains("vertex index -5 out of range"), "{}", error);
+
+        let error = parse_error(&format!("{}\nvn 0 0 1\nf 1//1 2//2 3//1", SQUARE));
+        assert!(error.starts_with("test.obj:8:"), "{}", error);
+        assert!(error.contains("normal index 2 out of range"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_material_reports_usemtl_line() {
+        let directory = std::env::temp_dir().join(format!("obj_test_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let obj_path = directory.join("model.obj");
+        let mtl_path = directory.join("model.mtl");
+        fs::write(&mtl_path, "newmtl red\nKd 1 0 0\n").unwrap();
+        fs::write(
+            &obj_path,
+            format!(
+                "mtllib model.mtl\n{}\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 3 4\n",
+                SQUARE
+            ),
+        )
+        .unwrap();
+
+        let error = format!("{:#}", load_obj(&mut Scene::new(), &obj_path).unwrap_err());
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        let expected = format!("{}:10: unknown material 'blue'", obj_path.display());
+        assert!(error.contains(&expected), "{}", error);
+    }
+
+    #[test]
+    fn mtl_parameters_pick_material() {
+        let source = "
+            newmtl glass
+            Kd 1 1 1
+            d 0.5
+            Ni 1.5
+
+            newmtl refractive
+            illum 7
+            Ni 1.3
+
+            newmtl mirror
+            illum 3
+            Ks 0.9 0.9 0.9
+            Ns 1000
+
+            newmtl plain
+            Kd 0.2 0.4 0.6
+            illum 2
+
+            newmtl opaque_glossy
+            d 1
+            illum 2
+            Ks 1 1 1
+        ";
+        let materials = parse_mtl(source.as_bytes(), Path::new("test.mtl"), Path::new("")).unwrap();
+
+        let describe = |name: &str| {
+            let mut scene = Scene::new();
+            insert_mtl_material(&mut scene, &materials[name]).unwrap();
+            let (_, material) = scene.materials().next().unwrap();
+            material.description().unwrap()
+        };
+
+        match describe("glass") {
+            MaterialDescription::Dielectric { refraction_index } => {
+                assert_eq!(refraction_index, 1.5)
+            }
+            other => panic!("glass is {:?}", other),
+        }
+        match describe("refractive") {
+            MaterialDescription::Dielectric { refraction_index } => {
+                assert_eq!(refraction_index, 1.3)
+            }
+            other => panic!("refractive is {:?}", other),
+        }
+        match describe("mirror") {
+            MaterialDescription::Metal { fuzz, .. } => assert!(fuzz < 0.1, "{}", fuzz),
+            other => panic!("mirror is {:?}", other),
+        }
+        assert!(matches!(
+            describe("plain"),
+            MaterialDescription::Lambertian { .. }
+        ));
+        assert!(matches!(
+            describe("opaque_glossy"),
+            MaterialDescription::Lambertian { .. }
+        ));
+
+        let error = format!(
+            "{:#}",
+            parse_mtl("Kd 1 1 1".as_bytes(), Path::new("test.mtl"), Path::new("")).unwrap_err()
+        );
+        assert!(error.starts_with("test.mtl:1:"), "{}", error);
+    }
+}
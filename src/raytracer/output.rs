@@ -0,0 +1,512 @@
+use crate::raytracer::framebuffer::*;
+use crate::raytracer::sampler::SamplerKind;
+
+use anyhow::{bail, Context};
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+//
+//
+//
+
+/*
+ * Settings an image was rendered with, stored in the image file where the format allows it
+ */
+#[derive(Debug, Clone)]
+pub struct RenderInfo {
+    pub scene: String,
+    pub sample_count: usize,
+    pub max_depth: usize,
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub render_time: Duration,
+}
+
+impl RenderInfo {
+    pub fn text_entries(&self) -> Vec<(String, String)> {
+        vec![
+            ("Software".to_string(), "raytracer_rs".to_string()),
+            ("Scene".to_string(), self.scene.clone()),
+            (
+                "Samples per pixel".to_string(),
+                self.sample_count.to_string(),
+            ),
+            ("Max depth".to_string(), self.max_depth.to_string()),
+            ("Seed".to_string(), self.seed.to_string()),
+            ("Sampler".to_string(), self.sampler.name().to_string()),
+            (
+                "Render time".to_string(),
+                format!("{:.3}s", self.render_time.as_secs_f64()),
+            ),
+        ]
+    }
+}
+
+/*
+ * `render_<seconds since epoch>.<extension>`, so repeated saves don't overwrite each other
+ */
+pub fn timestamped_file_name(extension: &str) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("render_{}.{}", seconds, extension)
+}
+
+// float by default, from the gui and the command line alike
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExrPrecision {
+    Half,
+    #[default]
+    Float,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    // 8 bit, gamma corrected and clamped
+    Png,
+    // Radiance rgbe, linear
+    Hdr,
+    // portable float map, linear
+    Pfm,
+    // OpenEXR, linear, with aovs as extra channels
+    Exr,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> anyhow::Result<ImageFormat> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("png") => Ok(ImageFormat::Png),
+            Some("hdr") => Ok(ImageFormat::Hdr),
+            Some("pfm") => Ok(ImageFormat::Pfm),
+            Some("exr") => Ok(ImageFormat::Exr),
+            _ => bail!(
+                "{}: unknown image format, expected png, hdr, pfm or exr",
+                path.display()
+            ),
+        }
+    }
+}
+
+/*
+ * Picks format by extension
+ */
+pub fn save_image(
+    path: &Path,
+    framebuffer: &Framebuffer,
+    info: Option<&RenderInfo>,
+    exr_precision: ExrPrecision,
+) -> anyhow::Result<()> {
+    match ImageFormat::from_path(path)? {
+        ImageFormat::Png => save_png(path, framebuffer, info),
+        ImageFormat::Hdr => save_hdr(path, framebuffer, info),
+        ImageFormat::Pfm => save_pfm(path, framebuffer),
+        ImageFormat::Exr => save_exr(path, framebuffer, info, exr_precision),
+    }
+}
+
+//
+//
+//
+
+/*
+ * Writes 8 bit gamma corrected rgb png, `info` goes into text chunks
+ */
+pub fn save_png(
+    path: &Path,
+    framebuffer: &Framebuffer,
+    info: Option<&RenderInfo>,
+) -> anyhow::Result<()> {
+    let file = File::create(path).with_context(|| format!("cannot create {}", path.display()))?;
+
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        framebuffer.width() as u32,
+        framebuffer.height() as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    if let Some(info) = info {
+        for (keyword, text) in info.text_entries() {
+            encoder.add_text_chunk(keyword, text)?;
+        }
+    }
+
+    let mut writer = encoder
+        .write_header()
+        .with_context(|| format!("cannot write {}", path.display()))?;
+    writer
+        .write_image_data(&framebuffer.to_rgb8())
+        .with_context(|| format!("cannot write {}", path.display()))?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+//
+//
+//
+
+/*
+ * Writes uncompressed Radiance rgbe, `info` goes into header comments
+ */
+pub fn save_hdr(
+    path: &Path,
+    framebuffer: &Framebuffer,
+    info: Option<&RenderInfo>,
+) -> anyhow::Result<()> {
+    let write = || -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writeln!(writer, "#?RADIANCE")?;
+        if let Some(info) = info {
+            for (keyword, text) in info.text_entries() {
+                writeln!(writer, "# {}: {}", keyword, text)?;
+            }
+        }
+        writeln!(writer, "FORMAT=32-bit_rle_rgbe")?;
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "-Y {} +X {}",
+            framebuffer.height(),
+            framebuffer.width()
+        )?;
+
+        for color in framebuffer.pixels() {
+            writer.write_all(&to_rgbe([color.r(), color.g(), color.b()]))?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    };
+
+    write().with_context(|| format!("cannot write {}", path.display()))
+}
+
+/*
+ * Shared exponent encoding: mantissas of all components are scaled by the largest one's exponent
+ */
+fn to_rgbe(rgb: [f32; 3]) -> [u8; 4] {
+    let [r, g, b] = rgb.map(|c| c.max(0.0));
+    let max = r.max(g).max(b);
+
+    if max < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // max = mantissa * 2^exponent, mantissa in [0.5, 1)
+    let mut exponent = max.log2().floor() as i32 + 1;
+    if max / 2.0f32.powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+
+    let scale = 256.0 / 2.0f32.powi(exponent);
+
+    [
+        (r * scale) as u8,
+        (g * scale) as u8,
+        (b * scale) as u8,
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+/*
+ * Writes little endian portable float map, rows go bottom to top
+ */
+pub fn save_pfm(
+    path: &Path,
+    framebuffer: &Framebuffer,
+) -> anyhow::Result<()> {
+    let write = || -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        // negative scale means little endian
+        write!(
+            writer,
+            "PF\n{} {}\n-1.0\n",
+            framebuffer.width(),
+            framebuffer.height()
+        )?;
+
+        for y in (0..framebuffer.height()).rev() {
+            for x in 0..framebuffer.width() {
+                let color = framebuffer.get(x, y);
+                for c in [color.r(), color.g(), color.b()] {
+                    writer.write_all(&c.to_le_bytes())?;
+                }
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    };
+
+    write().with_context(|| format!("cannot write {}", path.display()))
+}
+
+/*
+ * Writes single layer OpenEXR with `R`, `G`, `B` and one channel per aov, `info` goes into
+ * header attributes
+ */
+pub fn save_exr(
+    path: &Path,
+    framebuffer: &Framebuffer,
+    info: Option<&RenderInfo>,
+    precision: ExrPrecision,
+) -> anyhow::Result<()> {
+    use exr::prelude::*;
+
+    let samples = |values: Vec<f32>| match precision {
+        ExrPrecision::Half => FlatSamples::F16(values.into_iter().map(f16::from_f32).collect()),
+        ExrPrecision::Float => FlatSamples::F32(values),
+    };
+
+    let pixels = framebuffer.pixels();
+
+    let mut channels = SmallVec::new();
+    channels.push(AnyChannel::new(
+        "R",
+        samples(pixels.iter().map(|c| c.r()).collect()),
+    ));
+    channels.push(AnyChannel::new(
+        "G",
+        samples(pixels.iter().map(|c| c.g()).collect()),
+    ));
+    channels.push(AnyChannel::new(
+        "B",
+        samples(pixels.iter().map(|c| c.b()).collect()),
+    ));
+
+    for aov in framebuffer.aovs() {
+        let name = match Text::new_or_none(aov.name()) {
+            Some(name) => name,
+            None => bail!("aov name '{}' is not valid in exr", aov.name()),
+        };
+        channels.push(AnyChannel::new(name, samples(aov.values().to_vec())));
+    }
+
+    let mut attributes = LayerAttributes {
+        software_name: Some(Text::from("raytracer_rs")),
+        ..LayerAttributes::default()
+    };
+
+    if let Some(info) = info {
+        for (keyword, text) in info.text_entries() {
+            if let (Some(keyword), Some(text)) =
+                (Text::new_or_none(&keyword), Text::new_or_none(&text))
+            {
+                attributes.other.insert(keyword, AttributeValue::Text(text));
+            }
+        }
+    }
+
+    let layer = Layer::new(
+        (framebuffer.width(), framebuffer.height()),
+        attributes,
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+
+    Image::from_layer(layer)
+        .write()
+        .to_file(path)
+        .with_context(|| format!("cannot write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracer::color::Color;
+
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg32;
+
+    use std::{fs, path::PathBuf};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("output_test_{}_{}", std::process::id(), name))
+    }
+
+    fn info() -> RenderInfo {
+        RenderInfo {
+            scene: "Book 1 final scene".to_string(),
+            sample_count: 64,
+            max_depth: 12,
+            seed: 42,
+            sampler: SamplerKind::Sobol,
+            render_time: Duration::from_millis(1500),
+        }
+    }
+
+    fn from_rgbe(rgbe: [u8; 4]) -> [f32; 3] {
+        if rgbe[3] == 0 {
+            return [0.0; 3];
+        }
+        // mantissas are truncated, the middle of their interval is the best guess
+        let scale = 2.0f32.powi(rgbe[3] as i32 - 128) / 256.0;
+        [0, 1, 2].map(|i| (rgbe[i] as f32 + 0.5) * scale)
+    }
+
+    #[test]
+    fn rgbe_error_is_within_half_a_step_of_largest_component() {
+        let mut rng = Pcg32::seed_from_u64(5);
+
+        for _ in 0..100_000 {
+            let exponent = rng.gen_range(-20..20);
+            let rgb = [0, 1, 2].map(|_| rng.gen::<f32>() * 2.0f32.powi(exponent));
+            let max = rgb[0].max(rgb[1]).max(rgb[2]);
+
+            let decoded = from_rgbe(to_rgbe(rgb));
+
+            // mantissa of the largest component is in [128, 256), one step is at most max / 128
+            for (c, d) in rgb.iter().zip(decoded) {
+                assert!(
+                    (c - d).abs() <= max / 256.0 * 1.0001,
+                    "{:?} -> {:?}",
+                    rgb,
+                    decoded
+                );
+            }
+        }
+
+        assert_eq!(to_rgbe([0.0, 0.0, 0.0]), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe([-1.0, 0.0, 0.0]), [0, 0, 0, 0]);
+        // exact powers of two land at the top of the mantissa range
+        assert_eq!(to_rgbe([1.0, 0.5, 0.0]), [128, 64, 0, 129]);
+    }
+
+    #[test]
+    fn hdr_pixels_round_trip() {
+        let path = temp_path("round_trip.hdr");
+
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.set(0, 0, Color::from_rgb(10.0, 0.25, 0.0));
+        framebuffer.set(2, 1, Color::from_rgb(0.001, 0.002, 0.003));
+        save_hdr(&path, &framebuffer, Some(&info())).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let resolution = b"-Y 2 +X 3\n";
+        let data_start = bytes
+            .windows(resolution.len())
+            .position(|window| window == resolution)
+            .unwrap()
+            + resolution.len();
+        let data = &bytes[data_start..];
+        assert_eq!(data.len(), 4 * 3 * 2);
+
+        for (pixel, color) in data.chunks(4).zip(framebuffer.pixels()) {
+            let decoded = from_rgbe([pixel[0], pixel[1], pixel[2], pixel[3]]);
+            let max = color.r().max(color.g()).max(color.b());
+            for (c, d) in [color.r(), color.g(), color.b()].iter().zip(decoded) {
+                assert!(
+                    (c - d).abs() <= max / 256.0 * 1.0001,
+                    "{:?} -> {:?}",
+                    color,
+                    decoded
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn exr_has_rgb_and_aov_channels_in_requested_precision() {
+        use exr::prelude::{read_all_flat_layers_from_file, FlatSamples};
+
+        let mut framebuffer = Framebuffer::new(2, 2);
+        framebuffer.set(1, 0, Color::from_rgb(1.0 / 3.0, 1000.5, 0.0));
+        let depth = framebuffer.add_aov("Z");
+        framebuffer.set_aov(depth, 1, 0, 12.345);
+
+        for precision in [ExrPrecision::Half, ExrPrecision::Float] {
+            let path = temp_path(&format!("channels_{:?}.exr", precision));
+            save_exr(&path, &framebuffer, Some(&info()), precision).unwrap();
+            let image = read_all_flat_layers_from_file(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            let channels = &image.layer_data[0].channel_data.list;
+            let names: Vec<String> = channels.iter().map(|c| c.name.to_string()).collect();
+            assert_eq!(names, ["B", "G", "R", "Z"]);
+
+            // second pixel of the top row
+            let values: Vec<f32> = channels
+                .iter()
+                .map(|channel| match (&channel.sample_data, precision) {
+                    (FlatSamples::F16(samples), ExrPrecision::Half) => samples[1].to_f32(),
+                    (FlatSamples::F32(samples), ExrPrecision::Float) => samples[1],
+                    (samples, _) => panic!("{:?} written as {:?}", precision, samples),
+                })
+                .collect();
+            let expected = [0.0, 1000.5, 1.0 / 3.0, 12.345];
+
+            // half has 11 significant bits
+            let tolerance = match precision {
+                ExrPrecision::Half => 1.0 / 1024.0,
+                ExrPrecision::Float => 0.0,
+            };
+            for (value, expected) in values.iter().zip(expected) {
+                assert!(
+                    (value - expected).abs() <= tolerance * expected,
+                    "{:?}: {} read back as {}",
+                    precision,
+                    expected,
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn png_stores_render_info_in_text_chunks() {
+        let path = temp_path("info.png");
+
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.set(2, 1, Color::from_rgb(1.0, 0.5, 0.0));
+        save_png(&path, &framebuffer, Some(&info())).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+
+        let entries: Vec<(String, String)> = reader
+            .info()
+            .uncompressed_latin1_text
+            .iter()
+            .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+            .collect();
+
+        fs::remove_file(&path).unwrap();
+
+        let text = |keyword: &str| {
+            entries
+                .iter()
+                .find(|(k, _)| k == keyword)
+                .map(|(_, text)| text.as_str())
+                .unwrap_or_else(|| panic!("no '{}' in {:?}", keyword, entries))
+        };
+
+        assert_eq!(text("Software"), "raytracer_rs");
+        assert_eq!(text("Scene"), "Book 1 final scene");
+        assert_eq!(text("Samples per pixel"), "64");
+        assert_eq!(text("Max depth"), "12");
+        assert_eq!(text("Seed"), "42");
+        assert_eq!(text("Sampler"), SamplerKind::Sobol.name());
+        assert_eq!(text("Render time"), "1.500s");
+
+        assert_eq!(pixels, framebuffer.to_rgb8());
+    }
+}
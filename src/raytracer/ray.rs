@@ -1,4 +1,8 @@
 use crate::cgmath::Vec3;
+use crate::raytracer::aabb::Aabb;
+use crate::raytracer::description::ShapeDescription;
+use crate::raytracer::medium::Medium;
+use crate::raytracer::sampler::Sampler;
 
 use std::fmt::Debug;
 
@@ -10,15 +14,29 @@ use std::fmt::Debug;
 pub struct Ray {
     origin: Vec3,
     direction: Vec3,
+    // moment within the camera shutter interval, moving shapes are hit where they are then
+    time: f32,
 }
 
 impl Ray {
     pub fn new(
         origin: Vec3,
         direction: Vec3,
+    ) -> Ray {
+        Ray::with_time(origin, direction, 0.0)
+    }
+
+    pub fn with_time(
+        origin: Vec3,
+        direction: Vec3,
+        time: f32,
     ) -> Ray {
         let direction = direction.normalized();
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            time,
+        }
     }
 
     pub fn origin(&self) -> &Vec3 {
@@ -29,6 +47,10 @@ impl Ray {
         &self.direction
     }
 
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
     pub fn at(
         &self,
         t: f32,
@@ -46,6 +68,17 @@ pub struct ShapeHit {
     pub normal: Vec3,
     pub t: f32,
     pub is_front_face: bool,
+    // surface parametrization at `point`, shapes without one leave it at zero
+    pub u: f32,
+    pub v: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShapeSample {
+    // as if a ray from the sampling origin hit the shape
+    pub hit: ShapeHit,
+    // with respect to solid angle at the sampling origin
+    pub pdf: f32,
 }
 
 pub trait HittableShape: Sync + Send + Debug {
@@ -55,4 +88,62 @@ pub trait HittableShape: Sync + Send + Debug {
         near: f32,
         far: f32,
     ) -> Option<ShapeHit>;
+
+    /*
+     * Every crossing of the surface between `near` and `far`, nearest first. For closed shapes
+     * crossings alternate between entering through the front face and leaving.
+     *
+     * Repeats `hit` past each crossing unless overridden, `hit` never returns `near` itself.
+     */
+    fn hits(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Vec<ShapeHit> {
+        let mut hits = Vec::new();
+        let mut near = near;
+
+        while let Some(hit) = self.hit(ray, near, far) {
+            near = hit.t;
+            hits.push(hit);
+        }
+
+        hits
+    }
+
+    // `None` for unbounded shapes, those are always tested. Moving shapes are bounded over the
+    // whole shutter interval.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // point on the shape visible from `origin` at `time`, used for sampling lights
+    fn sample(
+        &self,
+        _origin: Vec3,
+        _time: f32,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ShapeSample> {
+        None
+    }
+
+    // density of `sample` producing `direction` from `origin` at `time`
+    fn pdf(
+        &self,
+        _origin: Vec3,
+        _direction: Vec3,
+        _time: f32,
+    ) -> f32 {
+        0.0
+    }
+
+    // medium filling the shape, rays crossing its surface enter or leave it instead of
+    // scattering there
+    fn medium(&self) -> Option<&dyn Medium> {
+        None
+    }
+
+    // how to write the shape to a scene file, `None` if it can't be
+    fn description(&self) -> Option<ShapeDescription> {
+        None
+    }
 }
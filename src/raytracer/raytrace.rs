@@ -1,56 +1,312 @@
 use crate::cgmath::*;
 use crate::raytracer::color::*;
 use crate::raytracer::ray::*;
+use crate::raytracer::sampler::*;
 use crate::raytracer::scene::*;
 
 #[derive(Clone, Copy)]
 pub struct RayCastOptions {
-    pub sample_count: usize,
     pub max_depth: usize,
 }
 
 impl Default for RayCastOptions {
     fn default() -> Self {
-        RayCastOptions {
-            sample_count: 50,
-            max_depth: 8,
-        }
+        RayCastOptions { max_depth: 8 }
     }
 }
 
+/*
+ * Path tracer with next event estimation.
+ *
+ * At every non specular bounce one light is sampled directly. Emission found by following the
+ * scattered ray is then also reachable by light sampling, so both are weighted by multiple
+ * importance sampling (power heuristic) to count it once.
+ *
+ * Rays passing the boundary of a medium carry on inside it, or outside again, without counting
+ * as a bounce. Camera rays start outside of every medium and media must not overlap.
+ */
 pub fn ray_color(
     options: &RayCastOptions,
     scene: &Scene,
     ray: &Ray,
-    ray_depth: usize,
+    sampler: &mut dyn Sampler,
 ) -> Color {
-    if ray_depth > options.max_depth {
-        return Color::from_rgb(0.0, 0.0, 0.0);
+    let mut radiance = Color::from_rgb(0.0, 0.0, 0.0);
+    let mut throughput = Color::from_rgb(1.0, 1.0, 1.0);
+    let mut ray = ray.clone();
+
+    // bsdf density of the previous bounce, `None` for camera rays and specular bounces
+    let mut previous_pdf: Option<f32> = None;
+
+    // medium the ray travels through, and how far along it the boundaries already crossed are
+    let mut medium: Option<ObjectId> = None;
+    let mut near = 0.001;
+
+    let mut bounce = 0;
+
+    // every vertex of the path, bounces and medium crossings, takes dimensions of its own
+    for vertex in 0.. {
+        sampler.set_dimension(BOUNCE_DIMENSION + vertex * DIMENSIONS_PER_BOUNCE);
+
+        let surface_hit = scene.nearest_hit(&ray, near, f32::INFINITY);
+
+        // scattering inside the medium comes first if it happens before the ray gets anywhere.
+        // A ray inside a closed medium always meets its boundary, one that doesn't has slipped
+        // out through a grazing crossing.
+        let medium_hit = match (medium, &surface_hit) {
+            (Some(object), Some(hit)) => {
+                sample_medium(scene, object, &ray, near, hit.shape_hit.t, sampler)
+            }
+            _ => None,
+        };
+
+        let hit = match (medium_hit, surface_hit) {
+            (Some(hit), _) => hit,
+            (None, Some(hit)) if scene.get_shape(hit.object).medium().is_some() => {
+                medium = crossed_medium(medium, &hit);
+                near = past(hit.shape_hit.t);
+                continue;
+            }
+            (None, Some(hit)) => hit,
+            (None, None) => {
+                radiance = radiance + throughput * background_color(scene.background(), &ray);
+                break;
+            }
+        };
+
+        let mat = scene.get_material(hit.object);
+
+        let emitted = mat.emitted(&ray, &hit.shape_hit);
+        if !emitted.is_black() {
+            let weight = match previous_pdf {
+                Some(bsdf_pdf) => {
+                    let light_pdf =
+                        scene.light_pdf(hit.object, *ray.origin(), *ray.direction(), ray.time());
+                    power_heuristic(bsdf_pdf, light_pdf)
+                }
+                None => 1.0,
+            };
+            radiance = radiance + weight * (throughput * emitted);
+        }
+
+        let scatter = match mat.scatter(&ray, &hit.shape_hit, sampler) {
+            Some(scatter) => scatter,
+            None => break,
+        };
+
+        if scatter.is_specular {
+            previous_pdf = None;
+        } else {
+            radiance = radiance + throughput * sample_light(scene, &ray, &hit, medium, sampler);
+            previous_pdf = Some(mat.pdf(&ray, &hit.shape_hit, *scatter.ray.direction()));
+        }
+
+        throughput = throughput * scatter.attenuation;
+        ray = scatter.ray;
+        near = scatter_offset(scene, &hit);
+
+        bounce += 1;
+        if bounce > options.max_depth {
+            break;
+        }
     }
 
-    let ohit = scene.nearest_hit(ray, 0.001, 100.0);
+    radiance
+}
+
+/*
+ * Where rays leaving `hit` start looking for the next hit. Off a surface they skip a little so
+ * that they don't hit it again, scattering in a medium isn't on any surface and a boundary could
+ * be closer than that.
+ */
+fn scatter_offset(
+    scene: &Scene,
+    hit: &Hit,
+) -> f32 {
+    if scene.get_shape(hit.object).medium().is_some() {
+        0.0
+    } else {
+        0.001
+    }
+}
+
+/*
+ * Scattering in the medium of `object` along `ray`, as a hit facing back along the ray
+ */
+fn sample_medium(
+    scene: &Scene,
+    object: ObjectId,
+    ray: &Ray,
+    near: f32,
+    far: f32,
+    sampler: &mut dyn Sampler,
+) -> Option<Hit> {
+    let medium = scene.get_shape(object).medium()?;
+    let t = medium.sample_scattering(ray, near, far, sampler)?;
+
+    Some(Hit {
+        object,
+        shape_hit: ShapeHit {
+            point: ray.at(t),
+            normal: -*ray.direction(),
+            t,
+            is_front_face: true,
+            u: 0.0,
+            v: 0.0,
+        },
+    })
+}
 
-    match ohit {
-        Some(hit) => {
-            let mat = scene.get_material(hit.object);
+/*
+ * First surface a camera ray meets, for aovs. Media boundaries are looked through.
+ */
+pub fn first_surface(
+    scene: &Scene,
+    ray: &Ray,
+) -> Option<Hit> {
+    let mut near = 0.001;
+
+    loop {
+        let hit = scene.nearest_hit(ray, near, f32::INFINITY)?;
+        if scene.get_shape(hit.object).medium().is_none() {
+            return Some(hit);
+        }
+        near = past(hit.shape_hit.t);
+    }
+}
 
-            if let Some(scatter) = mat.scatter(ray, &hit.shape_hit) {
-                let in_color = ray_color(options, scene, &scatter.ray, ray_depth + 1);
-                Color::from_rgb(
-                    scatter.attenuation.r() * in_color.r(),
-                    scatter.attenuation.g() * in_color.g(),
-                    scatter.attenuation.b() * in_color.b(),
-                )
-            } else {
-                Color::from_rgb(0.0, 0.0, 0.0)
+// distance just past `t` along a ray, where f32 gets coarse too
+fn past(t: f32) -> f32 {
+    t + f32::max(0.001, 1e-5 * t)
+}
+
+// medium after passing the boundary of a medium at `hit`
+fn crossed_medium(
+    medium: Option<ObjectId>,
+    hit: &Hit,
+) -> Option<ObjectId> {
+    if hit.shape_hit.is_front_face {
+        Some(hit.object)
+    } else if medium == Some(hit.object) {
+        None
+    } else {
+        medium
+    }
+}
+
+/*
+ * Fraction of light getting from `near` to `distance` along `ray` through the media on the way,
+ * starting in `medium`. `None` if a surface is in the way.
+ */
+fn transmittance(
+    scene: &Scene,
+    ray: &Ray,
+    near: f32,
+    distance: f32,
+    medium: Option<ObjectId>,
+    sampler: &mut dyn Sampler,
+) -> Option<f32> {
+    let mut medium = medium;
+    let mut near = near;
+    let mut transmittance = 1.0;
+
+    loop {
+        let hit = scene.nearest_hit(ray, near, distance - 0.001);
+
+        if let Some(inside) = medium.and_then(|object| scene.get_shape(object).medium()) {
+            let far = hit.as_ref().map_or(distance, |hit| hit.shape_hit.t);
+            transmittance *= inside.transmittance(ray, near, far, sampler);
+        }
+
+        match hit {
+            Some(hit) if scene.get_shape(hit.object).medium().is_some() => {
+                medium = crossed_medium(medium, &hit);
+                near = past(hit.shape_hit.t);
             }
+            Some(_) => return None,
+            None => return Some(transmittance),
         }
-        None => background_color(ray),
     }
 }
 
-fn background_color(ray: &Ray) -> Color {
-    let t = 0.5 * (ray.direction().y + 1.0);
-    let cs = Vec3::lerp(t, Vec3::ONE, Vec3::new(0.5, 0.7, 1.0));
-    cs.into()
+/*
+ * Direct lighting at `hit` from one uniformly chosen light, weighted against bsdf sampling
+ */
+fn sample_light(
+    scene: &Scene,
+    ray_in: &Ray,
+    hit: &Hit,
+    medium: Option<ObjectId>,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    let black = Color::from_rgb(0.0, 0.0, 0.0);
+
+    let lights = scene.lights();
+    if lights.is_empty() {
+        return black;
+    }
+
+    let light = lights[sample_index(sampler.get_1d(), lights.len())];
+
+    let origin = hit.shape_hit.point;
+    let time = ray_in.time();
+    let sample = match scene.get_shape(light).sample(origin, time, sampler) {
+        Some(sample) => sample,
+        None => return black,
+    };
+
+    let to_light = sample.hit.point - origin;
+    let distance = to_light.norm();
+    let shadow_ray = Ray::with_time(origin, to_light, time);
+
+    let mat = scene.get_material(hit.object);
+
+    let bsdf = mat.eval(ray_in, &hit.shape_hit, *shadow_ray.direction());
+    if bsdf.is_black() {
+        return black;
+    }
+
+    let emitted = scene.get_material(light).emitted(&shadow_ray, &sample.hit);
+    if emitted.is_black() {
+        return black;
+    }
+
+    let near = scatter_offset(scene, hit);
+    let transmittance = match transmittance(scene, &shadow_ray, near, distance, medium, sampler) {
+        Some(transmittance) => transmittance,
+        None => return black,
+    };
+
+    let light_pdf = sample.pdf / lights.len() as f32;
+    let bsdf_pdf = mat.pdf(ray_in, &hit.shape_hit, *shadow_ray.direction());
+    let weight = power_heuristic(light_pdf, bsdf_pdf);
+
+    (weight * transmittance / light_pdf) * (bsdf * emitted)
+}
+
+fn power_heuristic(
+    pdf: f32,
+    other_pdf: f32,
+) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+fn background_color(
+    background: &Background,
+    ray: &Ray,
+) -> Color {
+    match background {
+        Background::Sky => {
+            let t = 0.5 * (ray.direction().y + 1.0);
+            let cs = Vec3::lerp(t, Vec3::ONE, Vec3::new(0.5, 0.7, 1.0));
+            cs.into()
+        }
+        Background::Solid(color) => *color,
+    }
 }
@@ -0,0 +1,454 @@
+use crate::cgmath::*;
+use crate::raytracer::camera::*;
+use crate::raytracer::color::*;
+use crate::raytracer::framebuffer::*;
+use crate::raytracer::raytrace::*;
+use crate::raytracer::sampler::*;
+use crate::raytracer::scene::*;
+
+use anyhow::bail;
+use rayon::prelude::*;
+
+use std::time::{Duration, Instant};
+
+//
+//
+//
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    pub image_width: usize,
+    pub image_height: usize,
+    pub sample_count: usize,
+    pub max_depth: usize,
+    pub chunk_size: usize,
+    // same seed and settings give the same image, bit for bit
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    // also render albedo, normal and depth aovs, costs one more ray per sample
+    pub aovs: bool,
+}
+
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> f32 {
+        self.image_width as f32 / self.image_height as f32
+    }
+
+    /*
+     * Fails for settings that can't be rendered. The camera maps pixels to [0, 1] by dividing
+     * with size - 1, so images are at least 2x2.
+     */
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.image_width < 2 || self.image_height < 2 {
+            bail!(
+                "image must be at least 2x2 pixels, got {}x{}",
+                self.image_width,
+                self.image_height
+            );
+        }
+        if self.sample_count == 0 {
+            bail!("sample count must be positive");
+        }
+        if self.chunk_size == 0 {
+            bail!("chunk size must be positive");
+        }
+        Ok(())
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        let xs = self.image_width.div_ceil(self.chunk_size);
+        let ys = self.image_height.div_ceil(self.chunk_size);
+        xs * ys
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            image_width: 400,
+            image_height: 400,
+            sample_count: 10,
+            max_depth: 10,
+            chunk_size: 16,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            aovs: false,
+        }
+    }
+}
+
+/*
+ * Aovs of the first surfaces camera rays of a pixel see. Albedo and normal are averaged over
+ * samples, samples that miss count as zero. Depth is the distance to the nearest surface,
+ * infinite if every sample misses.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct PixelAovs {
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub depth: f32,
+}
+
+impl PixelAovs {
+    // framebuffer aov names, in the order of `values`
+    pub const NAMES: [&'static str; 7] =
+        ["albedo.R", "albedo.G", "albedo.B", "N.X", "N.Y", "N.Z", "Z"];
+
+    pub fn values(&self) -> [f32; 7] {
+        [
+            self.albedo.r(),
+            self.albedo.g(),
+            self.albedo.b(),
+            self.normal.x,
+            self.normal.y,
+            self.normal.z,
+            self.depth,
+        ]
+    }
+}
+
+/*
+ * Rectangle of rendered pixels, clipped to the image. `x` and `y` are pixel coordinates of its
+ * top left corner.
+ */
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub duration: Duration,
+    // linear, unclamped, row major
+    pub pixels: Vec<Color>,
+    // same layout as `pixels`, only with `RenderSettings::aovs`
+    pub aovs: Option<Vec<PixelAovs>>,
+}
+
+impl Chunk {
+    pub fn copy_to(
+        &self,
+        framebuffer: &mut Framebuffer,
+    ) {
+        for j in 0..self.height {
+            for i in 0..self.width {
+                framebuffer.set(self.x + i, self.y + j, self.pixels[j * self.width + i]);
+            }
+        }
+
+        if let Some(aovs) = &self.aovs {
+            let channels = PixelAovs::NAMES.map(|name| {
+                framebuffer
+                    .aov_index(name)
+                    .unwrap_or_else(|| framebuffer.add_aov(name))
+            });
+
+            for j in 0..self.height {
+                for i in 0..self.width {
+                    let values = aovs[j * self.width + i].values();
+                    for (&channel, value) in channels.iter().zip(values) {
+                        framebuffer.set_aov(channel, self.x + i, self.y + j, value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+//
+//
+//
+
+/*
+ * Renders the image chunk by chunk on the current rayon thread pool, `on_chunk` is called from
+ * worker threads as soon as a chunk is done, in no particular order.
+ *
+ * Panics if `settings` don't pass `RenderSettings::validate`.
+ */
+pub fn render_chunks<F>(
+    scene: &Scene,
+    camera: &CameraDescription,
+    settings: &RenderSettings,
+    on_chunk: F,
+) where
+    F: Fn(Chunk) + Sync,
+{
+    assert_valid(settings);
+
+    let camera = &camera.build(settings.aspect_ratio());
+
+    let chunk_size = settings.chunk_size;
+
+    //
+    let chunks = {
+        let x_chunks = settings.image_width.div_ceil(chunk_size);
+        let y_chunks = settings.image_height.div_ceil(chunk_size);
+
+        let mut chunks = Vec::with_capacity(x_chunks * y_chunks);
+
+        for y in 0..y_chunks {
+            for x in 0..x_chunks {
+                chunks.push((x * chunk_size, y * chunk_size));
+            }
+        }
+
+        chunks
+    };
+
+    chunks.into_par_iter().for_each(|(x, y)| {
+        let width = chunk_size.min(settings.image_width - x);
+        let height = chunk_size.min(settings.image_height - y);
+
+        let begin = Instant::now();
+
+        // samples only depend on pixel and sample index, so the image doesn't depend on which
+        // thread rendered which chunk or on chunk size
+        let sampler = &mut *settings
+            .sampler
+            .create(settings.sample_count, settings.seed);
+
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut aovs = Vec::new();
+
+        for ty in y..y + height {
+            for tx in x..x + width {
+                let (color, pixel_aovs) = render_pixel(scene, camera, settings, sampler, tx, ty);
+                pixels.push(color);
+                aovs.extend(pixel_aovs);
+            }
+        }
+
+        let end = Instant::now();
+
+        on_chunk(Chunk {
+            x,
+            y,
+            width,
+            height,
+            duration: end - begin,
+            pixels,
+            aovs: settings.aovs.then_some(aovs),
+        });
+    });
+}
+
+/*
+ * Renders the whole image into a framebuffer, `on_progress` gets number of finished and total
+ * chunks.
+ *
+ * Panics if `settings` don't pass `RenderSettings::validate`.
+ */
+pub fn render<F>(
+    scene: &Scene,
+    camera: &CameraDescription,
+    settings: &RenderSettings,
+    on_progress: F,
+) -> Framebuffer
+where
+    F: Fn(usize, usize) + Sync,
+{
+    use std::sync::Mutex;
+
+    assert_valid(settings);
+
+    let total = settings.chunk_count();
+
+    let state = Mutex::new((
+        Framebuffer::new(settings.image_width, settings.image_height),
+        0,
+    ));
+
+    render_chunks(scene, camera, settings, |chunk| {
+        let done = {
+            let mut state = state.lock().unwrap();
+            let (framebuffer, done) = &mut *state;
+            chunk.copy_to(framebuffer);
+            *done += 1;
+            *done
+        };
+
+        on_progress(done, total);
+    });
+
+    state.into_inner().unwrap().0
+}
+
+fn assert_valid(settings: &RenderSettings) {
+    if let Err(e) = settings.validate() {
+        panic!("invalid render settings: {}", e);
+    }
+}
+
+fn render_pixel(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+    x: usize,
+    y: usize,
+) -> (Color, Option<PixelAovs>) {
+    let image_width = settings.image_width;
+    let image_height = settings.image_height;
+
+    let u = x as f32 / (image_width - 1) as f32;
+    let v = 1.0 - (y as f32 / (image_height - 1) as f32);
+
+    let ray_cast_options = RayCastOptions {
+        max_depth: settings.max_depth,
+    };
+
+    let mut sum = Color::from_rgb(0.0, 0.0, 0.0);
+    let mut aovs = settings.aovs.then_some(PixelAovs {
+        albedo: Color::from_rgb(0.0, 0.0, 0.0),
+        normal: Vec3::ZERO,
+        depth: f32::INFINITY,
+    });
+
+    for sample in 0..settings.sample_count {
+        sampler.start_sample(x, y, sample);
+
+        sampler.set_dimension(PIXEL_DIMENSION);
+        let (px, py) = sampler.get_2d();
+        let du = (px - 0.5) / image_width as f32;
+        let dv = (py - 0.5) / image_height as f32;
+
+        let ray = camera.ray_at(u + du, v + dv, sampler);
+
+        if let Some(aovs) = &mut aovs {
+            if let Some(hit) = first_surface(scene, &ray) {
+                let albedo = scene.get_material(hit.object).albedo(&hit.shape_hit);
+                aovs.albedo = aovs.albedo + albedo;
+                aovs.normal = aovs.normal + hit.shape_hit.normal;
+                aovs.depth = aovs.depth.min((hit.shape_hit.point - *ray.origin()).norm());
+            }
+        }
+
+        let sample = ray_color(&ray_cast_options, scene, &ray, sampler);
+
+        // single nan or infinite sample would ruin the whole pixel
+        if sample.is_finite() {
+            sum = sum + sample;
+        }
+    }
+
+    let sample_count = settings.sample_count as f32;
+
+    let aovs = aovs.map(|aovs| PixelAovs {
+        albedo: aovs.albedo / sample_count,
+        normal: aovs.normal / sample_count,
+        ..aovs
+    });
+
+    (sum / sample_count, aovs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracer::material::Lambertian;
+    use crate::raytracer::shape::Sphere;
+
+    // sphere right in front of the camera, it covers the middle but not the corners
+    fn sphere_scene() -> (Scene, CameraDescription) {
+        let mut scene = Scene::new();
+        let sphere = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, 0.0, -5.0),
+            radius: 1.0,
+        });
+        let material = scene.insert_material(Lambertian::new(Color::from_rgb(0.2, 0.4, 0.6)));
+        scene.insert_object(sphere, material);
+
+        let camera = CameraDescription {
+            eye: Vec3::ZERO,
+            target: -Vec3::Z,
+            aperture: 0.0,
+            ..CameraDescription::default()
+        };
+
+        (scene, camera)
+    }
+
+    #[test]
+    fn aovs_describe_first_surface() {
+        let (scene, camera) = sphere_scene();
+        let settings = RenderSettings {
+            image_width: 9,
+            image_height: 9,
+            sample_count: 16,
+            chunk_size: 4,
+            aovs: true,
+            ..RenderSettings::default()
+        };
+
+        let framebuffer = render(&scene, &camera, &settings, |_, _| {});
+
+        let aov = |name: &str, x: usize, y: usize| {
+            let aov = &framebuffer.aovs()[framebuffer.aov_index(name).unwrap()];
+            aov.values()[y * framebuffer.width() + x]
+        };
+
+        assert!((aov("albedo.R", 4, 4) - 0.2).abs() < 1e-6);
+        assert!((aov("albedo.G", 4, 4) - 0.4).abs() < 1e-6);
+        assert!((aov("albedo.B", 4, 4) - 0.6).abs() < 1e-6);
+        assert!(aov("N.X", 4, 4).abs() < 0.05 && aov("N.Y", 4, 4).abs() < 0.05);
+        assert!(aov("N.Z", 4, 4) > 0.99);
+        assert!((aov("Z", 4, 4) - 4.0).abs() < 0.01);
+
+        // every sample of a corner misses
+        assert_eq!(aov("albedo.G", 0, 0), 0.0);
+        assert_eq!(aov("N.Z", 0, 0), 0.0);
+        assert_eq!(aov("Z", 0, 0), f32::INFINITY);
+    }
+
+    #[test]
+    fn aovs_are_left_out_unless_asked_for() {
+        let (scene, camera) = sphere_scene();
+        let settings = RenderSettings {
+            image_width: 4,
+            image_height: 4,
+            sample_count: 1,
+            ..RenderSettings::default()
+        };
+
+        let framebuffer = render(&scene, &camera, &settings, |_, _| {});
+        assert!(framebuffer.aovs().is_empty());
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let valid = RenderSettings::default();
+        assert!(valid.validate().is_ok());
+
+        let invalid = [
+            RenderSettings {
+                image_width: 1,
+                ..valid
+            },
+            RenderSettings {
+                image_height: 1,
+                ..valid
+            },
+            RenderSettings {
+                sample_count: 0,
+                ..valid
+            },
+            RenderSettings {
+                chunk_size: 0,
+                ..valid
+            },
+        ];
+        for settings in &invalid {
+            assert!(settings.validate().is_err(), "{:?}", settings);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "chunk size must be positive")]
+    fn render_panics_on_zero_chunk_size() {
+        let (scene, camera) = sphere_scene();
+        let settings = RenderSettings {
+            chunk_size: 0,
+            ..RenderSettings::default()
+        };
+
+        render(&scene, &camera, &settings, |_, _| {});
+    }
+}
@@ -0,0 +1,796 @@
+use crate::cgmath::*;
+
+use rand::{Rng, SeedableRng};
+
+use rand_pcg::Pcg32;
+
+use serde::{Deserialize, Serialize};
+
+use std::{f32::consts::PI, sync::OnceLock};
+
+//
+//
+//
+
+// where each part of a path takes its sample dimensions from
+pub const PIXEL_DIMENSION: usize = 0;
+pub const LENS_DIMENSION: usize = 2;
+pub const TIME_DIMENSION: usize = 4;
+pub const BOUNCE_DIMENSION: usize = 5;
+pub const DIMENSIONS_PER_BOUNCE: usize = 8;
+
+// largest f32 below 1
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/*
+ * Source of sample values in [0, 1).
+ *
+ * Values for the same dimension of different samples of a pixel are well distributed together,
+ * so every decision along a path should always take the same dimension: dimensions for a bounce
+ * start at `BOUNCE_DIMENSION + bounce * DIMENSIONS_PER_BOUNCE` whatever the previous bounces
+ * consumed.
+ */
+pub trait Sampler {
+    // dimensions start from 0 for every sample
+    fn start_sample(
+        &mut self,
+        x: usize,
+        y: usize,
+        index: usize,
+    );
+
+    fn set_dimension(
+        &mut self,
+        dimension: usize,
+    );
+
+    fn get_1d(&mut self) -> f32;
+
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SamplerKind> {
+        SamplerKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == name)
+    }
+
+    // `sample_count` is the number of samples taken per pixel
+    pub fn create(
+        &self,
+        sample_count: usize,
+        seed: u64,
+    ) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(sample_count, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+//
+//
+//
+
+/*
+ * Uniform random numbers, every sample of every pixel has its own generator
+ */
+#[derive(Debug, Clone)]
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler {
+            seed,
+            rng: Pcg32::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(
+        &mut self,
+        x: usize,
+        y: usize,
+        index: usize,
+    ) {
+        self.rng = Pcg32::seed_from_u64(hash(self.seed, &[x as u64, y as u64, index as u64]));
+    }
+
+    fn set_dimension(
+        &mut self,
+        _dimension: usize,
+    ) {
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng.gen()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.rng.gen(), self.rng.gen())
+    }
+}
+
+//
+//
+//
+
+/*
+ * Jittered strata, one per sample in 1d and a grid of `sample_count` cells in 2d. Samples are
+ * assigned to strata by a random permutation per pixel and dimension, so dimensions are not
+ * correlated with each other.
+ */
+#[derive(Debug, Clone)]
+pub struct StratifiedSampler {
+    sample_count: usize,
+    // 2d grid, `columns * rows == sample_count`
+    columns: usize,
+    rows: usize,
+    seed: u64,
+    pixel_seed: u64,
+    index: usize,
+    dimension: usize,
+}
+
+impl StratifiedSampler {
+    pub fn new(
+        sample_count: usize,
+        seed: u64,
+    ) -> StratifiedSampler {
+        let sample_count = sample_count.max(1);
+
+        // most square factorization
+        let columns = (1..=(sample_count as f64).sqrt() as usize)
+            .rev()
+            .find(|&columns| sample_count % columns == 0)
+            .unwrap_or(1);
+
+        StratifiedSampler {
+            sample_count,
+            columns,
+            rows: sample_count / columns,
+            seed,
+            pixel_seed: seed,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    // random stratum for the current sample, and hash for jittering inside it
+    fn stratum(&mut self) -> (usize, u64) {
+        let dimension_seed = hash(self.pixel_seed, &[self.dimension as u64]);
+        self.dimension += 1;
+
+        let stratum = permute(
+            (self.index % self.sample_count) as u32,
+            self.sample_count as u32,
+            dimension_seed as u32,
+        );
+
+        (stratum as usize, hash(dimension_seed, &[self.index as u64]))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(
+        &mut self,
+        x: usize,
+        y: usize,
+        index: usize,
+    ) {
+        self.pixel_seed = hash(self.seed, &[x as u64, y as u64]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(
+        &mut self,
+        dimension: usize,
+    ) {
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (stratum, jitter) = self.stratum();
+
+        ((stratum as f32 + to_unit_float(jitter)) / self.sample_count as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (stratum, jitter) = self.stratum();
+        self.dimension += 1;
+
+        let column = stratum % self.columns;
+        let row = stratum / self.columns;
+
+        let x = (column as f32 + to_unit_float(jitter)) / self.columns as f32;
+        let y = (row as f32 + to_unit_float(hash(jitter, &[1]))) / self.rows as f32;
+
+        (x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON))
+    }
+}
+
+//
+//
+//
+
+// dimensions past this use random numbers, halton in high prime bases is not better than that
+const HALTON_MAX_DIMENSIONS: usize = 1024;
+
+/*
+ * Halton sequence over the samples of a pixel, with random digit permutations per pixel and
+ * dimension
+ */
+#[derive(Debug, Clone)]
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: usize,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            pixel_seed: seed,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(
+        &mut self,
+        x: usize,
+        y: usize,
+        index: usize,
+    ) {
+        self.pixel_seed = hash(self.seed, &[x as u64, y as u64]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(
+        &mut self,
+        dimension: usize,
+    ) {
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        let dimension_seed = hash(self.pixel_seed, &[dimension as u64]);
+
+        match primes().get(dimension) {
+            Some(&base) => scrambled_radical_inverse(self.index as u64, base, dimension_seed),
+            None => to_unit_float(hash(dimension_seed, &[self.index as u64])),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+fn primes() -> &'static [u32] {
+    static PRIMES: OnceLock<Vec<u32>> = OnceLock::new();
+
+    PRIMES.get_or_init(|| {
+        let mut primes: Vec<u32> = Vec::with_capacity(HALTON_MAX_DIMENSIONS);
+        let mut candidate = 2;
+        while primes.len() < HALTON_MAX_DIMENSIONS {
+            if primes
+                .iter()
+                .take_while(|&&p| p * p <= candidate)
+                .all(|&p| candidate % p != 0)
+            {
+                primes.push(candidate);
+            }
+            candidate += 1;
+        }
+        primes
+    })
+}
+
+/*
+ * Mirrors digits of `index` in `base` around the radix point, every digit position goes through
+ * its own random permutation. Positions past the last digit of `index` are permuted zeros and
+ * still contribute, so it keeps going until they fall below f32 precision.
+ */
+fn scrambled_radical_inverse(
+    index: u64,
+    base: u32,
+    seed: u64,
+) -> f32 {
+    let inv_base = 1.0 / base as f64;
+
+    let mut index = index;
+    let mut weight = 1.0;
+    let mut result = 0.0;
+    let mut position = 0;
+
+    while weight > 1e-9 {
+        let digit = (index % base as u64) as u32;
+        index /= base as u64;
+        weight *= inv_base;
+
+        let digit_seed = hash(seed, &[position]) as u32;
+        result += permute(digit, base, digit_seed) as f64 * weight;
+
+        position += 1;
+    }
+
+    (result as f32).min(ONE_MINUS_EPSILON)
+}
+
+//
+//
+//
+
+/*
+ * Owen scrambled Sobol points, following Burley, "Practical Hash-based Owen Scrambling" (2020).
+ *
+ * Every pair of dimensions is the first two Sobol dimensions with its own index shuffle and
+ * scramble, so each pair is well stratified and pairs are independent of each other.
+ */
+#[derive(Debug, Clone)]
+pub struct SobolSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dimension: usize,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            seed,
+            pixel_seed: seed,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn dimension_seed(&mut self) -> u64 {
+        let seed = hash(self.pixel_seed, &[self.dimension as u64]);
+        self.dimension += 1;
+        seed
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(
+        &mut self,
+        x: usize,
+        y: usize,
+        index: usize,
+    ) {
+        self.pixel_seed = hash(self.seed, &[x as u64, y as u64]);
+        self.index = index as u32;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(
+        &mut self,
+        dimension: usize,
+    ) {
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let seed = self.dimension_seed();
+
+        let index = nested_uniform_scramble(self.index, seed as u32);
+        let x = nested_uniform_scramble(index.reverse_bits(), (seed >> 32) as u32);
+
+        to_unit_float_u32(x)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let seed = self.dimension_seed();
+        self.dimension += 1;
+
+        let index = nested_uniform_scramble(self.index, seed as u32);
+
+        let x = nested_uniform_scramble(index.reverse_bits(), (seed >> 32) as u32);
+        let y = nested_uniform_scramble(sobol_second_dimension(index), hash(seed, &[1]) as u32);
+
+        (to_unit_float_u32(x), to_unit_float_u32(y))
+    }
+}
+
+/*
+ * Direction numbers of the second dimension follow `v[k] = v[k - 1] ^ (v[k - 1] >> 1)`
+ */
+fn sobol_second_dimension(index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1 << 31;
+    let mut index = index;
+
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+
+    result
+}
+
+fn laine_karras_permutation(
+    x: u32,
+    seed: u32,
+) -> u32 {
+    let mut x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+// owen scramble of the bits of `x` read as a fraction
+fn nested_uniform_scramble(
+    x: u32,
+    seed: u32,
+) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+//
+//
+//
+
+/*
+ * Mixes `values` into `seed`, splitmix64 finalizer for each step
+ */
+pub fn hash(
+    seed: u64,
+    values: &[u64],
+) -> u64 {
+    values
+        .iter()
+        .fold(seed, |hash, &value| mix(hash ^ mix(value)))
+}
+
+fn mix(value: u64) -> u64 {
+    let value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+fn to_unit_float(hash: u64) -> f32 {
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
+
+fn to_unit_float_u32(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1u32 << 24) as f32
+}
+
+/*
+ * Element `i` of a random permutation of `0..length` chosen by `seed`, without building it.
+ * Kensler, "Correlated Multi-Jittered Sampling" (2013).
+ */
+fn permute(
+    i: u32,
+    length: u32,
+    seed: u32,
+) -> u32 {
+    if length <= 1 {
+        return 0;
+    }
+
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    let p = seed;
+    let mut i = i;
+
+    // cycle walking, repeats until the value lands inside the range
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= mask;
+        i ^= i >> 5;
+
+        if i < length {
+            break;
+        }
+    }
+
+    // without wrapping, `(i + p) % length` would stop being a rotation once the sum overflowed
+    ((i as u64 + p as u64) % length as u64) as u32
+}
+
+//
+//
+//
+
+/*
+ * Concentric mapping of the square to the unit disk in the xy plane, keeps strata compact
+ */
+pub fn sample_unit_disk(u: (f32, f32)) -> Vec3 {
+    let (x, y) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+
+    if x == 0.0 && y == 0.0 {
+        return Vec3::ZERO;
+    }
+
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, (PI / 4.0) * (y / x))
+    } else {
+        (y, PI / 2.0 - (PI / 4.0) * (x / y))
+    };
+
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+// uniformly distributed direction
+pub fn sample_unit_sphere(u: (f32, f32)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// uniformly distributed point inside the unit ball, `u` picks the direction and `v` the distance
+// from the center
+pub fn sample_unit_ball(
+    u: (f32, f32),
+    v: f32,
+) -> Vec3 {
+    v.cbrt() * sample_unit_sphere(u)
+}
+
+/*
+ * Index of one of `count` items, `u` is reused when the caller needs another value
+ */
+pub fn sample_index(
+    u: f32,
+    count: usize,
+) -> usize {
+    ((u * count as f32) as usize).min(count - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+
+    // `count` samples of one pixel, `take` draws from the sampler after `start_sample`
+    fn pixel_samples<T>(
+        sampler: &mut dyn Sampler,
+        count: usize,
+        mut take: impl FnMut(&mut dyn Sampler) -> T,
+    ) -> Vec<T> {
+        (0..count)
+            .map(|index| {
+                sampler.start_sample(3, 5, index);
+                take(sampler)
+            })
+            .collect()
+    }
+
+    fn assert_one_per_stratum_1d(
+        values: &[f32],
+        name: &str,
+    ) {
+        let strata: HashSet<_> = values
+            .iter()
+            .map(|&value| (value * values.len() as f32) as usize)
+            .collect();
+
+        assert_eq!(strata.len(), values.len(), "{}: {:?}", name, values);
+    }
+
+    fn assert_one_per_stratum_2d(
+        points: &[(f32, f32)],
+        columns: usize,
+        rows: usize,
+        name: &str,
+    ) {
+        assert_eq!(points.len(), columns * rows);
+
+        let cells: HashSet<_> = points
+            .iter()
+            .map(|&(x, y)| ((x * columns as f32) as usize, (y * rows as f32) as usize))
+            .collect();
+
+        assert_eq!(
+            cells.len(),
+            points.len(),
+            "{} on a {}x{} grid: {:?}",
+            name,
+            columns,
+            rows,
+            points
+        );
+    }
+
+    #[test]
+    fn values_are_in_unit_interval() {
+        for kind in SamplerKind::ALL {
+            let mut sampler = kind.create(16, 1);
+
+            for index in 0..16 {
+                sampler.start_sample(7, 2, index);
+
+                // past the halton primes too
+                for dimension in [
+                    0,
+                    5,
+                    37,
+                    HALTON_MAX_DIMENSIONS - 2,
+                    HALTON_MAX_DIMENSIONS + 3,
+                ] {
+                    sampler.set_dimension(dimension);
+
+                    let (x, y) = sampler.get_2d();
+                    for value in [sampler.get_1d(), x, y] {
+                        assert!((0.0..1.0).contains(&value), "{}: {}", kind.name(), value);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_samplers_cover_1d_strata() {
+        for count in [1, 2, 4, 8, 16, 64, 256] {
+            for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+                let mut sampler = kind.create(count, 9);
+
+                for dimension in 0..6 {
+                    let values = pixel_samples(&mut *sampler, count, |sampler| {
+                        sampler.set_dimension(dimension);
+                        sampler.get_1d()
+                    });
+                    assert_one_per_stratum_1d(&values, kind.name());
+                }
+            }
+
+            // only the first halton dimension has base 2
+            let mut sampler = SamplerKind::Halton.create(count, 9);
+            let values = pixel_samples(&mut *sampler, count, |sampler| sampler.get_1d());
+            assert_one_per_stratum_1d(&values, "halton");
+        }
+    }
+
+    #[test]
+    fn stratified_samplers_cover_2d_strata() {
+        for log_count in 0..=8 {
+            let count = 1 << log_count;
+
+            // every elementary interval of the (0, 2) sequence
+            let mut sobol = SobolSampler::new(9);
+            for dimension in [0, 2, 6] {
+                let points = pixel_samples(&mut sobol, count, |sampler| {
+                    sampler.set_dimension(dimension);
+                    sampler.get_2d()
+                });
+                for log_columns in 0..=log_count {
+                    let (columns, rows) = (1 << log_columns, 1 << (log_count - log_columns));
+                    assert_one_per_stratum_2d(&points, columns, rows, "sobol");
+                }
+            }
+
+            let mut stratified = StratifiedSampler::new(count, 9);
+            let (columns, rows) = (stratified.columns, stratified.rows);
+            for dimension in [0, 2, 6] {
+                let points = pixel_samples(&mut stratified, count, |sampler| {
+                    sampler.set_dimension(dimension);
+                    sampler.get_2d()
+                });
+                assert_one_per_stratum_2d(&points, columns, rows, "stratified");
+            }
+        }
+
+        // the first halton pair has bases 2 and 3, so powers of two stratify only along x
+        for (columns, rows) in [(1, 1), (2, 3), (4, 3), (4, 9), (16, 27)] {
+            let mut halton = HaltonSampler::new(9);
+            let points = pixel_samples(&mut halton, columns * rows, |sampler| sampler.get_2d());
+            assert_one_per_stratum_2d(&points, columns, rows, "halton");
+        }
+    }
+
+    #[test]
+    fn samples_depend_only_on_pixel_index_and_seed() {
+        let draw = |sampler: &mut dyn Sampler, x, y, index| {
+            sampler.start_sample(x, y, index);
+            let (u, v) = sampler.get_2d();
+            sampler.set_dimension(BOUNCE_DIMENSION + 3 * DIMENSIONS_PER_BOUNCE);
+            [u, v, sampler.get_1d(), sampler.get_1d()]
+        };
+
+        for kind in SamplerKind::ALL {
+            let mut first = kind.create(16, 4);
+            let mut second = kind.create(16, 4);
+            let mut reseeded = kind.create(16, 5);
+
+            // other pixels and samples in between don't change the outcome
+            let expected = draw(&mut *first, 10, 20, 7);
+            draw(&mut *second, 11, 20, 3);
+            draw(&mut *second, 10, 20, 8);
+            assert_eq!(draw(&mut *second, 10, 20, 7), expected, "{}", kind.name());
+            assert_eq!(draw(&mut *first, 10, 20, 7), expected, "{}", kind.name());
+
+            assert_ne!(draw(&mut *reseeded, 10, 20, 7), expected, "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn permute_is_a_bijection() {
+        let lengths = (1..=70).chain([100, 255, 256, 257, 1000, 4099]);
+
+        for length in lengths {
+            for seed in [0, 1, 0x9e37_79b9, u32::MAX] {
+                let images: HashSet<_> = (0..length).map(|i| permute(i, length, seed)).collect();
+
+                assert_eq!(images.len(), length as usize, "length {}", length);
+                assert!(
+                    images.iter().all(|&image| image < length),
+                    "length {}",
+                    length
+                );
+            }
+        }
+    }
+}
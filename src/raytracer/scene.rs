@@ -1,17 +1,24 @@
+use crate::cgmath::*;
+use crate::raytracer::bvh::*;
+use crate::raytracer::camera::*;
+use crate::raytracer::color::*;
 use crate::raytracer::material::*;
 use crate::raytracer::ray::*;
 
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
 //
 //
 //
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ShapeId(usize);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct MaterialId(usize);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ObjectId(usize);
 
 //
@@ -24,21 +31,61 @@ struct Object {
     material: MaterialId,
 }
 
+/*
+ * What rays that miss everything see
+ */
+#[derive(Debug, Clone, Copy)]
+pub enum Background {
+    // white to light blue gradient, bottom to top
+    Sky,
+    Solid(Color),
+}
+
 #[derive(Debug, Clone)]
 pub struct Hit {
     pub object: ObjectId,
     pub shape_hit: ShapeHit,
 }
 
+impl BvhHit for Hit {
+    fn t(&self) -> f32 {
+        self.shape_hit.t
+    }
+}
+
+// below this many objects brute force is as fast as the bvh
+const LINEAR_SEARCH_MAX_OBJECTS: usize = 8;
+
+#[derive(Debug)]
+struct Accelerator {
+    bvh: Bvh,
+    // bvh primitive index to object
+    bounded: Vec<ObjectId>,
+    // objects without bounding box, e.g. infinite planes
+    unbounded: Vec<ObjectId>,
+}
+
 //
 //
 //
 
 #[derive(Debug)]
 pub struct Scene {
-    shapes: Vec<Box<dyn HittableShape>>,
+    // shared with instances of them
+    shapes: Vec<Arc<dyn HittableShape>>,
     materials: Vec<Box<dyn Material>>,
     objects: Vec<Object>,
+    // objects with emissive materials
+    lights: Vec<ObjectId>,
+    background: Background,
+    camera: CameraDescription,
+    accelerator: OnceLock<Accelerator>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene::new()
+    }
 }
 
 impl Scene {
@@ -47,9 +94,35 @@ impl Scene {
             shapes: Vec::new(),
             materials: Vec::new(),
             objects: Vec::new(),
+            lights: Vec::new(),
+            background: Background::Sky,
+            camera: CameraDescription::default(),
+            accelerator: OnceLock::new(),
         }
     }
 
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub fn set_background(
+        &mut self,
+        background: Background,
+    ) {
+        self.background = background;
+    }
+
+    pub fn camera(&self) -> &CameraDescription {
+        &self.camera
+    }
+
+    pub fn set_camera(
+        &mut self,
+        camera: CameraDescription,
+    ) {
+        self.camera = camera;
+    }
+
     pub fn insert_shape<S>(
         &mut self,
         shape: S,
@@ -57,10 +130,22 @@ impl Scene {
     where
         S: 'static + HittableShape,
     {
-        self.shapes.push(Box::new(shape));
+        self.shapes.push(Arc::new(shape));
         ShapeId(self.shapes.len() - 1)
     }
 
+    /*
+     * Handle to a shape for building other shapes out of it, see `Instance`
+     */
+    pub fn shared_shape(
+        &self,
+        shape: ShapeId,
+    ) -> Arc<dyn HittableShape> {
+        assert!(shape.0 < self.shapes.len());
+
+        self.shapes[shape.0].clone()
+    }
+
     pub fn insert_material<M>(
         &mut self,
         material: M,
@@ -81,8 +166,59 @@ impl Scene {
         assert!(material.0 < self.materials.len());
 
         self.objects.push(Object { shape, material });
+        self.accelerator = OnceLock::new();
+
+        let object = ObjectId(self.objects.len() - 1);
+
+        if self.materials[material.0].is_emissive() {
+            self.lights.push(object);
+        }
+
+        object
+    }
+
+    pub fn shapes(&self) -> impl Iterator<Item = (ShapeId, &dyn HittableShape)> {
+        self.shapes
+            .iter()
+            .enumerate()
+            .map(|(index, shape)| (ShapeId(index), shape.as_ref()))
+    }
 
-        ObjectId(self.shapes.len() - 1)
+    pub fn materials(&self) -> impl Iterator<Item = (MaterialId, &dyn Material)> {
+        self.materials
+            .iter()
+            .enumerate()
+            .map(|(index, material)| (MaterialId(index), material.as_ref()))
+    }
+
+    // every object with its shape and material
+    pub fn objects(&self) -> impl Iterator<Item = (ObjectId, ShapeId, MaterialId)> + '_ {
+        self.objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| (ObjectId(index), object.shape, object.material))
+    }
+
+    pub fn lights(&self) -> &[ObjectId] {
+        &self.lights
+    }
+
+    /*
+     * Density of picking `direction` from `origin` at `time` when sampling `object` as one of the
+     * lights
+     */
+    pub fn light_pdf(
+        &self,
+        object: ObjectId,
+        origin: Vec3,
+        direction: Vec3,
+        time: f32,
+    ) -> f32 {
+        if !self.get_material(object).is_emissive() {
+            return 0.0;
+        }
+
+        self.get_shape(object).pdf(origin, direction, time) / self.lights.len() as f32
     }
 
     pub fn get_shape(
@@ -113,40 +249,180 @@ impl Scene {
         near: f32,
         far: f32,
     ) -> Option<Hit> {
-        // horrible linear brute force algorithm
-        let inf_hit = Hit {
-            object: ObjectId(usize::MAX),
-            shape_hit: ShapeHit {
-                t: f32::INFINITY,
-                ..Default::default()
-            },
+        if self.objects.len() <= LINEAR_SEARCH_MAX_OBJECTS {
+            return self.nearest_hit_linear(ray, near, far);
+        }
+
+        let accelerator = self.accelerator.get_or_init(|| self.build_accelerator());
+
+        let hit_object = |object: ObjectId, near: f32, far: f32| {
+            self.get_shape(object)
+                .hit(ray, near, far)
+                .map(|shape_hit| Hit { object, shape_hit })
         };
 
-        fn choose_nearer(
-            a: Hit,
-            b: Hit,
-        ) -> Hit {
-            if a.shape_hit.t < b.shape_hit.t {
-                a
-            } else {
-                b
+        let mut far = far;
+        let mut nearest = None;
+
+        for &object in &accelerator.unbounded {
+            if let Some(hit) = hit_object(object, near, far) {
+                far = hit.shape_hit.t;
+                nearest = Some(hit);
             }
         }
 
-        let hit = (0..self.objects.len())
-            .filter_map(|object| {
-                let object = ObjectId(object);
-                let shape = self.get_shape(object);
-                shape
-                    .hit(ray, near, far)
-                    .map(|shape_hit| Hit { object, shape_hit })
+        accelerator
+            .bvh
+            .nearest_hit(ray, near, far, |index, near, far| {
+                hit_object(accelerator.bounded[index], near, far)
             })
-            .fold(inf_hit, choose_nearer);
+            .or(nearest)
+    }
+
+    fn nearest_hit_linear(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<Hit> {
+        let mut far = far;
+        let mut nearest = None;
+
+        for object in (0..self.objects.len()).map(ObjectId) {
+            if let Some(shape_hit) = self.get_shape(object).hit(ray, near, far) {
+                far = shape_hit.t;
+                nearest = Some(Hit { object, shape_hit });
+            }
+        }
+
+        nearest
+    }
+
+    fn build_accelerator(&self) -> Accelerator {
+        let begin = Instant::now();
+
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        let mut bounds = Vec::new();
 
-        if f32::is_infinite(hit.shape_hit.t) {
-            None
-        } else {
-            Some(hit)
+        for object in (0..self.objects.len()).map(ObjectId) {
+            match self.get_shape(object).bounding_box() {
+                Some(aabb) => {
+                    bounded.push(object);
+                    bounds.push(aabb);
+                }
+                None => unbounded.push(object),
+            }
+        }
+
+        let bvh = Bvh::build(&bounds);
+
+        log::info!(
+            "built bvh over {} objects ({} unbounded) in {:?}",
+            bounded.len(),
+            unbounded.len(),
+            Instant::now() - begin
+        );
+
+        Accelerator {
+            bvh,
+            bounded,
+            unbounded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracer::shape::{Cuboid, Plane, Sphere};
+
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg32;
+
+    const SCENE_COUNT: usize = 20;
+    const RAYS_PER_SCENE: usize = 2_000;
+
+    fn random_point(
+        rng: &mut Pcg32,
+        extent: f32,
+    ) -> Vec3 {
+        Vec3::new(
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+        )
+    }
+
+    fn random_scene(
+        rng: &mut Pcg32,
+        object_count: usize,
+    ) -> Scene {
+        let mut scene = Scene::new();
+        let material = scene.insert_material(Lambertian::new(Color::from_rgb(0.5, 0.5, 0.5)));
+
+        for _ in 0..object_count {
+            let center = random_point(rng, 10.0);
+            let size = rng.gen_range(0.05..2.0);
+
+            let shape = if rng.gen_bool(0.5) {
+                scene.insert_shape(Sphere {
+                    center,
+                    radius: size,
+                })
+            } else {
+                let half = Vec3::new(size, rng.gen_range(0.05..2.0), rng.gen_range(0.05..2.0));
+                scene.insert_shape(Cuboid::new(center - half, center + half))
+            };
+            scene.insert_object(shape, material);
+        }
+
+        // unbounded objects are tested outside the bvh
+        if rng.gen_bool(0.5) {
+            let plane =
+                scene.insert_shape(Plane::new(random_point(rng, 10.0), random_point(rng, 1.0)));
+            scene.insert_object(plane, material);
+        }
+
+        scene
+    }
+
+    #[test]
+    fn bvh_nearest_hit_matches_linear_search() {
+        let mut rng = Pcg32::seed_from_u64(7);
+
+        for _ in 0..SCENE_COUNT {
+            let object_count = rng.gen_range(LINEAR_SEARCH_MAX_OBJECTS + 1..300);
+            let scene = random_scene(&mut rng, object_count);
+
+            for _ in 0..RAYS_PER_SCENE {
+                let origin = random_point(&mut rng, 15.0);
+                let direction = random_point(&mut rng, 1.0);
+                if direction.near_zero() {
+                    continue;
+                }
+                let ray = Ray::new(origin, direction);
+
+                let far = if rng.gen_bool(0.2) {
+                    rng.gen_range(1.0..20.0)
+                } else {
+                    f32::INFINITY
+                };
+
+                let expected = scene.nearest_hit_linear(&ray, 0.001, far);
+                let actual = scene.nearest_hit(&ray, 0.001, far);
+
+                match (expected, actual) {
+                    (None, None) => {}
+                    (Some(expected), Some(actual)) => {
+                        assert_eq!(expected.object, actual.object, "{:?}", ray);
+                        assert_eq!(expected.shape_hit.t, actual.shape_hit.t, "{:?}", ray);
+                    }
+                    (expected, actual) => {
+                        panic!("{:?}: linear {:?}, bvh {:?}", ray, expected, actual)
+                    }
+                }
+            }
         }
     }
 }
@@ -0,0 +1,1557 @@
+use crate::cgmath::*;
+use crate::raytracer::camera::*;
+use crate::raytracer::color::*;
+use crate::raytracer::csg::*;
+use crate::raytracer::description::*;
+use crate::raytracer::instance::*;
+use crate::raytracer::material::*;
+use crate::raytracer::medium::*;
+use crate::raytracer::mesh::*;
+use crate::raytracer::obj::*;
+use crate::raytracer::ray::*;
+use crate::raytracer::render::*;
+use crate::raytracer::sampler::SamplerKind;
+use crate::raytracer::scene::*;
+use crate::raytracer::shape::*;
+use crate::raytracer::texture::*;
+
+use anyhow::{anyhow, bail, Context};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use toml::Spanned;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/*
+ * Scene description file, TOML:
+ *
+ *   include = ["materials.toml"]        # loaded first, paths relative to this file
+ *
+ *   background = "sky"                  # or [r, g, b]
+ *
+ *   [camera]                            # every field optional
+ *   eye = [13, 2, 3]
+ *   target = [0, 0, 0]
+ *   up = [0, 1, 0]
+ *   vertical_fov = 20                   # degrees
+ *   aperture = 0.1
+ *   focus_distance = 10
+ *   shutter_open = 0                    # time interval of motion blur
+ *   shutter_close = 1
+ *
+ *   [render]                            # every field optional
+ *   width = 400
+ *   height = 400
+ *   samples = 10
+ *   max_depth = 10
+ *   chunk_size = 16
+ *   seed = 0
+ *   sampler = "sobol"                  # independent, stratified, halton, sobol
+ *
+ *   [textures.tiles]                    # named textures can be used by many materials
+ *   type = "checker"                    # constant, checker, image, noise
+ *   even = [0.2, 0.3, 0.1]
+ *   odd = { type = "noise", noise = "marble", scale = 4 }
+ *   size = 0.5
+ *   space = "solid"                     # or "uv"
+ *
+ *   [materials.glass]
+ *   type = "dielectric"                 # lambertian, metal, dielectric, diffuse_light,
+ *   refraction_index = 1.5              # isotropic, henyey_greenstein
+ *
+ *   [materials.floor]
+ *   type = "lambertian"
+ *   albedo = "tiles"                    # colours are [r, g, b], a texture name or table
+ *
+ *   [materials.haze]
+ *   type = "henyey_greenstein"          # phase function of media scattering mostly forwards,
+ *   albedo = [0.9, 0.9, 0.9]            # or backwards for negative g
+ *   g = 0.6
+ *
+ *   [shapes.ball]                       # named shapes can be used by many objects
+ *   type = "sphere"                     # sphere, moving_sphere, triangle, mesh, quad, box,
+ *   center = [0, 1, 0]                  # plane, disk, cylinder, cone, torus, instance,
+ *   radius = 1                          # constant_medium, grid_medium, noise_medium, csg
+ *
+ *   [shapes.wall]
+ *   type = "quad"                       # parallelogram, faces towards edge_u x edge_v
+ *   corner = [0, 0, 0]
+ *   edge_u = [2, 0, 0]
+ *   edge_v = [0, 2, 0]
+ *
+ *   [shapes.crate]
+ *   type = "box"                        # axis aligned, rotate it with an instance
+ *   min = [0, 0, 0]
+ *   max = [1, 1, 1]
+ *
+ *   [shapes.floor]
+ *   type = "plane"                      # infinite, faces towards normal
+ *   point = [0, 0, 0]
+ *   normal = [0, 1, 0]
+ *
+ *   [shapes.washer]
+ *   type = "disk"                       # faces towards normal
+ *   center = [0, 0, 0]
+ *   normal = [0, 0, 1]
+ *   radius = 1
+ *   inner_radius = 0.5                  # hole of an annulus, 0 by default
+ *
+ *   [shapes.pillar]
+ *   type = "cylinder"                   # or "cone" with its apex at the top, both stand along y
+ *   base = [0, 0, 0]                    # center of the bottom, rotate them with an instance
+ *   radius = 0.5
+ *   height = 2
+ *   capped = false                      # closed by disks, true by default
+ *
+ *   [shapes.ring]
+ *   type = "torus"                      # around the y axis
+ *   center = [0, 1, 0]
+ *   major_radius = 1                    # of the circle through the tube
+ *   minor_radius = 0.25                 # of the tube
+ *
+ *   [shapes.big_ball]
+ *   type = "instance"                   # another shape, transformed without copying it
+ *   shape = "ball"                      # or an inline shape table
+ *   transform = [                       # steps applied in order
+ *       { scale = 2 },                  # or [x, y, z]
+ *       { rotate = { axis = [0, 1, 0], angle = 45 } },
+ *       { translate = [3, 0, 0] },
+ *   ]                                   # { matrix = [[...], [...], [...]] } is 3x4, row major
+ *
+ *   [shapes.dome]
+ *   type = "csg"                        # closed shapes combined, crossing the front face of one
+ *   operation = "difference"            # enters it; union, intersection, difference
+ *   left = "big_ball"                   # named or inline shapes, csg nests
+ *   right = { type = "box", min = [0, -3, -3], max = [6, 0, 3] }
+ *
+ *   [shapes.smoke]
+ *   type = "constant_medium"            # fog filling a closed shape, scatters by its material
+ *   boundary = "crate"                  # or an inline shape table
+ *   density = 0.5                       # per unit length, isotropic materials suit it
+ *
+ *   [shapes.plume]
+ *   type = "grid_medium"                # densities from a voxel grid over the boundary's bounds
+ *   boundary = "crate"
+ *   path = "plume.grid"                 # little endian u32 sizes x, y, z then f32 voxels
+ *   density = 2                         # multiplies the grid values
+ *
+ *   [shapes.cloud]
+ *   type = "noise_medium"               # densities from noise clamped to [0, 1]
+ *   boundary = "ball"
+ *   density = 4
+ *   noise = "turbulence"                # same as noise textures
+ *   scale = 2
+ *
+ *   [[objects]]
+ *   shape = "ball"                      # or an inline shape table
+ *   material = "glass"
+ *
+ *   [[models]]
+ *   path = "teapot.obj"                 # inserted with its own mtl materials
+ *
+ * Names are shared between a file and everything it includes, later definitions of camera,
+ * render settings and background override earlier ones.
+ */
+pub struct SceneFile {
+    pub scene: Scene,
+    pub settings: RenderSettings,
+}
+
+pub fn load_scene_file(path: &Path) -> anyhow::Result<SceneFile> {
+    let mut loader = Loader {
+        scene: Scene::new(),
+        settings: RenderSettings::default(),
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        shapes: HashMap::new(),
+        include_stack: Vec::new(),
+    };
+
+    loader.load(path)?;
+
+    Ok(SceneFile {
+        scene: loader.scene,
+        settings: loader.settings,
+    })
+}
+
+/*
+ * Writes `scene` so that loading the file gives the same scene, `camera` replaces the scene's
+ * own. Shapes used by several objects are written once under `[shapes]`, unused shapes and
+ * materials are left out.
+ */
+pub fn save_scene_file(
+    path: &Path,
+    scene: &Scene,
+    camera: &CameraDescription,
+    settings: Option<&RenderSettings>,
+) -> anyhow::Result<()> {
+    let text = scene_to_string(scene, camera, settings)?;
+
+    fs::write(path, text).with_context(|| format!("cannot write {}", path.display()))
+}
+
+pub fn scene_to_string(
+    scene: &Scene,
+    camera: &CameraDescription,
+    settings: Option<&RenderSettings>,
+) -> anyhow::Result<String> {
+    let mut file = ExportDesc {
+        background: match *scene.background() {
+            Background::Sky => BackgroundDesc::Sky,
+            Background::Solid(color) => BackgroundDesc::Solid(color.into()),
+        },
+        camera: CameraDesc {
+            eye: Some(camera.eye.into()),
+            target: Some(camera.target.into()),
+            up: Some(camera.up.into()),
+            vertical_fov: Some(camera.vertical_fov.0),
+            aperture: Some(camera.aperture),
+            focus_distance: Some(camera.focus_distance),
+            shutter_open: Some(camera.shutter_open),
+            shutter_close: Some(camera.shutter_close),
+        },
+        render: settings.map(|settings| RenderDesc {
+            width: Some(settings.image_width),
+            height: Some(settings.image_height),
+            samples: Some(settings.sample_count),
+            max_depth: Some(settings.max_depth),
+            chunk_size: Some(settings.chunk_size),
+            seed: Some(settings.seed as i64),
+            sampler: Some(settings.sampler),
+        }),
+        materials: BTreeMap::new(),
+        shapes: BTreeMap::new(),
+        objects: Vec::new(),
+    };
+
+    let shapes: HashMap<ShapeId, &dyn HittableShape> = scene.shapes().collect();
+    let materials: HashMap<MaterialId, &dyn Material> = scene.materials().collect();
+
+    let mut shape_uses = HashMap::new();
+    for (_, shape, _) in scene.objects() {
+        *shape_uses.entry(shape).or_insert(0) += 1;
+    }
+
+    // numbered in order of first use so that exporting a loaded file gives the same file
+    let mut material_names = HashMap::new();
+    let mut shape_names = HashMap::new();
+    for (_, shape, material) in scene.objects() {
+        let count = material_names.len();
+        material_names.entry(material).or_insert(count);
+
+        if shape_uses[&shape] > 1 {
+            let count = shape_names.len();
+            shape_names.entry(shape).or_insert(count);
+        }
+
+        // shapes of instances, media and csg are always named, through any number of levels
+        let mut pending = vec![shape];
+        while let Some(current) = pending.pop() {
+            let targets = shapes[&current]
+                .description()
+                .map_or_else(Vec::new, |description| inner_shape_ids(&description));
+
+            for target in targets {
+                if !shape_names.contains_key(&target) {
+                    shape_names.insert(target, shape_names.len());
+                    pending.push(target);
+                }
+            }
+        }
+    }
+
+    // zero padded so that names sort in order
+    let name = |prefix: &str, index: usize, count: usize| {
+        let width = count.to_string().len();
+        format!("{}_{:0width$}", prefix, index, width = width)
+    };
+
+    // instances, media and csg refer to their shapes by name
+    let describe_shape = |shape_id: ShapeId| -> anyhow::Result<ShapeDescription> {
+        let mut description = shapes[&shape_id]
+            .description()
+            .ok_or_else(|| anyhow!("shape {:?} cannot be written to a scene file", shape_id))?;
+
+        for shape in description.inner_shapes_mut() {
+            if let ShapeRef::Id(target) = *shape {
+                *shape = ShapeRef::Named(name("shape", shape_names[&target], shape_names.len()));
+            }
+        }
+
+        Ok(description)
+    };
+
+    for (object, shape_id, material_id) in scene.objects() {
+        let material_name = name(
+            "material",
+            material_names[&material_id],
+            material_names.len(),
+        );
+
+        if !file.materials.contains_key(&material_name) {
+            let description = materials[&material_id].description().ok_or_else(|| {
+                anyhow!(
+                    "material of object {:?} cannot be written to a scene file",
+                    object
+                )
+            })?;
+            file.materials.insert(material_name.clone(), description);
+        }
+
+        let describe = || {
+            describe_shape(shape_id).with_context(|| format!("cannot write object {:?}", object))
+        };
+
+        let shape = match shape_names.get(&shape_id) {
+            Some(&index) => ShapeRef::Named(name("shape", index, shape_names.len())),
+            None => ShapeRef::Inline(describe()?),
+        };
+
+        file.objects.push(ObjectDesc {
+            shape,
+            material: material_name,
+        });
+    }
+
+    for (&shape_id, &index) in &shape_names {
+        file.shapes.insert(
+            name("shape", index, shape_names.len()),
+            describe_shape(shape_id)?,
+        );
+    }
+
+    let mut value = toml::Value::try_from(&file).context("cannot serialize scene")?;
+    shorten_floats(&mut value);
+
+    toml::to_string(&value).context("cannot serialize scene")
+}
+
+fn inner_shape_ids(description: &ShapeDescription) -> Vec<ShapeId> {
+    description
+        .inner_shapes()
+        .into_iter()
+        .filter_map(|shape| match shape {
+            ShapeRef::Id(id) => Some(*id),
+            _ => None,
+        })
+        .collect()
+}
+
+/*
+ * Every float in the format is `f32`, written as `f64` it would get noise digits (0.8 becomes
+ * 0.800000011920929). Replaces each with the shortest decimal that reads back as the same `f32`.
+ */
+fn shorten_floats(value: &mut toml::Value) {
+    match value {
+        toml::Value::Float(float) => {
+            let single = *float as f32;
+            let short: f64 = single.to_string().parse().unwrap_or(*float);
+            *float = if short as f32 == single {
+                short
+            } else {
+                single as f64
+            };
+        }
+        toml::Value::Array(array) => array.iter_mut().for_each(shorten_floats),
+        toml::Value::Table(table) => table
+            .iter_mut()
+            .for_each(|(_, value)| shorten_floats(value)),
+        _ => {}
+    }
+}
+
+//
+//
+//
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileDesc {
+    #[serde(default)]
+    include: Vec<Spanned<String>>,
+    background: Option<Spanned<BackgroundDesc>>,
+    camera: Option<Spanned<CameraDesc>>,
+    render: Option<Spanned<RenderDesc>>,
+    #[serde(default)]
+    textures: BTreeMap<String, Spanned<TextureDescription>>,
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<MaterialDescription>>,
+    #[serde(default)]
+    shapes: BTreeMap<String, Spanned<ShapeDescription>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDesc>>,
+    #[serde(default)]
+    models: Vec<Spanned<ModelDesc>>,
+}
+
+#[derive(Serialize)]
+struct ExportDesc {
+    background: BackgroundDesc,
+    camera: CameraDesc,
+    render: Option<RenderDesc>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    materials: BTreeMap<String, MaterialDescription>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    shapes: BTreeMap<String, ShapeDescription>,
+    objects: Vec<ObjectDesc>,
+}
+
+enum BackgroundDesc {
+    Sky,
+    Solid(ColorDesc),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    eye: Option<Vec3Desc>,
+    target: Option<Vec3Desc>,
+    up: Option<Vec3Desc>,
+    vertical_fov: Option<f32>,
+    aperture: Option<f32>,
+    focus_distance: Option<f32>,
+    shutter_open: Option<f32>,
+    shutter_close: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RenderDesc {
+    width: Option<usize>,
+    height: Option<usize>,
+    samples: Option<usize>,
+    max_depth: Option<usize>,
+    chunk_size: Option<usize>,
+    // encoded like `description::seed`
+    seed: Option<i64>,
+    sampler: Option<SamplerKind>,
+}
+
+/*
+ * Composes transform steps, first step is applied first
+ */
+fn build_transform(steps: &[TransformStep]) -> anyhow::Result<Affine3> {
+    let mut transform = Affine3::IDENTITY;
+
+    for step in steps {
+        let step = match *step {
+            TransformStep::Translate(offset) => Affine3::from_translation(Vec3::from(offset)),
+            TransformStep::Scale(ScaleDesc::Uniform(scale)) => {
+                Affine3::from_scale(Vec3::new(scale, scale, scale))
+            }
+            TransformStep::Scale(ScaleDesc::Axes(scale)) => Affine3::from_scale(Vec3::from(scale)),
+            TransformStep::Rotate { axis, angle } => {
+                let axis = Vec3::from(axis);
+                if axis.norm_squared() == 0.0 {
+                    bail!("rotation axis must not be zero");
+                }
+                Affine3::from_rotation(Quaternion::from_axis_angle(axis, Degrees(angle).into()))
+            }
+            TransformStep::Matrix(rows) => {
+                let [r0, r1, r2] = rows;
+                Affine3::from_mat4(&Mat4::new([r0, r1, r2, [0.0, 0.0, 0.0, 1.0]])).unwrap()
+            }
+        };
+
+        transform = transform.then(step);
+    }
+
+    if transform.inverse().is_none() {
+        bail!("transform is not invertible");
+    }
+
+    Ok(transform)
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectDesc {
+    shape: ShapeRef,
+    material: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelDesc {
+    path: String,
+}
+
+/*
+ * `"sky"` or `[r, g, b]`, untagged enums would lose the error message
+ */
+impl<'de> Deserialize<'de> for BackgroundDesc {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        match toml::Value::deserialize(deserializer)? {
+            toml::Value::String(name) if name == "sky" => Ok(BackgroundDesc::Sky),
+            toml::Value::String(name) => Err(D::Error::custom(format!(
+                "unknown background '{}', expected \"sky\" or [r, g, b]",
+                name
+            ))),
+            value => ColorDesc::deserialize(value)
+                .map(BackgroundDesc::Solid)
+                .map_err(D::Error::custom),
+        }
+    }
+}
+
+impl Serialize for BackgroundDesc {
+    fn serialize<S>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            BackgroundDesc::Sky => serializer.serialize_str("sky"),
+            BackgroundDesc::Solid(color) => color.serialize(serializer),
+        }
+    }
+}
+
+//
+//
+//
+struct Loader {
+    scene: Scene,
+    settings: RenderSettings,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, MaterialId>,
+    shapes: HashMap<String, ShapeId>,
+    // files being loaded, to detect include cycles
+    include_stack: Vec<PathBuf>,
+}
+
+/*
+ * Position in a file for error messages
+ */
+struct Source<'a> {
+    path: &'a Path,
+    text: &'a str,
+}
+
+impl Source<'_> {
+    // one based line and column, in characters, of the start of `span`
+    fn position(
+        &self,
+        span: Range<usize>,
+    ) -> (usize, usize) {
+        let before = &self.text[..span.start.min(self.text.len())];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+        (
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+        )
+    }
+
+    fn error(
+        &self,
+        span: Range<usize>,
+        message: impl std::fmt::Display,
+    ) -> anyhow::Error {
+        let (line, column) = self.position(span);
+        anyhow!("{}:{}:{}: {}", self.path.display(), line, column, message)
+    }
+}
+
+impl Loader {
+    fn load(
+        &mut self,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("cannot read {}", path.display()))?;
+
+        let source = Source { path, text: &text };
+
+        let file: FileDesc = toml::from_str(&text).map_err(|e| match e.span() {
+            Some(span) => source.error(span, e.message()),
+            None => anyhow!("{}: {}", path.display(), e.message()),
+        })?;
+
+        let canonical =
+            fs::canonicalize(path).with_context(|| format!("cannot resolve {}", path.display()))?;
+
+        self.include_stack.push(canonical);
+
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+        for include in &file.include {
+            let include_path = directory.join(include.get_ref());
+
+            let is_cycle = fs::canonicalize(&include_path)
+                .map(|canonical| self.include_stack.contains(&canonical))
+                .unwrap_or(false);
+
+            if is_cycle {
+                return Err(source.error(
+                    include.span(),
+                    format!("'{}' is already being included", include.get_ref()),
+                ));
+            }
+
+            self.load(&include_path)
+                .with_context(|| source.error(include.span(), "included from here"))?;
+        }
+
+        self.apply(&source, file)?;
+
+        self.include_stack.pop();
+
+        Ok(())
+    }
+
+    fn apply(
+        &mut self,
+        source: &Source,
+        file: FileDesc,
+    ) -> anyhow::Result<()> {
+        if let Some(background) = file.background {
+            let background = match background.into_inner() {
+                BackgroundDesc::Sky => Background::Sky,
+                BackgroundDesc::Solid(color) => Background::Solid(Color::from(color)),
+            };
+            self.scene.set_background(background);
+        }
+
+        if let Some(camera) = file.camera {
+            let span = camera.span();
+            let camera = camera.into_inner();
+
+            let mut description = *self.scene.camera();
+
+            if let Some(eye) = camera.eye {
+                description.eye = Vec3::from(eye);
+            }
+            if let Some(target) = camera.target {
+                description.target = Vec3::from(target);
+            }
+            if let Some(up) = camera.up {
+                description.up = Vec3::from(up);
+            }
+            if let Some(vertical_fov) = camera.vertical_fov {
+                description.vertical_fov = Degrees(vertical_fov);
+            }
+            if let Some(aperture) = camera.aperture {
+                description.aperture = aperture;
+            }
+            if let Some(focus_distance) = camera.focus_distance {
+                description.focus_distance = focus_distance;
+            }
+            if let Some(shutter_open) = camera.shutter_open {
+                description.shutter_open = shutter_open;
+            }
+            if let Some(shutter_close) = camera.shutter_close {
+                description.shutter_close = shutter_close;
+            }
+
+            if description.shutter_close < description.shutter_open {
+                return Err(source.error(span, "shutter must not close before it opens"));
+            }
+
+            // every ray would be nan and the image silently black
+            let (eye, target, up) = (description.eye, description.target, description.up);
+            if !(eye.is_finite() && target.is_finite() && up.is_finite()) {
+                return Err(source.error(span, "camera eye, target and up must be finite"));
+            }
+            if (target - eye).norm_squared() <= 0.0 {
+                return Err(source.error(span, "camera eye and target must differ"));
+            }
+            if Vec3::cross(target - eye, up).norm_squared() <= 0.0 {
+                return Err(
+                    source.error(span, "camera up must not be parallel to the view direction")
+                );
+            }
+            if !(0.0 < description.vertical_fov.0 && description.vertical_fov.0 < 180.0) {
+                return Err(source.error(
+                    span,
+                    "camera vertical_fov must be between 0 and 180 degrees",
+                ));
+            }
+
+            self.scene.set_camera(description);
+        }
+
+        if let Some(render) = file.render {
+            let span = render.span();
+            let render = render.into_inner();
+
+            let counts = [render.samples, render.max_depth, render.chunk_size];
+            if counts.contains(&Some(0)) {
+                return Err(source.error(span, "render counts must be positive"));
+            }
+
+            // camera maps pixel to [0, 1] by dividing with (size - 1)
+            if render.width.is_some_and(|w| w < 2) || render.height.is_some_and(|h| h < 2) {
+                return Err(source.error(span, "image must be at least 2x2 pixels"));
+            }
+
+            let settings = &mut self.settings;
+
+            settings.image_width = render.width.unwrap_or(settings.image_width);
+            settings.image_height = render.height.unwrap_or(settings.image_height);
+            settings.sample_count = render.samples.unwrap_or(settings.sample_count);
+            settings.max_depth = render.max_depth.unwrap_or(settings.max_depth);
+            settings.chunk_size = render.chunk_size.unwrap_or(settings.chunk_size);
+            settings.seed = render.seed.map_or(settings.seed, |seed| seed as u64);
+            settings.sampler = render.sampler.unwrap_or(settings.sampler);
+        }
+
+        let directory = source.path.parent().unwrap_or_else(|| Path::new(""));
+
+        for (name, texture) in &file.textures {
+            if self.textures.contains_key(name) {
+                return Err(source.error(
+                    texture.span(),
+                    format!("texture '{}' is already defined", name),
+                ));
+            }
+        }
+
+        // textures may refer to each other, each one is built after those it names
+        let mut pending = file.textures;
+        while let Some(name) = pending.keys().next().cloned() {
+            self.insert_pending_texture(source, directory, &name, &mut pending)?;
+        }
+
+        for (name, material) in file.materials {
+            if self.materials.contains_key(&name) {
+                return Err(source.error(
+                    material.span(),
+                    format!("material '{}' is already defined", name),
+                ));
+            }
+
+            let span = material.span();
+            let id = self
+                .insert_material(directory, material.into_inner())
+                .map_err(|e| source.error(span, format!("{:#}", e)))?;
+            self.materials.insert(name, id);
+        }
+
+        for (name, shape) in &file.shapes {
+            if self.shapes.contains_key(name) {
+                return Err(
+                    source.error(shape.span(), format!("shape '{}' is already defined", name))
+                );
+            }
+        }
+
+        // instances refer to other shapes, same as textures
+        let mut pending = file.shapes;
+        while let Some(name) = pending.keys().next().cloned() {
+            self.insert_pending_shape(source, directory, &name, &mut pending)?;
+        }
+
+        for object in file.objects {
+            let span = object.span();
+            let object = object.into_inner();
+
+            let material = match self.materials.get(&object.material) {
+                Some(&material) => material,
+                None => {
+                    return Err(
+                        source.error(span, format!("unknown material '{}'", object.material))
+                    )
+                }
+            };
+
+            let shape = self
+                .resolve_shape(directory, object.shape)
+                .map_err(|e| source.error(span, format!("{:#}", e)))?;
+
+            self.scene.insert_object(shape, material);
+        }
+
+        for model in file.models {
+            load_obj(&mut self.scene, &directory.join(&model.get_ref().path))
+                .with_context(|| source.error(model.span(), "cannot load model"))?;
+        }
+
+        Ok(())
+    }
+
+    /*
+     * Builds texture `name` from `pending`, after any textures it names that are still pending.
+     * A texture naming itself, directly or not, is no longer pending when it comes around again
+     * and fails as unknown.
+     */
+    fn insert_pending_texture(
+        &mut self,
+        source: &Source,
+        directory: &Path,
+        name: &str,
+        pending: &mut BTreeMap<String, Spanned<TextureDescription>>,
+    ) -> anyhow::Result<()> {
+        let texture = pending.remove(name).unwrap();
+        let span = texture.span();
+        let texture = texture.into_inner();
+
+        for dependency in texture.texture_names() {
+            if pending.contains_key(dependency) {
+                self.insert_pending_texture(source, directory, dependency, pending)?;
+            }
+        }
+
+        let texture = self
+            .build_texture(directory, &ColorSource::Texture(Box::new(texture)))
+            .map_err(|e| source.error(span, format!("{:#}", e)))?;
+
+        self.textures.insert(name.to_string(), texture);
+
+        Ok(())
+    }
+
+    /*
+     * Same as `insert_pending_texture`, for shapes
+     */
+    fn insert_pending_shape(
+        &mut self,
+        source: &Source,
+        directory: &Path,
+        name: &str,
+        pending: &mut BTreeMap<String, Spanned<ShapeDescription>>,
+    ) -> anyhow::Result<()> {
+        let shape = pending.remove(name).unwrap();
+        let span = shape.span();
+        let shape = shape.into_inner();
+
+        for dependency in shape.shape_names() {
+            if pending.contains_key(dependency) {
+                self.insert_pending_shape(source, directory, dependency, pending)?;
+            }
+        }
+
+        let id = self
+            .insert_shape(directory, shape)
+            .map_err(|e| source.error(span, format!("{:#}", e)))?;
+
+        self.shapes.insert(name.to_string(), id);
+
+        Ok(())
+    }
+
+    fn build_texture(
+        &self,
+        directory: &Path,
+        color: &ColorSource,
+    ) -> anyhow::Result<Arc<dyn Texture>> {
+        let texture = match color {
+            ColorSource::Color(color) => {
+                return Ok(Arc::new(ConstantTexture::new(Color::from(*color))))
+            }
+            ColorSource::Named(name) => {
+                return self
+                    .textures
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("unknown texture '{}'", name))
+            }
+            ColorSource::Texture(texture) => texture.as_ref(),
+        };
+
+        let texture: Arc<dyn Texture> = match texture {
+            TextureDescription::Constant { color } => {
+                Arc::new(ConstantTexture::new(Color::from(*color)))
+            }
+            TextureDescription::Checker {
+                even,
+                odd,
+                size,
+                space,
+            } => {
+                if *size <= 0.0 {
+                    bail!("checker size must be positive");
+                }
+
+                Arc::new(CheckerTexture::new(
+                    self.build_texture(directory, even)?,
+                    self.build_texture(directory, odd)?,
+                    *size,
+                    *space,
+                ))
+            }
+            TextureDescription::Image { path, wrap, filter } => {
+                // absolute, so that the scene can be written anywhere else
+                let path = directory.join(path);
+                let path = fs::canonicalize(&path).unwrap_or(path);
+                Arc::new(ImageTexture::load(&path, *wrap, *filter)?)
+            }
+            TextureDescription::Noise { noise, scale, seed } => {
+                Arc::new(NoiseTexture::new(*noise, *scale, *seed))
+            }
+        };
+
+        Ok(texture)
+    }
+
+    fn insert_material(
+        &mut self,
+        directory: &Path,
+        material: MaterialDescription,
+    ) -> anyhow::Result<MaterialId> {
+        let id = match material {
+            MaterialDescription::Lambertian { albedo } => {
+                let albedo = self.build_texture(directory, &albedo)?;
+                self.scene.insert_material(Lambertian::textured(albedo))
+            }
+            MaterialDescription::Metal { albedo, fuzz } => {
+                let albedo = self.build_texture(directory, &albedo)?;
+                self.scene.insert_material(Metal::textured(albedo, fuzz))
+            }
+            MaterialDescription::Dielectric { refraction_index } => self
+                .scene
+                .insert_material(Dielectric::new(refraction_index)),
+            MaterialDescription::DiffuseLight { emit } => {
+                let emit = self.build_texture(directory, &emit)?;
+                self.scene.insert_material(DiffuseLight::textured(emit))
+            }
+            MaterialDescription::Isotropic { albedo } => {
+                let albedo = self.build_texture(directory, &albedo)?;
+                self.scene.insert_material(Isotropic::textured(albedo))
+            }
+            MaterialDescription::HenyeyGreenstein { albedo, g } => {
+                if !(g > -1.0 && g < 1.0) {
+                    bail!("henyey_greenstein g must be between -1 and 1");
+                }
+                let albedo = self.build_texture(directory, &albedo)?;
+                self.scene
+                    .insert_material(HenyeyGreenstein::textured(albedo, g))
+            }
+        };
+
+        Ok(id)
+    }
+
+    fn resolve_shape(
+        &mut self,
+        directory: &Path,
+        shape: ShapeRef,
+    ) -> anyhow::Result<ShapeId> {
+        match shape {
+            ShapeRef::Named(name) => self
+                .shapes
+                .get(&name)
+                .copied()
+                .ok_or_else(|| anyhow!("unknown shape '{}'", name)),
+            ShapeRef::Inline(shape) => self.insert_shape(directory, shape),
+            ShapeRef::Id(_) => bail!("shape ids cannot be loaded from files"),
+        }
+    }
+
+    fn insert_shape(
+        &mut self,
+        directory: &Path,
+        shape: ShapeDescription,
+    ) -> anyhow::Result<ShapeId> {
+        let id = match shape {
+            ShapeDescription::Sphere { center, radius } => self.scene.insert_shape(Sphere {
+                center: Vec3::from(center),
+                radius,
+            }),
+            ShapeDescription::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+            } => {
+                if time1 <= time0 {
+                    bail!("moving sphere must have time1 after time0");
+                }
+
+                self.scene.insert_shape(MovingSphere {
+                    center0: Vec3::from(center0),
+                    center1: Vec3::from(center1),
+                    time0,
+                    time1,
+                    radius,
+                })
+            }
+            ShapeDescription::Triangle {
+                positions,
+                normals,
+                uvs,
+            } => self.scene.insert_shape(Triangle {
+                positions: positions.map(Vec3::from),
+                normals: normals.map(|normals| normals.map(Vec3::from)),
+                uvs: uvs.map(|uvs| uvs.map(to_uv)),
+            }),
+            ShapeDescription::Mesh {
+                positions,
+                normals,
+                uvs,
+                indices,
+            } => {
+                if normals.as_ref().is_some_and(|n| n.len() != positions.len()) {
+                    bail!("mesh must have as many normals as positions");
+                }
+                if uvs.as_ref().is_some_and(|uvs| uvs.len() != positions.len()) {
+                    bail!("mesh must have as many uvs as positions");
+                }
+                if let Some(index) = indices.iter().flatten().find(|&&i| i >= positions.len()) {
+                    bail!(
+                        "mesh index {} is out of range, there are {} positions",
+                        index,
+                        positions.len()
+                    );
+                }
+
+                self.scene.insert_shape(TriangleMesh::new(
+                    positions.into_iter().map(Vec3::from).collect(),
+                    normals.map(|normals| normals.into_iter().map(Vec3::from).collect()),
+                    uvs.map(|uvs| uvs.into_iter().map(to_uv).collect()),
+                    indices,
+                ))
+            }
+            ShapeDescription::Quad {
+                corner,
+                edge_u,
+                edge_v,
+            } => {
+                let [corner, edge_u, edge_v] = [corner, edge_u, edge_v].map(Vec3::from);
+                if ![corner, edge_u, edge_v].iter().all(|v| v.is_finite()) {
+                    bail!("quad corner and edges must be finite");
+                }
+                // huge finite edges can still give a nan cross product, which `Quad::new` rejects
+                let area = Vec3::cross(edge_u, edge_v).norm_squared();
+                if area.is_nan() || area <= 0.0 {
+                    bail!("quad edges must not be parallel");
+                }
+
+                self.scene.insert_shape(Quad::new(corner, edge_u, edge_v))
+            }
+            ShapeDescription::Box { min, max } => {
+                let (min, max) = (Vec3::from(min), Vec3::from(max));
+                if !(min.is_finite() && max.is_finite() && (max - min).is_finite()) {
+                    bail!("box min, max and extents must be finite");
+                }
+                if !(min.x < max.x && min.y < max.y && min.z < max.z) {
+                    bail!("box min must be below max on every axis");
+                }
+
+                self.scene.insert_shape(Cuboid::new(min, max))
+            }
+            ShapeDescription::Plane { point, normal } => {
+                let (point, normal) = (Vec3::from(point), Vec3::from(normal));
+                if !(point.is_finite() && normal.is_finite()) {
+                    bail!("plane point and normal must be finite");
+                }
+                if normal.norm_squared() <= 0.0 {
+                    bail!("plane normal must not be zero");
+                }
+
+                self.scene.insert_shape(Plane::new(point, normal))
+            }
+            ShapeDescription::Disk {
+                center,
+                normal,
+                radius,
+                inner_radius,
+            } => {
+                let (center, normal) = (Vec3::from(center), Vec3::from(normal));
+                if !(center.is_finite() && normal.is_finite() && radius.is_finite()) {
+                    bail!("disk center, normal and radius must be finite");
+                }
+                if normal.norm_squared() <= 0.0 {
+                    bail!("disk normal must not be zero");
+                }
+                if !(0.0 <= inner_radius && inner_radius < radius) {
+                    bail!("disk radii must satisfy 0 <= inner_radius < radius");
+                }
+
+                self.scene
+                    .insert_shape(Disk::new(center, normal, radius, inner_radius))
+            }
+            ShapeDescription::Cylinder {
+                base,
+                radius,
+                height,
+                capped,
+            } => {
+                if !(Vec3::from(base).is_finite() && radius.is_finite() && height.is_finite()) {
+                    bail!("cylinder base, radius and height must be finite");
+                }
+                if !(radius > 0.0 && height > 0.0) {
+                    bail!("cylinder radius and height must be positive");
+                }
+
+                self.scene
+                    .insert_shape(Cylinder::new(Vec3::from(base), radius, height, capped))
+            }
+            ShapeDescription::Cone {
+                base,
+                radius,
+                height,
+                capped,
+            } => {
+                if !(Vec3::from(base).is_finite() && radius.is_finite() && height.is_finite()) {
+                    bail!("cone base, radius and height must be finite");
+                }
+                if !(radius > 0.0 && height > 0.0) {
+                    bail!("cone radius and height must be positive");
+                }
+
+                self.scene
+                    .insert_shape(Cone::new(Vec3::from(base), radius, height, capped))
+            }
+            ShapeDescription::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let center = Vec3::from(center);
+                if !(center.is_finite() && major_radius.is_finite() && minor_radius.is_finite()) {
+                    bail!("torus center and radii must be finite");
+                }
+                if !(major_radius > 0.0 && minor_radius > 0.0) {
+                    bail!("torus radii must be positive");
+                }
+
+                self.scene
+                    .insert_shape(Torus::new(center, major_radius, minor_radius))
+            }
+            ShapeDescription::Instance { shape, transform } => {
+                let transform =
+                    build_transform(&transform).context("invalid instance transform")?;
+                let shape = self.resolve_shape(directory, *shape)?;
+
+                let instance = Instance::new(&self.scene, shape, transform);
+                self.scene.insert_shape(instance)
+            }
+            ShapeDescription::ConstantMedium { boundary, density } => {
+                if density < 0.0 {
+                    bail!("medium density must not be negative");
+                }
+                let boundary = self.resolve_shape(directory, *boundary)?;
+
+                let medium = ConstantMedium::new(&self.scene, boundary, density);
+                self.scene.insert_shape(medium)
+            }
+            ShapeDescription::GridMedium {
+                boundary,
+                path,
+                density,
+            } => {
+                if density < 0.0 {
+                    bail!("medium density must not be negative");
+                }
+                let boundary = self.resolve_shape(directory, *boundary)?;
+                if self.scene.shared_shape(boundary).bounding_box().is_none() {
+                    bail!("grid medium boundary must be bounded");
+                }
+
+                // absolute, same as image textures
+                let path = directory.join(path);
+                let path = fs::canonicalize(&path).unwrap_or(path);
+                let grid = DensityGrid::load(&path)?;
+
+                let medium = GridMedium::new(&self.scene, boundary, grid, density);
+                self.scene.insert_shape(medium)
+            }
+            ShapeDescription::NoiseMedium {
+                boundary,
+                density,
+                noise,
+                scale,
+                seed,
+            } => {
+                if density < 0.0 {
+                    bail!("medium density must not be negative");
+                }
+                let boundary = self.resolve_shape(directory, *boundary)?;
+
+                let noise = NoiseTexture::new(noise, scale, seed);
+                let medium = NoiseMedium::new(&self.scene, boundary, noise, density);
+                self.scene.insert_shape(medium)
+            }
+            ShapeDescription::Csg {
+                operation,
+                left,
+                right,
+            } => {
+                let left = self.resolve_shape(directory, *left)?;
+                let right = self.resolve_shape(directory, *right)?;
+
+                let csg = Csg::new(&self.scene, operation, left, right);
+                self.scene.insert_shape(csg)
+            }
+        };
+
+        Ok(id)
+    }
+}
+
+fn to_uv(uv: UvDesc) -> (f32, f32) {
+    (uv[0], uv[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+     * Directory of scene files for one test, removed when dropped
+     */
+    struct TestDirectory {
+        path: PathBuf,
+    }
+
+    impl TestDirectory {
+        fn new(name: &str) -> TestDirectory {
+            let path = std::env::temp_dir().join(format!(
+                "scene_file_test_{}_{}",
+                std::process::id(),
+                name
+            ));
+            fs::create_dir_all(&path).unwrap();
+            TestDirectory { path }
+        }
+
+        fn write(
+            &self,
+            name: &str,
+            text: &str,
+        ) -> PathBuf {
+            let path = self.path.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, text).unwrap();
+            path
+        }
+
+        fn load_error(
+            &self,
+            name: &str,
+        ) -> String {
+            match load_scene_file(&self.path.join(name)) {
+                Ok(_) => panic!("{} loaded", name),
+                Err(e) => format!("{:#}", e),
+            }
+        }
+    }
+
+    impl Drop for TestDirectory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    const RED: &str = "
+[materials.red]
+type = \"lambertian\"
+albedo = [1, 0, 0]
+";
+
+    #[test]
+    fn bad_field_value_reports_file_line_and_column() {
+        let directory = TestDirectory::new("bad_value");
+        let path = directory.write(
+            "camera.toml",
+            "[camera]\neye = [0, 0, 0]\nvertical_fov = \"wide\"\n",
+        );
+        let error = directory.load_error("camera.toml");
+        let location = format!("{}:3:16:", path.display());
+        assert!(error.starts_with(&location), "{}", error);
+        assert!(error.contains("expected f32"), "{}", error);
+
+        // Tagged shape tables are buffered by serde, so the error points at
+        // the table header rather than the field.
+        let path = directory.write(
+            "shape.toml",
+            "background = \"sky\"\n\n[shapes.ball]\ntype = \"sphere\"\nradius = \"big\"\n",
+        );
+        let error = directory.load_error("shape.toml");
+        let location = format!("{}:3:1:", path.display());
+        assert!(error.starts_with(&location), "{}", error);
+        assert!(error.contains("expected f32"), "{}", error);
+    }
+
+    #[test]
+    fn degenerate_cameras_are_errors() {
+        let directory = TestDirectory::new("degenerate_cameras");
+
+        let cases = [
+            (
+                "eye = [0, 0, 0]\ntarget = [0, 0, 0]",
+                "camera eye and target must differ",
+            ),
+            (
+                "eye = [0, 0, 0]\ntarget = [0, 2, 0]\nup = [0, 1, 0]",
+                "camera up must not be parallel to the view direction",
+            ),
+            (
+                "eye = [0, nan, 0]\ntarget = [0, 0, -1]",
+                "camera eye, target and up must be finite",
+            ),
+            (
+                "vertical_fov = 0",
+                "camera vertical_fov must be between 0 and 180 degrees",
+            ),
+            (
+                "vertical_fov = 180",
+                "camera vertical_fov must be between 0 and 180 degrees",
+            ),
+            (
+                "vertical_fov = nan",
+                "camera vertical_fov must be between 0 and 180 degrees",
+            ),
+        ];
+
+        for (index, (camera, message)) in cases.iter().enumerate() {
+            let name = format!("camera_{}.toml", index);
+            let path = directory.write(
+                &name,
+                &format!("background = \"sky\"\n\n[camera]\n{}\n", camera),
+            );
+
+            let error = directory.load_error(&name);
+            assert!(
+                error.starts_with(&format!("{}:3:1:", path.display())),
+                "{}",
+                error
+            );
+            assert!(error.ends_with(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn degenerate_and_non_finite_shapes_are_errors() {
+        let directory = TestDirectory::new("degenerate_shapes");
+
+        let cases = [
+            (
+                "type = \"quad\"\ncorner = [0, 0, 0]\nedge_u = [nan, 0, 0]\nedge_v = [0, 1, 0]",
+                "quad corner and edges must be finite",
+            ),
+            (
+                "type = \"quad\"\ncorner = [0, 0, 0]\nedge_u = [1, 0, 0]\nedge_v = [2, 0, 0]",
+                "quad edges must not be parallel",
+            ),
+            (
+                "type = \"plane\"\npoint = [0, 0, 0]\nnormal = [0, nan, 0]",
+                "plane point and normal must be finite",
+            ),
+            (
+                "type = \"plane\"\npoint = [0, 0, inf]\nnormal = [0, 1, 0]",
+                "plane point and normal must be finite",
+            ),
+            (
+                "type = \"disk\"\ncenter = [0, 0, 0]\nnormal = [0, 0, nan]\nradius = 1",
+                "disk center, normal and radius must be finite",
+            ),
+            (
+                "type = \"disk\"\ncenter = [0, 0, 0]\nnormal = [0, 0, 0]\nradius = 1",
+                "disk normal must not be zero",
+            ),
+            (
+                "type = \"cylinder\"\nbase = [0, 0, 0]\nradius = inf\nheight = 1",
+                "cylinder base, radius and height must be finite",
+            ),
+            (
+                "type = \"cone\"\nbase = [0, nan, 0]\nradius = 1\nheight = 1",
+                "cone base, radius and height must be finite",
+            ),
+            (
+                "type = \"torus\"\ncenter = [0, 0, 0]\nmajor_radius = 2\nminor_radius = nan",
+                "torus center and radii must be finite",
+            ),
+            (
+                "type = \"box\"\nmin = [0, 0, 0]\nmax = [1, 1, inf]",
+                "box min, max and extents must be finite",
+            ),
+            (
+                "type = \"box\"\nmin = [-3e38, 0, 0]\nmax = [3e38, 1, 1]",
+                "box min, max and extents must be finite",
+            ),
+        ];
+
+        for (index, (shape, message)) in cases.iter().enumerate() {
+            let name = format!("shape_{}.toml", index);
+            let path = directory.write(&name, &format!("[shapes.bad]\n{}\n", shape));
+
+            let error = directory.load_error(&name);
+            assert!(
+                error.starts_with(&format!("{}:1:1:", path.display())),
+                "{}",
+                error
+            );
+            assert!(error.ends_with(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn unknown_names_report_their_object() {
+        let directory = TestDirectory::new("unknown_names");
+
+        let path = directory.write(
+            "material.toml",
+            &format!(
+                "{}\n[[objects]]\nshape = {{ type = \"sphere\", center = [0, 0, 0], radius = 1 }}\nmaterial = \"blue\"\n",
+                RED
+            ),
+        );
+        let error = directory.load_error("material.toml");
+        assert!(
+            error.starts_with(&format!("{}:6:1:", path.display())),
+            "{}",
+            error
+        );
+        assert!(error.contains("unknown material 'blue'"), "{}", error);
+
+        let path = directory.write(
+            "shape.toml",
+            &format!(
+                "{}\n[[objects]]\nshape = \"ball\"\nmaterial = \"red\"\n",
+                RED
+            ),
+        );
+        let error = directory.load_error("shape.toml");
+        assert!(
+            error.starts_with(&format!("{}:6:1:", path.display())),
+            "{}",
+            error
+        );
+        assert!(error.contains("unknown shape 'ball'"), "{}", error);
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let directory = TestDirectory::new("duplicates");
+        directory.write("materials.toml", RED);
+
+        // across an include
+        let path = directory.write(
+            "scene.toml",
+            &format!("include = [\"materials.toml\"]\n{}", RED),
+        );
+        let error = directory.load_error("scene.toml");
+        assert!(
+            error.starts_with(&format!("{}:3:1:", path.display())),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("material 'red' is already defined"),
+            "{}",
+            error
+        );
+
+        // within one file toml itself rejects it
+        directory.write("twice.toml", &format!("{}{}", RED, RED));
+        let error = directory.load_error("twice.toml");
+        assert!(error.contains("twice.toml:6:"), "{}", error);
+    }
+
+    #[test]
+    fn includes_are_relative_to_including_file() {
+        let directory = TestDirectory::new("relative_includes");
+        directory.write(
+            "parts/shapes.toml",
+            "[shapes.ball]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\n",
+        );
+        directory.write(
+            "parts/materials.toml",
+            &format!("include = [\"shapes.toml\"]\n{}", RED),
+        );
+        let path = directory.write(
+            "scene.toml",
+            "include = [\"parts/materials.toml\"]\n\n[[objects]]\nshape = \"ball\"\nmaterial = \"red\"\n",
+        );
+
+        let file = load_scene_file(&path).unwrap();
+        assert_eq!(file.scene.objects().count(), 1);
+        assert_eq!(file.scene.materials().count(), 1);
+    }
+
+    #[test]
+    fn include_cycles_are_rejected() {
+        let directory = TestDirectory::new("include_cycles");
+        directory.write("a.toml", "include = [\"b.toml\"]\n");
+        let b = directory.write("b.toml", "include = [\"a.toml\"]\n");
+        directory.write("self.toml", "include = [\"self.toml\"]\n");
+
+        let error = directory.load_error("a.toml");
+        assert!(error.contains(&format!("{}:1:", b.display())), "{}", error);
+        assert!(
+            error.contains("'a.toml' is already being included"),
+            "{}",
+            error
+        );
+
+        let error = directory.load_error("self.toml");
+        assert!(
+            error.contains("'self.toml' is already being included"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn exported_scenes_reload_unchanged() {
+        use crate::scenes::{create_scene, scene_creators};
+        use std::collections::HashSet;
+
+        fn used_counts(scene: &Scene) -> (usize, usize, usize) {
+            let objects: Vec<_> = scene.objects().collect();
+            let shapes: HashSet<_> = objects.iter().map(|&(_, shape, _)| shape).collect();
+            let materials: HashSet<_> = objects.iter().map(|&(_, _, material)| material).collect();
+            (objects.len(), shapes.len(), materials.len())
+        }
+
+        let directory = TestDirectory::new("round_trip");
+
+        let settings = RenderSettings {
+            image_width: 8,
+            image_height: 6,
+            sample_count: 4,
+            max_depth: 4,
+            ..RenderSettings::default()
+        };
+
+        // the smoke grid of "Clouds" is built in code and has no file to refer to
+        let mut names: Vec<_> = scene_creators()
+            .into_iter()
+            .filter(|&(name, _)| name != "Clouds")
+            .collect();
+        names.sort_by_key(|&(name, _)| name);
+
+        for (name, creator) in names {
+            let scene = create_scene(creator, 7);
+            let path = directory
+                .path
+                .join(format!("{}.toml", name.replace(' ', "_")));
+            save_scene_file(&path, &scene, scene.camera(), Some(&settings))
+                .unwrap_or_else(|e| panic!("'{}' cannot be exported: {:#}", name, e));
+
+            let file = load_scene_file(&path)
+                .unwrap_or_else(|e| panic!("'{}' does not reload: {:#}", name, e));
+            assert_eq!(used_counts(&file.scene), used_counts(&scene), "{}", name);
+
+            let expected = render(&scene, scene.camera(), &settings, |_, _| {});
+            let actual = render(&file.scene, file.scene.camera(), &file.settings, |_, _| {});
+            let (x, y) = (settings.image_width / 2, settings.image_height / 2);
+            let (expected, actual) = (expected.get(x, y), actual.get(x, y));
+            let difference: ColorDesc = (expected - actual).into();
+            assert!(
+                difference.iter().all(|d| d.abs() < 1e-3),
+                "'{}' centre pixel {:?} became {:?}",
+                name,
+                expected,
+                actual
+            );
+        }
+    }
+}
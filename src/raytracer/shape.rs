@@ -1,5 +1,28 @@
 use crate::cgmath::*;
+use crate::raytracer::aabb::*;
+use crate::raytracer::description::ShapeDescription;
 use crate::raytracer::ray::*;
+use crate::raytracer::sampler::*;
+
+use std::f32::consts::PI;
+
+/*
+ * Longitude and latitude of a unit `direction` from the center, both in [0, 1]. `u` goes around
+ * the y axis starting from -x, `v` goes from the bottom pole up.
+ */
+fn sphere_uv(direction: Vec3) -> (f32, f32) {
+    let theta = (-direction.y).clamp(-1.0, 1.0).acos();
+
+    (azimuth(direction.x, direction.z), theta / PI)
+}
+
+// angle of `(x, z)` around the y axis in [0, 1], starting from -x like on spheres
+fn azimuth(
+    x: f32,
+    z: f32,
+) -> f32 {
+    ((-z).atan2(x) + PI) / (2.0 * PI)
+}
 
 //
 //
@@ -10,6 +33,28 @@ pub struct Sphere {
     pub radius: f32,
 }
 
+impl Sphere {
+    /*
+     * `1 - cos(theta_max)` of the cone the sphere subtends from `origin`, `None` from inside.
+     *
+     * Written as `x / (1 + sqrt(1 - x))` so that small distant spheres don't round to zero.
+     */
+    fn subtended_cone(
+        &self,
+        origin: Vec3,
+    ) -> Option<f32> {
+        let distance_squared = (self.center - origin).norm_squared();
+        let radius_squared = self.radius * self.radius;
+
+        if distance_squared <= radius_squared {
+            return None;
+        }
+
+        let x = radius_squared / distance_squared;
+        Some(x / (1.0 + (1.0 - x).sqrt()))
+    }
+}
+
 impl HittableShape for Sphere {
     fn hit(
         &self,
@@ -31,32 +76,1512 @@ impl HittableShape for Sphere {
 
         let d = d.sqrt();
 
+        // nearest root in range, the far one when the ray starts past the near one
         let t1 = (-half_b - d) / a;
         let t2 = (-half_b + d) / a;
-        let t = match (t1 > 0.0, t2 > 0.0) {
-            (true, true) => f32::min(t1, t2),
-            (true, false) => t1,
-            (false, true) => t2,
-            _ => return None,
-        };
-
-        if !(near < t && t < far) {
+        let t = if near < t1 && t1 < far {
+            t1
+        } else if near < t2 && t2 < far {
+            t2
+        } else {
             return None;
-        }
+        };
 
         //
         let point = ray.at(t);
-        let normal = self.radius.signum() * (point - self.center).normalized();
+        let direction = (point - self.center).normalized();
+        let normal = self.radius.signum() * direction;
 
         let is_front_face = Vec3::dot(normal, *ray.direction()) < 0.0;
 
         let normal = if is_front_face { normal } else { -normal };
 
+        let (u, v) = sphere_uv(direction);
+
         Some(ShapeHit {
             point,
             normal,
             t,
             is_front_face,
+            u,
+            v,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+
+    /*
+     * Uniformly samples cone of directions the sphere subtends
+     */
+    fn sample(
+        &self,
+        origin: Vec3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ShapeSample> {
+        let one_minus_cos_max = self.subtended_cone(origin)?;
+
+        let w = (self.center - origin).normalized();
+        let (u, v) = w.orthonormal_basis();
+
+        let (u1, u2) = sampler.get_2d();
+
+        let cos_theta = 1.0 - u1 * one_minus_cos_max;
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        let direction = (sin_theta * phi.cos()) * u + (sin_theta * phi.sin()) * v + cos_theta * w;
+
+        // grazing directions can miss due to precision
+        let hit = self.hit(&Ray::with_time(origin, direction, time), 0.0, f32::INFINITY)?;
+
+        Some(ShapeSample {
+            hit,
+            pdf: 1.0 / (2.0 * PI * one_minus_cos_max),
+        })
+    }
+
+    fn pdf(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        time: f32,
+    ) -> f32 {
+        match self.subtended_cone(origin) {
+            Some(one_minus_cos_max)
+                if self
+                    .hit(&Ray::with_time(origin, direction, time), 0.0, f32::INFINITY)
+                    .is_some() =>
+            {
+                1.0 / (2.0 * PI * one_minus_cos_max)
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Sphere {
+            center: self.center.into(),
+            radius: self.radius,
+        })
+    }
+}
+
+/*
+ * Sphere moving linearly from `center0` at `time0` to `center1` at `time1`, at rest before and
+ * after
+ */
+#[derive(Debug)]
+pub struct MovingSphere {
+    pub center0: Vec3,
+    pub center1: Vec3,
+    pub time0: f32,
+    pub time1: f32,
+    pub radius: f32,
+}
+
+impl MovingSphere {
+    pub fn center(
+        &self,
+        time: f32,
+    ) -> Vec3 {
+        let t = (time - self.time0) / (self.time1 - self.time0);
+        Vec3::lerp(t.clamp(0.0, 1.0), self.center0, self.center1)
+    }
+
+    // where the sphere is at `time`
+    fn at_time(
+        &self,
+        time: f32,
+    ) -> Sphere {
+        Sphere {
+            center: self.center(time),
+            radius: self.radius,
+        }
+    }
+}
+
+impl HittableShape for MovingSphere {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        self.at_time(ray.time()).hit(ray, near, far)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let b0 = self.at_time(self.time0).bounding_box()?;
+        let b1 = self.at_time(self.time1).bounding_box()?;
+        Some(b0.union(b1))
+    }
+
+    fn sample(
+        &self,
+        origin: Vec3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ShapeSample> {
+        self.at_time(time).sample(origin, time, sampler)
+    }
+
+    fn pdf(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        time: f32,
+    ) -> f32 {
+        self.at_time(time).pdf(origin, direction, time)
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::MovingSphere {
+            center0: self.center0.into(),
+            center1: self.center1.into(),
+            time0: self.time0,
+            time1: self.time1,
+            radius: self.radius,
+        })
+    }
+}
+
+//
+//
+//
+
+/*
+ * Parallelogram spanned by `edge_u` and `edge_v` from `corner`. Its front side is the one
+ * `edge_u x edge_v` points to, `u` and `v` go from 0 to 1 along the edges.
+ */
+#[derive(Debug, Clone)]
+pub struct Quad {
+    pub corner: Vec3,
+    pub edge_u: Vec3,
+    pub edge_v: Vec3,
+}
+
+impl Quad {
+    /*
+     * Panics if the edges are parallel
+     */
+    pub fn new(
+        corner: Vec3,
+        edge_u: Vec3,
+        edge_v: Vec3,
+    ) -> Quad {
+        assert!(
+            Vec3::cross(edge_u, edge_v).norm_squared() > 0.0,
+            "quad edges must not be parallel"
+        );
+
+        Quad {
+            corner,
+            edge_u,
+            edge_v,
+        }
+    }
+
+    pub fn area(&self) -> f32 {
+        Vec3::cross(self.edge_u, self.edge_v).norm()
+    }
+
+    /*
+     * Distance along `ray` and edge coordinates of the hit
+     */
+    fn intersect(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<(f32, f32, f32)> {
+        let n = Vec3::cross(self.edge_u, self.edge_v);
+
+        let denominator = Vec3::dot(n, *ray.direction());
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = Vec3::dot(n, self.corner - *ray.origin()) / denominator;
+        if !(near < t && t < far) {
+            return None;
+        }
+
+        // coordinates of the hit in the basis of the edges
+        let w = n / n.norm_squared();
+        let offset = ray.at(t) - self.corner;
+        let alpha = Vec3::dot(w, Vec3::cross(offset, self.edge_v));
+        let beta = Vec3::dot(w, Vec3::cross(self.edge_u, offset));
+
+        if !((0.0..=1.0).contains(&alpha) && (0.0..=1.0).contains(&beta)) {
+            return None;
+        }
+
+        Some((t, alpha, beta))
+    }
+
+    fn shape_hit(
+        &self,
+        ray: &Ray,
+        t: f32,
+        u: f32,
+        v: f32,
+    ) -> ShapeHit {
+        let normal = Vec3::cross(self.edge_u, self.edge_v).normalized();
+        let is_front_face = Vec3::dot(normal, *ray.direction()) < 0.0;
+
+        ShapeHit {
+            point: ray.at(t),
+            normal: if is_front_face { normal } else { -normal },
+            t,
+            is_front_face,
+            u,
+            v,
+        }
+    }
+
+    /*
+     * Converts area density `1 / area` at distance `t` along unit `direction` to solid angle
+     * density
+     */
+    fn solid_angle_pdf(
+        &self,
+        direction: Vec3,
+        t: f32,
+        area: f32,
+    ) -> f32 {
+        let normal = Vec3::cross(self.edge_u, self.edge_v).normalized();
+        let cosine = Vec3::dot(normal, direction).abs();
+
+        if cosine < 1e-6 {
+            return 0.0;
+        }
+
+        t * t / (cosine * area)
+    }
+
+    /*
+     * Uniformly samples a point on the quad, `area` is the area the pdf is relative to
+     */
+    fn sample_area(
+        &self,
+        origin: Vec3,
+        time: f32,
+        area: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ShapeSample> {
+        let (u, v) = sampler.get_2d();
+        let point = self.corner + u * self.edge_u + v * self.edge_v;
+
+        let ray = Ray::with_time(origin, point - origin, time);
+        let t = (point - origin).norm();
+
+        let pdf = self.solid_angle_pdf(*ray.direction(), t, area);
+        if pdf == 0.0 || !pdf.is_finite() {
+            return None;
+        }
+
+        Some(ShapeSample {
+            hit: self.shape_hit(&ray, t, u, v),
+            pdf,
+        })
+    }
+}
+
+impl HittableShape for Quad {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        let (t, u, v) = self.intersect(ray, near, far)?;
+        Some(self.shape_hit(ray, t, u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let c = self.corner;
+        let bounds = Aabb::new(c, c + self.edge_u)
+            .grow(c + self.edge_v)
+            .grow(c + self.edge_u + self.edge_v);
+
+        // flat along an axis, slabs of zero thickness would be missed by precision
+        let padding = Vec3::new(1e-4, 1e-4, 1e-4);
+        Some(Aabb::new(bounds.min - padding, bounds.max + padding))
+    }
+
+    fn sample(
+        &self,
+        origin: Vec3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ShapeSample> {
+        self.sample_area(origin, time, self.area(), sampler)
+    }
+
+    fn pdf(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        time: f32,
+    ) -> f32 {
+        let ray = Ray::with_time(origin, direction, time);
+
+        match self.intersect(&ray, 0.0, f32::INFINITY) {
+            Some((t, _, _)) => self.solid_angle_pdf(*ray.direction(), t, self.area()),
+            None => 0.0,
+        }
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Quad {
+            corner: self.corner.into(),
+            edge_u: self.edge_u.into(),
+            edge_v: self.edge_v.into(),
+        })
+    }
+}
+
+/*
+ * Axis aligned box made of six quads facing out
+ */
+#[derive(Debug, Clone)]
+pub struct Cuboid {
+    min: Vec3,
+    max: Vec3,
+    faces: [Quad; 6],
+}
+
+impl Cuboid {
+    /*
+     * Panics unless `min` is below `max` on every axis
+     */
+    pub fn new(
+        min: Vec3,
+        max: Vec3,
+    ) -> Cuboid {
+        assert!(
+            min.x < max.x && min.y < max.y && min.z < max.z,
+            "box min must be below max on every axis"
+        );
+
+        let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+        // edge order makes every normal point out of the box
+        let faces = [
+            Quad::new(Vec3::new(min.x, min.y, max.z), dx, dy),
+            Quad::new(Vec3::new(max.x, min.y, max.z), -dz, dy),
+            Quad::new(Vec3::new(max.x, min.y, min.z), -dx, dy),
+            Quad::new(min, dz, dy),
+            Quad::new(Vec3::new(min.x, max.y, max.z), dx, -dz),
+            Quad::new(min, dx, dz),
+        ];
+
+        Cuboid { min, max, faces }
+    }
+
+    pub fn min(&self) -> Vec3 {
+        self.min
+    }
+
+    pub fn max(&self) -> Vec3 {
+        self.max
+    }
+
+    pub fn area(&self) -> f32 {
+        self.faces.iter().map(Quad::area).sum()
+    }
+}
+
+impl HittableShape for Cuboid {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        let mut nearest = None;
+        let mut far = far;
+
+        for face in &self.faces {
+            if let Some(hit) = face.hit(ray, near, far) {
+                far = hit.t;
+                nearest = Some(hit);
+            }
+        }
+
+        nearest
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+
+    /*
+     * Uniformly samples the whole surface, faces turned away from `origin` included
+     */
+    fn sample(
+        &self,
+        origin: Vec3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ShapeSample> {
+        let area = self.area();
+
+        let mut target = sampler.get_1d() * area;
+        let face = self
+            .faces
+            .iter()
+            .find(|face| {
+                target -= face.area();
+                target < 0.0
+            })
+            .unwrap_or(&self.faces[5]);
+
+        face.sample_area(origin, time, area, sampler)
+    }
+
+    // every face along `direction` could have been sampled
+    fn pdf(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        time: f32,
+    ) -> f32 {
+        let ray = Ray::with_time(origin, direction, time);
+        let area = self.area();
+
+        self.faces
+            .iter()
+            .filter_map(|face| {
+                let (t, _, _) = face.intersect(&ray, 0.0, f32::INFINITY)?;
+                Some(face.solid_angle_pdf(*ray.direction(), t, area))
+            })
+            .sum()
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Box {
+            min: self.min.into(),
+            max: self.max.into(),
+        })
+    }
+}
+
+/*
+ * Infinite plane through `point`, its front side is the one `normal` points to. `u` and `v` are
+ * distances along two directions in the plane, so textures repeat every unit.
+ */
+#[derive(Debug, Clone)]
+pub struct Plane {
+    point: Vec3,
+    normal: Vec3,
+    tangent_u: Vec3,
+    tangent_v: Vec3,
+}
+
+impl Plane {
+    /*
+     * Panics if `normal` is zero
+     */
+    pub fn new(
+        point: Vec3,
+        normal: Vec3,
+    ) -> Plane {
+        assert!(normal.norm_squared() > 0.0, "plane normal must not be zero");
+
+        let normal = normal.normalized();
+        let (tangent_u, tangent_v) = normal.orthonormal_basis();
+
+        Plane {
+            point,
+            normal,
+            tangent_u,
+            tangent_v,
+        }
+    }
+
+    pub fn point(&self) -> Vec3 {
+        self.point
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+}
+
+impl HittableShape for Plane {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        let denominator = Vec3::dot(self.normal, *ray.direction());
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = Vec3::dot(self.normal, self.point - *ray.origin()) / denominator;
+        if !(near < t && t < far) {
+            return None;
+        }
+
+        let point = ray.at(t);
+        let offset = point - self.point;
+        let is_front_face = denominator < 0.0;
+
+        Some(ShapeHit {
+            point,
+            normal: if is_front_face {
+                self.normal
+            } else {
+                -self.normal
+            },
+            t,
+            is_front_face,
+            u: Vec3::dot(offset, self.tangent_u),
+            v: Vec3::dot(offset, self.tangent_v),
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Plane {
+            point: self.point.into(),
+            normal: self.normal.into(),
         })
     }
 }
+
+/*
+ * Disk of `radius` around `center`, with a hole of `inner_radius` for an annulus. Its front side
+ * is the one `normal` points to, `u` goes around from 0 to 1 and `v` from the inner edge out.
+ */
+#[derive(Debug, Clone)]
+pub struct Disk {
+    center: Vec3,
+    normal: Vec3,
+    radius: f32,
+    inner_radius: f32,
+    tangent_u: Vec3,
+    tangent_v: Vec3,
+}
+
+impl Disk {
+    /*
+     * Panics if `normal` is zero or unless `0 <= inner_radius < radius`
+     */
+    pub fn new(
+        center: Vec3,
+        normal: Vec3,
+        radius: f32,
+        inner_radius: f32,
+    ) -> Disk {
+        assert!(normal.norm_squared() > 0.0, "disk normal must not be zero");
+        assert!(
+            0.0 <= inner_radius && inner_radius < radius,
+            "disk radii must satisfy 0 <= inner_radius < radius"
+        );
+
+        let normal = normal.normalized();
+        let (tangent_u, tangent_v) = normal.orthonormal_basis();
+
+        Disk {
+            center,
+            normal,
+            radius,
+            inner_radius,
+            tangent_u,
+            tangent_v,
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        self.center
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn inner_radius(&self) -> f32 {
+        self.inner_radius
+    }
+
+    pub fn area(&self) -> f32 {
+        PI * (self.radius * self.radius - self.inner_radius * self.inner_radius)
+    }
+
+    fn intersect(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<f32> {
+        let denominator = Vec3::dot(self.normal, *ray.direction());
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = Vec3::dot(self.normal, self.center - *ray.origin()) / denominator;
+        if !(near < t && t < far) {
+            return None;
+        }
+
+        let distance_squared = (ray.at(t) - self.center).norm_squared();
+        let (outer, inner) = (self.radius, self.inner_radius);
+        if distance_squared > outer * outer || distance_squared < inner * inner {
+            return None;
+        }
+
+        Some(t)
+    }
+
+    fn shape_hit(
+        &self,
+        ray: &Ray,
+        t: f32,
+    ) -> ShapeHit {
+        let point = ray.at(t);
+        let offset = point - self.center;
+        let is_front_face = Vec3::dot(self.normal, *ray.direction()) < 0.0;
+
+        let distance = offset.norm();
+        let v = (distance - self.inner_radius) / (self.radius - self.inner_radius);
+
+        ShapeHit {
+            point,
+            normal: if is_front_face {
+                self.normal
+            } else {
+                -self.normal
+            },
+            t,
+            is_front_face,
+            u: azimuth(
+                Vec3::dot(offset, self.tangent_u),
+                Vec3::dot(offset, self.tangent_v),
+            ),
+            v: v.clamp(0.0, 1.0),
+        }
+    }
+
+    // area density at distance `t` along unit `direction` to solid angle density
+    fn solid_angle_pdf(
+        &self,
+        direction: Vec3,
+        t: f32,
+    ) -> f32 {
+        let cosine = Vec3::dot(self.normal, direction).abs();
+        if cosine < 1e-6 {
+            return 0.0;
+        }
+
+        t * t / (cosine * self.area())
+    }
+}
+
+impl HittableShape for Disk {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        let t = self.intersect(ray, near, far)?;
+        Some(self.shape_hit(ray, t))
+    }
+
+    // flat along the normal, padded like quads
+    fn bounding_box(&self) -> Option<Aabb> {
+        let n = self.normal;
+        let extent = |n: f32| self.radius * (1.0 - n * n).max(0.0).sqrt() + 1e-4;
+        let half = Vec3::new(extent(n.x), extent(n.y), extent(n.z));
+
+        Some(Aabb::new(self.center - half, self.center + half))
+    }
+
+    /*
+     * Uniform over the area, radius squared is uniform between the edges
+     */
+    fn sample(
+        &self,
+        origin: Vec3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ShapeSample> {
+        let (u1, u2) = sampler.get_2d();
+        let (outer, inner) = (self.radius, self.inner_radius);
+
+        let r = (inner * inner + u1 * (outer * outer - inner * inner)).sqrt();
+        let phi = 2.0 * PI * u2;
+        let point =
+            self.center + (r * phi.cos()) * self.tangent_u + (r * phi.sin()) * self.tangent_v;
+
+        let ray = Ray::with_time(origin, point - origin, time);
+        let t = (point - origin).norm();
+
+        let pdf = self.solid_angle_pdf(*ray.direction(), t);
+        if pdf == 0.0 || !pdf.is_finite() {
+            return None;
+        }
+
+        Some(ShapeSample {
+            hit: self.shape_hit(&ray, t),
+            pdf,
+        })
+    }
+
+    fn pdf(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        time: f32,
+    ) -> f32 {
+        let ray = Ray::with_time(origin, direction, time);
+
+        match self.intersect(&ray, 0.0, f32::INFINITY) {
+            Some(t) => self.solid_angle_pdf(*ray.direction(), t),
+            None => 0.0,
+        }
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Disk {
+            center: self.center.into(),
+            normal: self.normal.into(),
+            radius: self.radius,
+            inner_radius: self.inner_radius,
+        })
+    }
+}
+
+/*
+ * Cylinder of `radius` standing on `base` and going `height` up the y axis, turn it with an
+ * instance. Capped cylinders are closed by disks, open ones are tubes. On the side `u` goes
+ * around the axis like on spheres and `v` up, caps have disk uvs.
+ */
+#[derive(Debug, Clone)]
+pub struct Cylinder {
+    base: Vec3,
+    radius: f32,
+    height: f32,
+    // bottom and top, facing out
+    caps: Option<[Disk; 2]>,
+}
+
+impl Cylinder {
+    /*
+     * Panics unless `radius` and `height` are positive
+     */
+    pub fn new(
+        base: Vec3,
+        radius: f32,
+        height: f32,
+        capped: bool,
+    ) -> Cylinder {
+        assert!(
+            radius > 0.0 && height > 0.0,
+            "cylinder radius and height must be positive"
+        );
+
+        let caps = capped.then(|| {
+            [
+                Disk::new(base, -Vec3::Y, radius, 0.0),
+                Disk::new(base + height * Vec3::Y, Vec3::Y, radius, 0.0),
+            ]
+        });
+
+        Cylinder {
+            base,
+            radius,
+            height,
+            caps,
+        }
+    }
+
+    pub fn base(&self) -> Vec3 {
+        self.base
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    pub fn is_capped(&self) -> bool {
+        self.caps.is_some()
+    }
+
+    fn side_hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        let o = *ray.origin() - self.base;
+        let d = *ray.direction();
+
+        let a = d.x * d.x + d.z * d.z;
+        let b = 2.0 * (o.x * d.x + o.z * d.z);
+        let c = o.x * o.x + o.z * o.z - self.radius * self.radius;
+
+        let t = solve_quadratic(a as f64, b as f64, c as f64)
+            .into_iter()
+            .map(|t| t as f32)
+            .find(|&t| {
+                let y = o.y + t * d.y;
+                near < t && t < far && (0.0..=self.height).contains(&y)
+            })?;
+
+        let point = ray.at(t);
+        let offset = point - self.base;
+
+        // from the axis rather than divided by the radius, which the hit is only near
+        let normal = Vec3::new(offset.x, 0.0, offset.z).normalized();
+        let is_front_face = Vec3::dot(normal, *ray.direction()) < 0.0;
+
+        Some(ShapeHit {
+            point,
+            normal: if is_front_face { normal } else { -normal },
+            t,
+            is_front_face,
+            u: azimuth(offset.x, offset.z),
+            v: (offset.y / self.height).clamp(0.0, 1.0),
+        })
+    }
+}
+
+impl HittableShape for Cylinder {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        let mut nearest = self.side_hit(ray, near, far);
+        let mut far = nearest.as_ref().map_or(far, |hit| hit.t);
+
+        for cap in self.caps.iter().flatten() {
+            if let Some(hit) = cap.hit(ray, near, far) {
+                far = hit.t;
+                nearest = Some(hit);
+            }
+        }
+
+        nearest
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.radius;
+        Some(Aabb::new(
+            self.base - Vec3::new(r, 0.0, r),
+            self.base + Vec3::new(r, self.height, r),
+        ))
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Cylinder {
+            base: self.base.into(),
+            radius: self.radius,
+            height: self.height,
+            capped: self.is_capped(),
+        })
+    }
+}
+
+/*
+ * Cone with a base of `radius` around `base` and its apex `height` up the y axis, turn it with
+ * an instance. A capped cone is closed by a disk at the base. On the side `u` goes around the
+ * axis like on spheres and `v` up to the apex, the cap has disk uvs.
+ */
+#[derive(Debug, Clone)]
+pub struct Cone {
+    base: Vec3,
+    radius: f32,
+    height: f32,
+    cap: Option<Disk>,
+}
+
+impl Cone {
+    /*
+     * Panics unless `radius` and `height` are positive
+     */
+    pub fn new(
+        base: Vec3,
+        radius: f32,
+        height: f32,
+        capped: bool,
+    ) -> Cone {
+        assert!(
+            radius > 0.0 && height > 0.0,
+            "cone radius and height must be positive"
+        );
+
+        Cone {
+            base,
+            radius,
+            height,
+            cap: capped.then(|| Disk::new(base, -Vec3::Y, radius, 0.0)),
+        }
+    }
+
+    pub fn base(&self) -> Vec3 {
+        self.base
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    pub fn is_capped(&self) -> bool {
+        self.cap.is_some()
+    }
+
+    fn side_hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        let o = *ray.origin() - self.base;
+        let d = *ray.direction();
+
+        // distance from the axis is `k` times the height left to the apex
+        let k = self.radius / self.height;
+        let kk = k * k;
+        let to_apex = self.height - o.y;
+
+        let a = d.x * d.x + d.z * d.z - kk * d.y * d.y;
+        let b = 2.0 * (o.x * d.x + o.z * d.z + kk * to_apex * d.y);
+        let c = o.x * o.x + o.z * o.z - kk * to_apex * to_apex;
+
+        // the mirrored cone above the apex solves the same equation
+        let t = solve_quadratic(a as f64, b as f64, c as f64)
+            .into_iter()
+            .map(|t| t as f32)
+            .find(|&t| {
+                let y = o.y + t * d.y;
+                near < t && t < far && (0.0..=self.height).contains(&y)
+            })?;
+
+        let point = ray.at(t);
+        let offset = point - self.base;
+
+        // slope of the side tilts the normal up, straight up at the apex
+        let radial = Vec3::new(offset.x, 0.0, offset.z);
+        let normal = if radial.norm_squared() > 0.0 {
+            (self.height * radial.normalized() + self.radius * Vec3::Y).normalized()
+        } else {
+            Vec3::Y
+        };
+        let is_front_face = Vec3::dot(normal, *ray.direction()) < 0.0;
+
+        Some(ShapeHit {
+            point,
+            normal: if is_front_face { normal } else { -normal },
+            t,
+            is_front_face,
+            u: azimuth(offset.x, offset.z),
+            v: (offset.y / self.height).clamp(0.0, 1.0),
+        })
+    }
+}
+
+impl HittableShape for Cone {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        let nearest = self.side_hit(ray, near, far);
+        let far = nearest.as_ref().map_or(far, |hit| hit.t);
+
+        match self.cap.as_ref().and_then(|cap| cap.hit(ray, near, far)) {
+            Some(hit) => Some(hit),
+            None => nearest,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.radius;
+        Some(Aabb::new(
+            self.base - Vec3::new(r, 0.0, r),
+            self.base + Vec3::new(r, self.height, r),
+        ))
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Cone {
+            base: self.base.into(),
+            radius: self.radius,
+            height: self.height,
+            capped: self.is_capped(),
+        })
+    }
+}
+
+/*
+ * Torus around the y axis through `center`, a tube of `minor_radius` around a circle of
+ * `major_radius`. `u` goes around the axis like on spheres and `v` around the tube.
+ */
+#[derive(Debug, Clone)]
+pub struct Torus {
+    center: Vec3,
+    major_radius: f32,
+    minor_radius: f32,
+}
+
+impl Torus {
+    /*
+     * Panics unless both radii are positive
+     */
+    pub fn new(
+        center: Vec3,
+        major_radius: f32,
+        minor_radius: f32,
+    ) -> Torus {
+        assert!(
+            major_radius > 0.0 && minor_radius > 0.0,
+            "torus radii must be positive"
+        );
+
+        Torus {
+            center,
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        self.center
+    }
+
+    pub fn major_radius(&self) -> f32 {
+        self.major_radius
+    }
+
+    pub fn minor_radius(&self) -> f32 {
+        self.minor_radius
+    }
+
+    /*
+     * Nearest distance along `ray` in range, from the quartic
+     * `(|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2)` solved in double precision
+     */
+    fn intersect(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<f32> {
+        let to_f64 = |v: Vec3| [v.x as f64, v.y as f64, v.z as f64];
+        let dot = |a: [f64; 3], b: [f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+
+        let o = to_f64(*ray.origin() - self.center);
+        let d = to_f64(*ray.direction());
+        let (major, minor) = (self.major_radius as f64, self.minor_radius as f64);
+
+        // start the ray at the bounding sphere, roots far from the origin lose precision
+        let bound = major + minor;
+        let entry = match solve_quadratic(1.0, 2.0 * dot(o, d), dot(o, o) - bound * bound)[..] {
+            [t0, t1] if t1 > near as f64 && t0 < far as f64 => t0.max(0.0),
+            _ => return None,
+        };
+        let o = [0, 1, 2].map(|i| o[i] + entry * d[i]);
+
+        let od = dot(o, d);
+        let k = dot(o, o) + major * major - minor * minor;
+        let rr4 = 4.0 * major * major;
+
+        let roots = solve_quartic(
+            1.0,
+            4.0 * od,
+            4.0 * od * od + 2.0 * k - rr4 * (d[0] * d[0] + d[2] * d[2]),
+            4.0 * od * k - 2.0 * rr4 * (o[0] * d[0] + o[2] * d[2]),
+            k * k - rr4 * (o[0] * o[0] + o[2] * o[2]),
+        );
+
+        roots
+            .into_iter()
+            .map(|t| (t + entry) as f32)
+            .find(|&t| near < t && t < far)
+    }
+}
+
+impl HittableShape for Torus {
+    fn hit(
+        &self,
+        ray: &Ray,
+        near: f32,
+        far: f32,
+    ) -> Option<ShapeHit> {
+        let t = self.intersect(ray, near, far)?;
+
+        let point = ray.at(t);
+        let offset = point - self.center;
+
+        // away from the nearest point of the circle inside the tube
+        let radial = Vec3::new(offset.x, 0.0, offset.z).normalized();
+        let normal = (offset - self.major_radius * radial).normalized();
+        let is_front_face = Vec3::dot(normal, *ray.direction()) < 0.0;
+
+        let tube_angle = offset
+            .y
+            .atan2(Vec3::dot(offset, radial) - self.major_radius);
+
+        Some(ShapeHit {
+            point,
+            normal: if is_front_face { normal } else { -normal },
+            t,
+            is_front_face,
+            u: azimuth(offset.x, offset.z),
+            v: (tube_angle + PI) / (2.0 * PI),
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        let half = Vec3::new(outer, self.minor_radius, outer);
+        Some(Aabb::new(self.center - half, self.center + half))
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Torus {
+            center: self.center.into(),
+            major_radius: self.major_radius,
+            minor_radius: self.minor_radius,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sphere_hit_from_inside_takes_far_root() {
+        let sphere = Sphere {
+            center: Vec3::new(0.0, 0.0, -2.0),
+            radius: 1.0,
+        };
+
+        // from the center only the far root is ahead
+        let hit = sphere
+            .hit(
+                &Ray::new(Vec3::new(0.0, 0.0, -2.0), Vec3::X),
+                0.001,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5);
+        assert!(!hit.is_front_face);
+
+        // leaving the surface the near root is ahead of the origin but below `near`
+        let hit = sphere
+            .hit(
+                &Ray::new(Vec3::new(0.0, 0.0, -1.0005), -Vec3::Z),
+                0.001,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 1.9995).abs() < 1e-4);
+        assert!(!hit.is_front_face);
+        assert!((hit.normal - Vec3::Z).norm() < 1e-5);
+    }
+
+    #[test]
+    fn quad_hit_has_edge_uvs_and_faces_cross_product() {
+        let quad = Quad::new(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 4.0, 0.0),
+        );
+
+        let hit = quad
+            .hit(
+                &Ray::new(Vec3::new(0.5, 2.0, 3.0), Vec3::new(0.0, 0.0, -1.0)),
+                0.001,
+                f32::INFINITY,
+            )
+            .unwrap();
+
+        assert!((hit.t - 3.0).abs() < 1e-5);
+        assert!((hit.u - 0.75).abs() < 1e-5 && (hit.v - 0.75).abs() < 1e-5);
+        assert!(hit.is_front_face);
+        assert!((hit.normal - Vec3::Z).norm() < 1e-5);
+
+        // from behind the normal still faces the ray
+        let hit = quad
+            .hit(
+                &Ray::new(Vec3::new(0.5, 2.0, -3.0), Vec3::Z),
+                0.001,
+                f32::INFINITY,
+            )
+            .unwrap();
+        assert!(!hit.is_front_face);
+        assert!((hit.normal + Vec3::Z).norm() < 1e-5);
+
+        // beside the quad, inside its plane's other half
+        assert!(quad
+            .hit(
+                &Ray::new(Vec3::new(1.5, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0)),
+                0.001,
+                f32::INFINITY,
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn box_faces_point_out() {
+        let cuboid = Cuboid::new(Vec3::new(-1.0, -2.0, -3.0), Vec3::new(1.0, 2.0, 3.0));
+
+        for direction in [Vec3::X, Vec3::Y, Vec3::Z, -Vec3::X, -Vec3::Y, -Vec3::Z] {
+            // from outside towards the center
+            let hit = cuboid
+                .hit(
+                    &Ray::new(10.0 * direction, -direction),
+                    0.001,
+                    f32::INFINITY,
+                )
+                .unwrap();
+            assert!(hit.is_front_face);
+            assert!((hit.normal - direction).norm() < 1e-5);
+
+            // from the center out
+            let hit = cuboid
+                .hit(&Ray::new(Vec3::ZERO, direction), 0.001, f32::INFINITY)
+                .unwrap();
+            assert!(!hit.is_front_face);
+            assert!((hit.normal + direction).norm() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn box_sample_pdf_matches_pdf_and_integrates_to_one() {
+        let cuboid = Cuboid::new(Vec3::new(-1.0, 0.0, -0.5), Vec3::new(0.5, 2.0, 1.0));
+        let origin = Vec3::new(2.0, 3.0, 1.5);
+
+        let sample_count = 1 << 16;
+        let mut sampler = SamplerKind::Sobol.create(sample_count, 3);
+
+        let mut integral = 0.0;
+
+        for index in 0..sample_count {
+            sampler.start_sample(0, 0, index);
+
+            // hidden faces are sampled too, their direction has the visible face's density added
+            if let Some(sample) = cuboid.sample(origin, 0.0, &mut *sampler) {
+                let direction = (sample.hit.point - origin).normalized();
+                let pdf = cuboid.pdf(origin, direction, 0.0);
+                assert!(pdf >= sample.pdf * (1.0 - 1e-3), "{} < {}", pdf, sample.pdf);
+            }
+
+            let direction = sample_unit_sphere(sampler.get_2d());
+            integral += 4.0 * PI * cuboid.pdf(origin, direction, 0.0);
+        }
+
+        let integral = integral / sample_count as f32;
+        assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+    }
+
+    #[test]
+    fn plane_is_hit_from_both_sides() {
+        let plane = Plane::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 2.0, 0.0));
+        assert!(plane.bounding_box().is_none());
+
+        let ray = Ray::new(Vec3::new(5.0, 1.0, -7.0), Vec3::new(1.0, -1.0, 0.0));
+        let hit = plane.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!(hit.is_front_face);
+        assert!((hit.point.y + 1.0).abs() < 1e-5);
+        assert!((hit.normal - Vec3::Y).norm() < 1e-5);
+
+        let ray = Ray::new(Vec3::new(5.0, -3.0, -7.0), Vec3::new(1.0, 1.0, 0.0));
+        let hit = plane.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!(!hit.is_front_face);
+        assert!((hit.normal + Vec3::Y).norm() < 1e-5);
+
+        // parallel and pointing away
+        assert!(plane
+            .hit(&Ray::new(Vec3::ZERO, Vec3::X), 0.001, f32::INFINITY)
+            .is_none());
+        assert!(plane
+            .hit(&Ray::new(Vec3::ZERO, Vec3::Y), 0.001, f32::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn annulus_has_a_hole_and_its_sample_pdf_integrates_to_one() {
+        let annulus = Disk::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 1.0, 0.0), 1.0, 0.5);
+
+        // through the middle of the hole, then through the ring
+        let ray = Ray::new(Vec3::new(3.0, 4.0, 0.0), Vec3::new(-1.0, -1.0, 0.0));
+        assert!(annulus.hit(&ray, 0.001, f32::INFINITY).is_none());
+
+        let ray = Ray::new(Vec3::new(3.0, 4.0, 0.75), Vec3::new(-1.0, -1.0, 0.0));
+        let hit = annulus.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!(hit.is_front_face);
+        assert!((hit.v - 0.5).abs() < 1e-4, "{}", hit.v);
+
+        let origin = Vec3::new(1.0, 3.0, -1.0);
+
+        let sample_count = 1 << 16;
+        let mut sampler = SamplerKind::Sobol.create(sample_count, 5);
+
+        let mut integral = 0.0;
+
+        for index in 0..sample_count {
+            sampler.start_sample(0, 0, index);
+
+            let sample = annulus.sample(origin, 0.0, &mut *sampler).unwrap();
+            let direction = (sample.hit.point - origin).normalized();
+            let pdf = annulus.pdf(origin, direction, 0.0);
+            assert!(
+                (pdf - sample.pdf).abs() <= 1e-3 * pdf,
+                "{} != {}",
+                pdf,
+                sample.pdf
+            );
+
+            let direction = sample_unit_sphere(sampler.get_2d());
+            integral += 4.0 * PI * annulus.pdf(origin, direction, 0.0);
+        }
+
+        let integral = integral / sample_count as f32;
+        assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+    }
+
+    #[test]
+    fn cylinder_side_and_caps() {
+        let base = Vec3::new(1.0, -1.0, 0.0);
+        let capped = Cylinder::new(base, 0.5, 2.0, true);
+        let open = Cylinder::new(base, 0.5, 2.0, false);
+
+        let ray = Ray::new(Vec3::new(-4.0, 0.5, 0.0), Vec3::X);
+        for cylinder in [&capped, &open] {
+            let hit = cylinder.hit(&ray, 0.001, f32::INFINITY).unwrap();
+            assert!((hit.t - 4.5).abs() < 1e-4 && hit.is_front_face);
+            assert!((hit.normal + Vec3::X).norm() < 1e-5);
+            assert!((hit.u - 0.0).abs() < 1e-5 && (hit.v - 0.75).abs() < 1e-5);
+        }
+
+        // down the axis the caps are hit, the open tube is seen through
+        let ray = Ray::new(Vec3::new(1.1, 5.0, 0.0), -Vec3::Y);
+        let hits = capped.hits(&ray, 0.001, f32::INFINITY);
+        assert_eq!(hits.len(), 2);
+        assert!((hits[0].t - 4.0).abs() < 1e-4 && hits[0].is_front_face);
+        assert!((hits[1].t - 6.0).abs() < 1e-4 && !hits[1].is_front_face);
+        assert!((hits[1].normal - Vec3::Y).norm() < 1e-5);
+        assert!(open.hit(&ray, 0.001, f32::INFINITY).is_none());
+
+        // into the open end, hitting the inside of the side
+        let ray = Ray::new(Vec3::new(1.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.2));
+        let hit = open.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!(!hit.is_front_face);
+        assert!((hit.normal + Vec3::Z).norm() < 1e-5);
+    }
+
+    #[test]
+    fn cone_normals_are_perpendicular_to_the_side() {
+        let cone = Cone::new(Vec3::ZERO, 1.0, 2.0, true);
+
+        let ray = Ray::new(Vec3::new(-5.0, 1.0, 0.0), Vec3::X);
+        let hit = cone.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.point - Vec3::new(-0.5, 1.0, 0.0)).norm() < 1e-4);
+        assert!(hit.is_front_face);
+
+        // the side runs from the rim to the apex
+        let slope = Vec3::new(0.0, 2.0, 0.0) - Vec3::new(-1.0, 0.0, 0.0);
+        assert!(Vec3::dot(hit.normal, slope).abs() < 1e-5);
+        assert!(hit.normal.x < 0.0 && hit.normal.y > 0.0);
+
+        // the mirrored cone above the apex isn't there
+        let ray = Ray::new(Vec3::new(-5.0, 3.0, 0.0), Vec3::X);
+        assert!(cone.hit(&ray, 0.001, f32::INFINITY).is_none());
+
+        // from below through the cap
+        let ray = Ray::new(Vec3::new(0.2, -1.0, 0.0), Vec3::Y);
+        let hit = cone.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-5 && hit.is_front_face);
+        assert!((hit.normal + Vec3::Y).norm() < 1e-5);
+    }
+
+    #[test]
+    fn torus_is_crossed_four_times_through_the_hole() {
+        let torus = Torus::new(Vec3::new(0.0, 1.0, 0.0), 2.0, 0.5);
+
+        // across the whole ring from far away, where roots lose precision without the shift
+        let ray = Ray::new(Vec3::new(-1000.0, 1.0, 0.0), Vec3::X);
+        let hits = torus.hits(&ray, 0.001, f32::INFINITY);
+        let expected = [997.5, 998.5, 1001.5, 1002.5];
+        assert_eq!(hits.len(), 4);
+        for (i, (hit, t)) in hits.iter().zip(expected).enumerate() {
+            assert!((hit.t - t).abs() < 1e-3, "{} != {}", hit.t, t);
+            assert_eq!(hit.is_front_face, i % 2 == 0);
+        }
+        assert!((hits[0].normal + Vec3::X).norm() < 1e-4);
+        assert!((hits[1].normal + Vec3::X).norm() < 1e-4);
+
+        // down through the hole, and grazing the top of the tube
+        let ray = Ray::new(Vec3::new(0.0, 5.0, 0.0), -Vec3::Y);
+        assert!(torus.hit(&ray, 0.001, f32::INFINITY).is_none());
+
+        let ray = Ray::new(Vec3::new(0.0, 5.0, 2.0), -Vec3::Y);
+        let hit = torus.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 3.5).abs() < 1e-4);
+        assert!((hit.normal - Vec3::Y).norm() < 1e-4);
+        assert!((hit.v - 0.75).abs() < 1e-4, "{}", hit.v);
+    }
+
+    #[test]
+    fn round_shapes_fit_their_bounding_boxes() {
+        let shapes: [&dyn HittableShape; 4] = [
+            &Disk::new(Vec3::ZERO, Vec3::new(1.0, 2.0, -1.0), 1.5, 0.0),
+            &Cylinder::new(Vec3::ZERO, 1.0, 1.5, true),
+            &Cone::new(Vec3::ZERO, 1.0, 1.5, false),
+            &Torus::new(Vec3::ZERO, 1.0, 0.5),
+        ];
+
+        for shape in shapes {
+            let bounds = shape.bounding_box().unwrap();
+
+            for i in 0..256 {
+                let direction =
+                    sample_unit_sphere(((i % 16) as f32 / 16.0, (i / 16) as f32 / 16.0));
+                let ray = Ray::new(-5.0 * direction, direction + Vec3::new(0.01, 0.02, 0.0));
+                for hit in shape.hits(&ray, 0.001, f32::INFINITY) {
+                    let p = hit.point;
+                    for axis in 0..3 {
+                        assert!(
+                            bounds.min[axis] - 1e-4 <= p[axis]
+                                && p[axis] <= bounds.max[axis] + 1e-4
+                        );
+                    }
+                    assert!((hit.normal.norm() - 1.0).abs() < 1e-4);
+                    assert!(Vec3::dot(hit.normal, *ray.direction()) <= 0.0);
+                }
+            }
+        }
+    }
+}
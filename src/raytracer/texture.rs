@@ -0,0 +1,633 @@
+use crate::cgmath::*;
+use crate::raytracer::color::*;
+use crate::raytracer::description::{ColorSource, TextureDescription};
+
+use anyhow::{bail, Context};
+
+use rand::{seq::SliceRandom, RngCore, SeedableRng};
+
+use rand_pcg::Pcg32;
+
+use serde::{Deserialize, Serialize};
+
+use std::{
+    fmt::{self, Debug},
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+//
+//
+//
+pub trait Texture: Send + Sync + Debug {
+    // colour at surface coordinates `u`, `v` of `point`
+    fn value(
+        &self,
+        u: f32,
+        v: f32,
+        point: Vec3,
+    ) -> Color;
+
+    // how to write the texture to a scene file, `None` if it can't be
+    fn description(&self) -> Option<TextureDescription> {
+        None
+    }
+}
+
+//
+//
+//
+#[derive(Debug, Clone)]
+pub struct ConstantTexture {
+    pub color: Color,
+}
+
+impl ConstantTexture {
+    pub fn new(color: Color) -> ConstantTexture {
+        ConstantTexture { color }
+    }
+}
+
+impl Texture for ConstantTexture {
+    fn value(
+        &self,
+        _u: f32,
+        _v: f32,
+        _point: Vec3,
+    ) -> Color {
+        self.color
+    }
+
+    fn description(&self) -> Option<TextureDescription> {
+        Some(TextureDescription::Constant {
+            color: self.color.into(),
+        })
+    }
+}
+
+//
+//
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckerSpace {
+    // cubes in world space, the pattern doesn't depend on how a shape is parametrized
+    #[default]
+    Solid,
+    // squares in uv space
+    Uv,
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckerTexture {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    // side of one square, in world units or in uv units
+    pub size: f32,
+    pub space: CheckerSpace,
+}
+
+impl CheckerTexture {
+    pub fn new(
+        even: Arc<dyn Texture>,
+        odd: Arc<dyn Texture>,
+        size: f32,
+        space: CheckerSpace,
+    ) -> CheckerTexture {
+        CheckerTexture {
+            even,
+            odd,
+            size,
+            space,
+        }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(
+        &self,
+        u: f32,
+        v: f32,
+        point: Vec3,
+    ) -> Color {
+        let cell = |x: f32| (x / self.size).floor() as i64;
+
+        let sum = match self.space {
+            CheckerSpace::Solid => cell(point.x) + cell(point.y) + cell(point.z),
+            CheckerSpace::Uv => cell(u) + cell(v),
+        };
+
+        if sum.rem_euclid(2) == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+
+    fn description(&self) -> Option<TextureDescription> {
+        Some(TextureDescription::Checker {
+            even: ColorSource::from_texture(&*self.even)?,
+            odd: ColorSource::from_texture(&*self.odd)?,
+            size: self.size,
+            space: self.space,
+        })
+    }
+}
+
+//
+//
+//
+
+// what happens to uv outside of [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    fn apply(
+        &self,
+        i: i64,
+        size: usize,
+    ) -> usize {
+        let size = size as i64;
+
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Clamp => i.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+        };
+
+        i as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureFilter {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+/*
+ * Texture from an image file, `v = 0` is the bottom row.
+ *
+ * 8 bit images are stored with the same gamma 2 that rendered images are written with, so they
+ * are converted to linear on load. HDR images are already linear.
+ */
+#[derive(Clone)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    // linear, row major, top row first
+    texels: Vec<Color>,
+    wrap: WrapMode,
+    filter: TextureFilter,
+    // file it was loaded from, for writing scene files
+    path: Option<PathBuf>,
+}
+
+impl ImageTexture {
+    pub fn new(
+        width: usize,
+        height: usize,
+        texels: Vec<Color>,
+        wrap: WrapMode,
+        filter: TextureFilter,
+    ) -> ImageTexture {
+        assert!(width > 0 && height > 0);
+        assert_eq!(texels.len(), width * height);
+
+        ImageTexture {
+            width,
+            height,
+            texels,
+            wrap,
+            filter,
+            path: None,
+        }
+    }
+
+    /*
+     * Loads anything the `image` crate reads, `.hdr` files keep their full range
+     */
+    pub fn load(
+        path: &Path,
+        wrap: WrapMode,
+        filter: TextureFilter,
+    ) -> anyhow::Result<ImageTexture> {
+        let is_hdr = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("hdr"));
+
+        let load = || -> anyhow::Result<(usize, usize, Vec<Color>)> {
+            if is_hdr {
+                let file = BufReader::new(File::open(path)?);
+                let decoder = image::codecs::hdr::HdrDecoder::new(file)?;
+                let metadata = decoder.metadata();
+                let texels = decoder
+                    .read_image_hdr()?
+                    .into_iter()
+                    .map(|p| Color::from_rgb(p[0], p[1], p[2]))
+                    .collect();
+
+                Ok((metadata.width as usize, metadata.height as usize, texels))
+            } else {
+                let image = image::open(path)?.into_rgb8();
+                let to_linear = |c: u8| {
+                    let c = c as f32 / 255.0;
+                    c * c
+                };
+                let texels = image
+                    .pixels()
+                    .map(|p| Color::from_rgb(to_linear(p[0]), to_linear(p[1]), to_linear(p[2])))
+                    .collect();
+
+                Ok((image.width() as usize, image.height() as usize, texels))
+            }
+        };
+
+        let (width, height, texels) =
+            load().with_context(|| format!("cannot load texture {}", path.display()))?;
+
+        if width == 0 || height == 0 {
+            bail!("{} is empty", path.display());
+        }
+
+        Ok(ImageTexture {
+            path: Some(path.to_path_buf()),
+            ..ImageTexture::new(width, height, texels, wrap, filter)
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn texel(
+        &self,
+        x: i64,
+        y: i64,
+    ) -> Color {
+        let x = self.wrap.apply(x, self.width);
+        let y = self.wrap.apply(y, self.height);
+        self.texels[y * self.width + x]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(
+        &self,
+        u: f32,
+        v: f32,
+        _point: Vec3,
+    ) -> Color {
+        // texel centers are at half integers
+        let x = u * self.width as f32;
+        let y = (1.0 - v) * self.height as f32;
+
+        match self.filter {
+            TextureFilter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            TextureFilter::Bilinear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = (1.0 - fx) * self.texel(x0, y0) + fx * self.texel(x0 + 1, y0);
+                let bottom = (1.0 - fx) * self.texel(x0, y0 + 1) + fx * self.texel(x0 + 1, y0 + 1);
+
+                (1.0 - fy) * top + fy * bottom
+            }
+        }
+    }
+
+    fn description(&self) -> Option<TextureDescription> {
+        Some(TextureDescription::Image {
+            path: self.path.clone()?,
+            wrap: self.wrap,
+            filter: self.filter,
+        })
+    }
+}
+
+impl Debug for ImageTexture {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        f.debug_struct("ImageTexture")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("wrap", &self.wrap)
+            .field("filter", &self.filter)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+//
+//
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseKind {
+    // smooth gray noise
+    Perlin,
+    // sum of octaves of absolute noise
+    Turbulence,
+    // stripes along z distorted by turbulence
+    Marble,
+}
+
+#[derive(Debug, Clone)]
+pub struct NoiseTexture {
+    kind: NoiseKind,
+    // frequency of the noise in world space, for marble only of the stripes
+    scale: f32,
+    seed: u64,
+    perlin: Perlin,
+}
+
+impl NoiseTexture {
+    pub fn new(
+        kind: NoiseKind,
+        scale: f32,
+        seed: u64,
+    ) -> NoiseTexture {
+        NoiseTexture {
+            kind,
+            scale,
+            seed,
+            perlin: Perlin::new(&mut Pcg32::seed_from_u64(seed)),
+        }
+    }
+
+    pub fn kind(&self) -> NoiseKind {
+        self.kind
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(
+        &self,
+        _u: f32,
+        _v: f32,
+        point: Vec3,
+    ) -> Color {
+        let p = self.scale * point;
+
+        let value = match self.kind {
+            NoiseKind::Perlin => 0.5 * (1.0 + self.perlin.noise(p)),
+            NoiseKind::Turbulence => self.perlin.turbulence(p, 7),
+            NoiseKind::Marble => {
+                // large scale turbulence, scaling it too would turn veins into speckles
+                let phase = p.z + 10.0 * self.perlin.turbulence(point, 7);
+                0.5 * (1.0 + phase.sin())
+            }
+        };
+
+        Color::from_rgb(value, value, value)
+    }
+
+    fn description(&self) -> Option<TextureDescription> {
+        Some(TextureDescription::Noise {
+            noise: self.kind,
+            scale: self.scale,
+            seed: self.seed,
+        })
+    }
+}
+
+const PERLIN_POINT_COUNT: usize = 256;
+
+/*
+ * Gradient noise over a lattice of random unit vectors, in [-1, 1]
+ */
+#[derive(Clone)]
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    permutations: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    pub fn new(rng: &mut dyn RngCore) -> Perlin {
+        let gradients = (0..PERLIN_POINT_COUNT)
+            .map(|_| Vec3::random_range(rng, -1.0..1.0).normalized())
+            .collect();
+
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..PERLIN_POINT_COUNT).collect();
+            p.shuffle(rng);
+            p
+        };
+
+        Perlin {
+            gradients,
+            permutations: [permutation(), permutation(), permutation()],
+        }
+    }
+
+    pub fn noise(
+        &self,
+        p: Vec3,
+    ) -> f32 {
+        let (i, j, k) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - i, p.y - j, p.z - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+
+        // hermite smoothing hides the lattice
+        let (uu, vv, ww) = (
+            u * u * (3.0 - 2.0 * u),
+            v * v * (3.0 - 2.0 * v),
+            w * w * (3.0 - 2.0 * w),
+        );
+
+        let mask = PERLIN_POINT_COUNT as i64 - 1;
+        let [px, py, pz] = &self.permutations;
+
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = px[((i + di) & mask) as usize]
+                        ^ py[((j + dj) & mask) as usize]
+                        ^ pz[((k + dk) & mask) as usize];
+
+                    let (fi, fj, fk) = (di as f32, dj as f32, dk as f32);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+
+                    sum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * Vec3::dot(self.gradients[index], weight);
+                }
+            }
+        }
+
+        sum
+    }
+
+    // sum of `depth` octaves, each twice the frequency and half the weight of the previous one
+    pub fn turbulence(
+        &self,
+        p: Vec3,
+        depth: usize,
+    ) -> f32 {
+        let mut sum = 0.0;
+        let mut p = p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            sum += weight * self.noise(p);
+            weight *= 0.5;
+            p = 2.0 * p;
+        }
+
+        sum.abs()
+    }
+}
+
+impl Debug for Perlin {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        f.debug_struct("Perlin").finish_non_exhaustive()
+    }
+}
+
+//
+//
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: f32) -> Color {
+        Color::from_rgb(value, value, value)
+    }
+
+    // 2x2 image, top row 0 and 1, bottom row 2 and 3
+    fn two_by_two(
+        wrap: WrapMode,
+        filter: TextureFilter,
+    ) -> ImageTexture {
+        let texels = vec![gray(0.0), gray(1.0), gray(2.0), gray(3.0)];
+        ImageTexture::new(2, 2, texels, wrap, filter)
+    }
+
+    fn at(
+        texture: &dyn Texture,
+        u: f32,
+        v: f32,
+    ) -> f32 {
+        texture.value(u, v, Vec3::ZERO).r()
+    }
+
+    #[test]
+    fn nearest_picks_texel_under_uv() {
+        let texture = two_by_two(WrapMode::Repeat, TextureFilter::Nearest);
+
+        assert_eq!(at(&texture, 0.25, 0.75), 0.0);
+        assert_eq!(at(&texture, 0.75, 0.75), 1.0);
+        assert_eq!(at(&texture, 0.25, 0.25), 2.0);
+        assert_eq!(at(&texture, 0.75, 0.25), 3.0);
+    }
+
+    #[test]
+    fn bilinear_is_exact_at_texel_centers_and_blends_between() {
+        let texture = two_by_two(WrapMode::Clamp, TextureFilter::Bilinear);
+
+        assert_eq!(at(&texture, 0.25, 0.75), 0.0);
+        assert_eq!(at(&texture, 0.75, 0.25), 3.0);
+
+        // halfway between all four centers
+        assert!((at(&texture, 0.5, 0.5) - 1.5).abs() < 1e-6);
+        // halfway between the top two
+        assert!((at(&texture, 0.5, 0.75) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn wrap_modes() {
+        let repeat = two_by_two(WrapMode::Repeat, TextureFilter::Nearest);
+        let clamp = two_by_two(WrapMode::Clamp, TextureFilter::Nearest);
+        let mirror = two_by_two(WrapMode::Mirror, TextureFilter::Nearest);
+
+        // one texel right of the image
+        assert_eq!(at(&repeat, 1.25, 0.75), 0.0);
+        assert_eq!(at(&clamp, 1.25, 0.75), 1.0);
+        assert_eq!(at(&mirror, 1.25, 0.75), 1.0);
+
+        // one texel left of the image
+        assert_eq!(at(&repeat, -0.25, 0.75), 1.0);
+        assert_eq!(at(&clamp, -0.25, 0.75), 0.0);
+        assert_eq!(at(&mirror, -0.25, 0.75), 0.0);
+
+        // second texel right of the image
+        assert_eq!(at(&repeat, 1.75, 0.75), 1.0);
+        assert_eq!(at(&mirror, 1.75, 0.75), 0.0);
+    }
+
+    #[test]
+    fn checker_alternates() {
+        let checker = CheckerTexture::new(
+            Arc::new(ConstantTexture::new(gray(0.0))),
+            Arc::new(ConstantTexture::new(gray(1.0))),
+            0.5,
+            CheckerSpace::Uv,
+        );
+
+        assert_eq!(at(&checker, 0.25, 0.25), 0.0);
+        assert_eq!(at(&checker, 0.75, 0.25), 1.0);
+        assert_eq!(at(&checker, 0.75, 0.75), 0.0);
+        assert_eq!(at(&checker, -0.25, 0.25), 1.0);
+    }
+
+    #[test]
+    fn noise_is_bounded_and_seeded() {
+        let a = NoiseTexture::new(NoiseKind::Perlin, 4.0, 1);
+        let b = NoiseTexture::new(NoiseKind::Perlin, 4.0, 1);
+        let c = NoiseTexture::new(NoiseKind::Perlin, 4.0, 2);
+
+        let mut differs = false;
+        for i in 0..1000 {
+            let p = Vec3::new(i as f32 * 0.013, i as f32 * 0.007, i as f32 * -0.011);
+
+            let value = a.value(0.0, 0.0, p).r();
+            assert!((0.0..=1.0).contains(&value));
+            assert_eq!(value, b.value(0.0, 0.0, p).r());
+
+            differs |= value != c.value(0.0, 0.0, p).r();
+        }
+
+        assert!(differs);
+    }
+}
@@ -0,0 +1,827 @@
+use std::collections::HashMap;
+
+use crate::raytracer::scene::Scene;
+
+use rand::{RngCore, SeedableRng};
+
+use rand_pcg::Pcg32;
+
+//
+//
+//
+// generators draw random numbers only from `rng`, so a seed reproduces the scene
+pub type SceneCreator = fn(&mut dyn RngCore) -> Scene;
+pub type SceneCreators = HashMap<&'static str, SceneCreator>;
+
+pub fn create_scene(
+    creator: SceneCreator,
+    seed: u64,
+) -> Scene {
+    creator(&mut Pcg32::seed_from_u64(seed))
+}
+
+pub fn scene_creators() -> SceneCreators {
+    let mut hash_map = HashMap::new();
+
+    hash_map.insert(
+        "Book 1 final scene",
+        make_book_1_final_scene as SceneCreator,
+    );
+
+    hash_map.insert(
+        "Triangle meshes",
+        make_triangle_meshes_scene as SceneCreator,
+    );
+
+    hash_map.insert("Simple light", make_simple_light_scene as SceneCreator);
+
+    hash_map.insert("Textures", make_textures_scene as SceneCreator);
+
+    hash_map.insert(
+        "Bouncing spheres",
+        make_bouncing_spheres_scene as SceneCreator,
+    );
+
+    hash_map.insert("Instances", make_instances_scene as SceneCreator);
+
+    hash_map.insert("Cornell box", make_cornell_box_scene as SceneCreator);
+
+    hash_map.insert("Cornell smoke", make_cornell_smoke_scene as SceneCreator);
+
+    hash_map.insert("Clouds", make_clouds_scene as SceneCreator);
+
+    hash_map
+}
+
+//
+//
+//
+fn make_book_1_final_scene(rng: &mut dyn RngCore) -> Scene {
+    make_random_spheres_scene(rng, false)
+}
+
+/*
+ * Book 1 final scene with diffuse spheres bouncing up during the shutter interval
+ */
+fn make_bouncing_spheres_scene(rng: &mut dyn RngCore) -> Scene {
+    use crate::raytracer::camera::*;
+
+    let mut scene = make_random_spheres_scene(rng, true);
+
+    scene.set_camera(CameraDescription {
+        shutter_open: 0.0,
+        shutter_close: 1.0,
+        ..*scene.camera()
+    });
+
+    scene
+}
+
+fn make_random_spheres_scene(
+    rng: &mut dyn RngCore,
+    bouncing: bool,
+) -> Scene {
+    use crate::cgmath::*;
+    use crate::raytracer::color::*;
+    use crate::raytracer::material::*;
+    use crate::raytracer::scene::*;
+    use crate::raytracer::shape::*;
+
+    use rand::Rng;
+
+    //
+    let mut scene = Scene::new();
+
+    // ground
+    {
+        let m = scene.insert_material(Lambertian::new(Color::from_rgb(0.5, 0.5, 0.5)));
+
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    // predefined spheres
+    {
+        let m = scene.insert_material(Dielectric::new(1.5));
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, 1.0, 0.0),
+            radius: 1.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    {
+        let m = scene.insert_material(Lambertian::new(Color::from_rgb(0.4, 0.2, 0.1)));
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(-4.0, 1.0, 0.0),
+            radius: 1.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    {
+        let m = scene.insert_material(Metal::new(Color::from_rgb(0.7, 0.6, 0.5), 0.0));
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(4.0, 1.0, 0.0),
+            radius: 1.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    // random spheres
+    {
+        let danger = Vec3::new(4.0, 0.2, 0.0);
+
+        for a in -11..11 {
+            for b in -11..11 {
+                let center = Vec3::new(
+                    a as f32 + 0.9 * rng.gen_range(0.0..1.0),
+                    0.2,
+                    b as f32 + 0.9 * rng.gen_range(0.0..1.0),
+                );
+
+                if (center - danger).norm() < 0.9 {
+                    continue;
+                }
+
+                let kind = rng.gen_range(0..3);
+                let is_diffuse = kind == 0;
+
+                let m = match kind {
+                    0 => {
+                        let albedo = Color::random(rng) * Color::random(rng);
+                        scene.insert_material(Lambertian::new(albedo))
+                    }
+                    1 => {
+                        let albedo = Color::from_rgb(0.5, 0.5, 0.5) + 0.5 * Color::random(rng);
+                        let fuzz = rng.gen_range(0.0..0.5);
+                        scene.insert_material(Metal::new(albedo, fuzz))
+                    }
+                    2 => scene.insert_material(Dielectric::new(rng.gen_range(1.1..1.9))),
+                    _ => unreachable!(),
+                };
+
+                // only diffuse spheres move, rng is untouched otherwise so the static scene
+                // doesn't change
+                let s = if bouncing && is_diffuse {
+                    scene.insert_shape(MovingSphere {
+                        center0: center,
+                        center1: center + Vec3::new(0.0, rng.gen_range(0.0..0.5), 0.0),
+                        time0: 0.0,
+                        time1: 1.0,
+                        radius: 0.2,
+                    })
+                } else {
+                    scene.insert_shape(Sphere {
+                        center,
+                        radius: 0.2,
+                    })
+                };
+
+                scene.insert_object(s, m);
+            }
+        }
+    }
+
+    //
+    scene
+}
+
+//
+//
+//
+fn make_triangle_meshes_scene(_rng: &mut dyn RngCore) -> Scene {
+    use crate::cgmath::*;
+    use crate::raytracer::camera::*;
+    use crate::raytracer::color::*;
+    use crate::raytracer::material::*;
+    use crate::raytracer::mesh::*;
+    use crate::raytracer::scene::*;
+    use crate::raytracer::shape::*;
+
+    //
+    let mut scene = Scene::new();
+
+    scene.set_camera(CameraDescription {
+        eye: Vec3::new(10.0, 3.0, 1.0),
+        target: Vec3::new(0.0, 1.0, 0.0),
+        up: Vec3::Y,
+        vertical_fov: Degrees(35.0),
+        aperture: 0.0,
+        focus_distance: 10.0,
+        shutter_open: 0.0,
+        shutter_close: 0.0,
+    });
+
+    // ground
+    {
+        let m = scene.insert_material(Lambertian::new(Color::from_rgb(0.5, 0.5, 0.5)));
+
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    // icosahedron, flat and smooth shaded
+    let (positions, indices) = icosahedron();
+
+    {
+        let center = Vec3::new(0.0, 1.0, -2.0);
+        let m = scene.insert_material(Lambertian::new(Color::from_rgb(0.4, 0.2, 0.1)));
+        let s = scene.insert_shape(TriangleMesh::new(
+            positions.iter().map(|&p| center + p).collect(),
+            None,
+            None,
+            indices.clone(),
+        ));
+
+        scene.insert_object(s, m);
+    }
+
+    {
+        let center = Vec3::new(0.0, 1.0, 2.0);
+        let m = scene.insert_material(Metal::new(Color::from_rgb(0.7, 0.6, 0.5), 0.0));
+        let s = scene.insert_shape(TriangleMesh::new(
+            positions.iter().map(|&p| center + p).collect(),
+            Some(positions.clone()),
+            None,
+            indices,
+        ));
+
+        scene.insert_object(s, m);
+    }
+
+    // single triangle
+    {
+        let m = scene.insert_material(Lambertian::new(Color::from_rgb(0.1, 0.2, 0.5)));
+        let s = scene.insert_shape(Triangle::new(
+            Vec3::new(-3.0, 0.0, -1.5),
+            Vec3::new(-3.0, 0.0, 1.5),
+            Vec3::new(-3.0, 2.5, 0.0),
+        ));
+
+        scene.insert_object(s, m);
+    }
+
+    //
+    scene
+}
+
+//
+//
+//
+fn make_simple_light_scene(_rng: &mut dyn RngCore) -> Scene {
+    use crate::cgmath::*;
+    use crate::raytracer::camera::*;
+    use crate::raytracer::color::*;
+    use crate::raytracer::material::*;
+    use crate::raytracer::mesh::*;
+    use crate::raytracer::scene::*;
+    use crate::raytracer::shape::*;
+
+    //
+    let mut scene = Scene::new();
+
+    scene.set_camera(CameraDescription {
+        eye: Vec3::new(26.0, 3.0, 6.0),
+        target: Vec3::new(0.0, 2.0, 0.0),
+        up: Vec3::Y,
+        vertical_fov: Degrees(20.0),
+        aperture: 0.0,
+        focus_distance: 10.0,
+        shutter_open: 0.0,
+        shutter_close: 0.0,
+    });
+
+    scene.set_background(Background::Solid(Color::from_rgb(0.0, 0.0, 0.0)));
+
+    // ground
+    {
+        let m = scene.insert_material(Lambertian::new(Color::from_rgb(0.5, 0.5, 0.5)));
+
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    {
+        let m = scene.insert_material(Lambertian::new(Color::from_rgb(0.4, 0.2, 0.1)));
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, 1.0, 0.0),
+            radius: 1.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    // rectangular light behind the sphere, facing it
+    {
+        let m = scene.insert_material(DiffuseLight::new(Color::from_rgb(4.0, 4.0, 4.0)));
+        let s = scene.insert_shape(TriangleMesh::new(
+            vec![
+                Vec3::new(-2.0, 0.5, -2.0),
+                Vec3::new(2.0, 0.5, -2.0),
+                Vec3::new(2.0, 2.5, -2.0),
+                Vec3::new(-2.0, 2.5, -2.0),
+            ],
+            None,
+            None,
+            vec![[0, 1, 2], [0, 2, 3]],
+        ));
+
+        scene.insert_object(s, m);
+    }
+
+    // spherical light above
+    {
+        let m = scene.insert_material(DiffuseLight::new(Color::from_rgb(4.0, 4.0, 3.6)));
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, 4.0, 0.0),
+            radius: 1.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    //
+    scene
+}
+
+fn make_textures_scene(rng: &mut dyn RngCore) -> Scene {
+    use crate::cgmath::*;
+    use crate::raytracer::camera::*;
+    use crate::raytracer::color::*;
+    use crate::raytracer::material::*;
+    use crate::raytracer::scene::*;
+    use crate::raytracer::shape::*;
+    use crate::raytracer::texture::*;
+
+    use std::sync::Arc;
+
+    //
+    let mut scene = Scene::new();
+
+    scene.set_camera(CameraDescription {
+        eye: Vec3::new(13.0, 2.0, 3.0),
+        target: Vec3::new(0.0, 1.0, 0.0),
+        up: Vec3::Y,
+        vertical_fov: Degrees(25.0),
+        aperture: 0.0,
+        focus_distance: 10.0,
+        shutter_open: 0.0,
+        shutter_close: 0.0,
+    });
+
+    let solid =
+        |r, g, b| -> Arc<dyn Texture> { Arc::new(ConstantTexture::new(Color::from_rgb(r, g, b))) };
+
+    // ground, checkered in world space
+    {
+        let checker = CheckerTexture::new(
+            solid(0.2, 0.3, 0.1),
+            solid(0.9, 0.9, 0.9),
+            1.0,
+            CheckerSpace::Solid,
+        );
+        let m = scene.insert_material(Lambertian::textured(Arc::new(checker)));
+
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    // marble, noise is seeded from the scene seed
+    {
+        let marble = NoiseTexture::new(NoiseKind::Marble, 4.0, rng.next_u64());
+        let m = scene.insert_material(Lambertian::textured(Arc::new(marble)));
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, 1.0, -2.2),
+            radius: 1.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    // checkered in uv, shows the sphere parametrization
+    {
+        let checker = CheckerTexture::new(
+            solid(0.8, 0.1, 0.1),
+            solid(0.9, 0.9, 0.9),
+            0.1,
+            CheckerSpace::Uv,
+        );
+        let m = scene.insert_material(Lambertian::textured(Arc::new(checker)));
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, 1.0, 0.0),
+            radius: 1.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    // turbulent metal
+    {
+        let turbulence = NoiseTexture::new(NoiseKind::Turbulence, 2.0, rng.next_u64());
+        let m = scene.insert_material(Metal::textured(Arc::new(turbulence), 0.2));
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, 1.0, 2.2),
+            radius: 1.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    //
+    scene
+}
+
+/*
+ * One icosahedron mesh placed thousands of times with random rotation and scale
+ */
+fn make_instances_scene(rng: &mut dyn RngCore) -> Scene {
+    use crate::cgmath::*;
+    use crate::raytracer::camera::*;
+    use crate::raytracer::color::*;
+    use crate::raytracer::instance::*;
+    use crate::raytracer::material::*;
+    use crate::raytracer::mesh::*;
+    use crate::raytracer::scene::*;
+    use crate::raytracer::shape::*;
+
+    use rand::Rng;
+
+    //
+    let mut scene = Scene::new();
+
+    scene.set_camera(CameraDescription {
+        eye: Vec3::new(0.0, 9.0, 22.0),
+        target: Vec3::new(0.0, 0.0, 0.0),
+        up: Vec3::Y,
+        vertical_fov: Degrees(40.0),
+        aperture: 0.0,
+        focus_distance: 10.0,
+        shutter_open: 0.0,
+        shutter_close: 0.0,
+    });
+
+    // ground
+    {
+        let m = scene.insert_material(Lambertian::new(Color::from_rgb(0.5, 0.5, 0.5)));
+
+        let s = scene.insert_shape(Sphere {
+            center: Vec3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+        });
+
+        scene.insert_object(s, m);
+    }
+
+    // only inserted as a shape, objects are all instances of it
+    let mesh = {
+        let (positions, indices) = icosahedron();
+        scene.insert_shape(TriangleMesh::new(positions, None, None, indices))
+    };
+
+    let materials = [
+        scene.insert_material(Lambertian::new(Color::from_rgb(0.7, 0.3, 0.1))),
+        scene.insert_material(Lambertian::new(Color::from_rgb(0.1, 0.4, 0.7))),
+        scene.insert_material(Metal::new(Color::from_rgb(0.8, 0.8, 0.8), 0.1)),
+    ];
+
+    for a in -30..30 {
+        for b in -30..30 {
+            let axis = Vec3::random_unit_vector(rng);
+            let angle = Degrees(rng.gen_range(0.0..360.0));
+            let scale = Vec3::new(
+                rng.gen_range(0.1..0.3),
+                rng.gen_range(0.1..0.3),
+                rng.gen_range(0.1..0.3),
+            );
+            let position = Vec3::new(
+                0.5 * a as f32 + rng.gen_range(0.0..0.3),
+                0.3,
+                0.5 * b as f32 + rng.gen_range(0.0..0.3),
+            );
+
+            let transform = Affine3::from_scale(scale)
+                .then(Affine3::from_rotation(Quaternion::from_axis_angle(
+                    axis,
+                    angle.into(),
+                )))
+                .then(Affine3::from_translation(position));
+
+            let s = scene.insert_shape(Instance::new(&scene, mesh, transform));
+            let m = materials[rng.gen_range(0..materials.len())];
+
+            scene.insert_object(s, m);
+        }
+    }
+
+    //
+    scene
+}
+
+fn make_cornell_box_scene(_rng: &mut dyn RngCore) -> Scene {
+    make_cornell_scene(false)
+}
+
+/*
+ * Cornell box with blocks of dark and light smoke instead of solid ones
+ */
+fn make_cornell_smoke_scene(_rng: &mut dyn RngCore) -> Scene {
+    make_cornell_scene(true)
+}
+
+/*
+ * Walls are quads facing into the room, the blocks are boxes turned by instances
+ */
+fn make_cornell_scene(smoke: bool) -> Scene {
+    use crate::cgmath::*;
+    use crate::raytracer::camera::*;
+    use crate::raytracer::color::*;
+    use crate::raytracer::instance::*;
+    use crate::raytracer::material::*;
+    use crate::raytracer::medium::*;
+    use crate::raytracer::scene::*;
+    use crate::raytracer::shape::*;
+
+    //
+    let mut scene = Scene::new();
+
+    scene.set_camera(CameraDescription {
+        eye: Vec3::new(278.0, 278.0, -800.0),
+        target: Vec3::new(278.0, 278.0, 0.0),
+        up: Vec3::Y,
+        vertical_fov: Degrees(40.0),
+        aperture: 0.0,
+        focus_distance: 10.0,
+        shutter_open: 0.0,
+        shutter_close: 0.0,
+    });
+
+    scene.set_background(Background::Solid(Color::from_rgb(0.0, 0.0, 0.0)));
+
+    let red = scene.insert_material(Lambertian::new(Color::from_rgb(0.65, 0.05, 0.05)));
+    let white = scene.insert_material(Lambertian::new(Color::from_rgb(0.73, 0.73, 0.73)));
+    let green = scene.insert_material(Lambertian::new(Color::from_rgb(0.12, 0.45, 0.15)));
+
+    // walls, the camera looks in through the open side at z = 0
+    let walls = [
+        // left and right
+        (
+            Quad::new(
+                Vec3::new(555.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 555.0),
+                Vec3::new(0.0, 555.0, 0.0),
+            ),
+            green,
+        ),
+        (
+            Quad::new(
+                Vec3::ZERO,
+                Vec3::new(0.0, 555.0, 0.0),
+                Vec3::new(0.0, 0.0, 555.0),
+            ),
+            red,
+        ),
+        // floor, ceiling and back
+        (
+            Quad::new(
+                Vec3::ZERO,
+                Vec3::new(0.0, 0.0, 555.0),
+                Vec3::new(555.0, 0.0, 0.0),
+            ),
+            white,
+        ),
+        (
+            Quad::new(
+                Vec3::new(555.0, 555.0, 555.0),
+                Vec3::new(-555.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -555.0),
+            ),
+            white,
+        ),
+        (
+            Quad::new(
+                Vec3::new(0.0, 0.0, 555.0),
+                Vec3::new(0.0, 555.0, 0.0),
+                Vec3::new(555.0, 0.0, 0.0),
+            ),
+            white,
+        ),
+    ];
+
+    for (quad, m) in walls {
+        let s = scene.insert_shape(quad);
+        scene.insert_object(s, m);
+    }
+
+    // light in the ceiling, facing down
+    {
+        let m = scene.insert_material(DiffuseLight::new(Color::from_rgb(15.0, 15.0, 15.0)));
+        let s = scene.insert_shape(Quad::new(
+            Vec3::new(343.0, 554.0, 332.0),
+            Vec3::new(-130.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -105.0),
+        ));
+
+        scene.insert_object(s, m);
+    }
+
+    // tall and short block
+    let blocks = [
+        (
+            Vec3::new(165.0, 330.0, 165.0),
+            15.0,
+            Vec3::new(265.0, 0.0, 295.0),
+            Color::from_rgb(0.0, 0.0, 0.0),
+        ),
+        (
+            Vec3::new(165.0, 165.0, 165.0),
+            -18.0,
+            Vec3::new(130.0, 0.0, 65.0),
+            Color::from_rgb(1.0, 1.0, 1.0),
+        ),
+    ];
+
+    for (size, angle, position, smoke_color) in blocks {
+        let block = scene.insert_shape(Cuboid::new(Vec3::ZERO, size));
+
+        let transform =
+            Affine3::from_rotation(Quaternion::from_axis_angle(Vec3::Y, Degrees(angle).into()))
+                .then(Affine3::from_translation(position));
+
+        let s = scene.insert_shape(Instance::new(&scene, block, transform));
+
+        if smoke {
+            let m = scene.insert_material(Isotropic::new(smoke_color));
+            let s = scene.insert_shape(ConstantMedium::new(&scene, s, 0.01));
+            scene.insert_object(s, m);
+        } else {
+            scene.insert_object(s, white);
+        }
+    }
+
+    //
+    scene
+}
+
+/*
+ * Noise clouds in the sky over a column of smoke from a voxel grid
+ */
+fn make_clouds_scene(_rng: &mut dyn RngCore) -> Scene {
+    use crate::cgmath::*;
+    use crate::raytracer::camera::*;
+    use crate::raytracer::color::*;
+    use crate::raytracer::instance::*;
+    use crate::raytracer::material::*;
+    use crate::raytracer::medium::*;
+    use crate::raytracer::scene::*;
+    use crate::raytracer::shape::*;
+    use crate::raytracer::texture::*;
+
+    //
+    let mut scene = Scene::new();
+
+    scene.set_camera(CameraDescription {
+        eye: Vec3::new(0.0, 2.0, 12.0),
+        target: Vec3::new(0.0, 3.0, 0.0),
+        up: Vec3::Y,
+        vertical_fov: Degrees(45.0),
+        aperture: 0.0,
+        focus_distance: 10.0,
+        shutter_open: 0.0,
+        shutter_close: 0.0,
+    });
+
+    {
+        let m = scene.insert_material(Lambertian::new(Color::from_rgb(0.4, 0.5, 0.3)));
+        let s = scene.insert_shape(Plane::new(Vec3::ZERO, Vec3::Y));
+        scene.insert_object(s, m);
+    }
+
+    // flattened balls of turbulence, scattering forwards like water droplets
+    let cloud = scene.insert_material(HenyeyGreenstein::new(
+        Color::from_rgb(0.95, 0.95, 0.95),
+        0.7,
+    ));
+    let ball = scene.insert_shape(Sphere {
+        center: Vec3::ZERO,
+        radius: 1.0,
+    });
+
+    let clouds = [
+        (Vec3::new(-4.0, 6.5, -4.0), Vec3::new(3.0, 1.2, 2.0), 1),
+        (Vec3::new(2.5, 7.0, -6.0), Vec3::new(4.0, 1.5, 2.5), 2),
+        (Vec3::new(5.0, 5.0, -2.0), Vec3::new(2.0, 0.9, 1.5), 3),
+    ];
+
+    for (position, size, seed) in clouds {
+        let noise = NoiseTexture::new(NoiseKind::Turbulence, 1.5, seed);
+        let medium = scene.insert_shape(NoiseMedium::new(&scene, ball, noise, 3.0));
+
+        let transform = Affine3::from_scale(size).then(Affine3::from_translation(position));
+        let s = scene.insert_shape(Instance::new(&scene, medium, transform));
+        scene.insert_object(s, cloud);
+    }
+
+    // smoke thinning out and spreading as it rises
+    {
+        let size = [16, 48, 16];
+        let mut values = Vec::with_capacity(size[0] * size[1] * size[2]);
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    let [x, y, z] = [(x, 0), (y, 1), (z, 2)]
+                        .map(|(i, axis)| (i as f32 + 0.5) / size[axis] as f32);
+
+                    let radius = 0.15 + 0.3 * y;
+                    let sway = 0.1 * (8.0 * y).sin() * y;
+                    let distance = ((x - 0.5 - sway).powi(2) + (z - 0.5).powi(2)).sqrt();
+
+                    values.push((1.0 - distance / radius).max(0.0) * (1.0 - y));
+                }
+            }
+        }
+
+        let box_shape = scene.insert_shape(Cuboid::new(
+            Vec3::new(-2.0, 0.0, -1.0),
+            Vec3::new(0.0, 6.0, 1.0),
+        ));
+        let grid = DensityGrid::new(size, values);
+        let s = scene.insert_shape(GridMedium::new(&scene, box_shape, grid, 4.0));
+        let m = scene.insert_material(HenyeyGreenstein::new(Color::from_rgb(0.3, 0.3, 0.3), 0.3));
+        scene.insert_object(s, m);
+    }
+
+    //
+    scene
+}
+
+//
+//
+//
+fn icosahedron() -> (Vec<crate::cgmath::Vec3>, Vec<[usize; 3]>) {
+    use crate::cgmath::*;
+
+    let p = (1.0 + 5.0f32.sqrt()) * 0.5;
+
+    let positions: Vec<Vec3> = [
+        (-1.0, p, 0.0),
+        (1.0, p, 0.0),
+        (-1.0, -p, 0.0),
+        (1.0, -p, 0.0),
+        (0.0, -1.0, p),
+        (0.0, 1.0, p),
+        (0.0, -1.0, -p),
+        (0.0, 1.0, -p),
+        (p, 0.0, -1.0),
+        (p, 0.0, 1.0),
+        (-p, 0.0, -1.0),
+        (-p, 0.0, 1.0),
+    ]
+    .iter()
+    .map(|&(x, y, z)| Vec3::new(x, y, z).normalized())
+    .collect();
+
+    let indices = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    (positions, indices)
+}
@@ -0,0 +1,346 @@
+/*
+ * Golden image tests.
+ *
+ * Small scenes are rendered with a fixed seed and low sample count and compared to reference
+ * images in `tests/golden/reference`. Rendering is deterministic, the tolerance only absorbs
+ * floating point differences between platforms and compilers.
+ *
+ * After a change that is meant to alter the output, regenerate references with
+ *
+ *   GOLDEN_UPDATE=1 cargo test --test golden
+ *
+ * and look at the new images before committing them. Failed comparisons write the rendered
+ * image, the reference and a diff to `target/tmp/golden`.
+ */
+use raytracer_rs::{
+    raytracer::{
+        color::Color,
+        framebuffer::Framebuffer,
+        output::{save_image, save_pfm, ExrPrecision},
+        render::{render, RenderSettings},
+        sampler::SamplerKind,
+        scene_file::load_scene_file,
+    },
+    scenes::{create_scene, scene_creators},
+};
+
+use anyhow::{bail, Context};
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+//
+//
+//
+
+// root mean square error of all channels. Tight enough that a 1% change of albedo, which only
+// shows in indirect light, fails every scene.
+const MAX_RMSE: f32 = 2.5e-4;
+
+// mean of squared error over squared reference, bright pixels don't dominate it
+const MAX_RELATIVE_MSE: f32 = 1e-6;
+
+// keeps relative error of black pixels finite
+const RELATIVE_MSE_EPSILON: f32 = 1e-2;
+
+// diff image is scaled up, small differences would be invisible otherwise
+const DIFF_SCALE: f32 = 10.0;
+
+//
+//
+//
+#[test]
+fn diffuse_sobol() {
+    let framebuffer = render_scene_file("diffuse", Some(SamplerKind::Sobol));
+    check_golden("diffuse_sobol", &framebuffer);
+}
+
+#[test]
+fn diffuse_halton() {
+    let framebuffer = render_scene_file("diffuse", Some(SamplerKind::Halton));
+    check_golden("diffuse_halton", &framebuffer);
+}
+
+#[test]
+fn diffuse_stratified() {
+    let framebuffer = render_scene_file("diffuse", Some(SamplerKind::Stratified));
+    check_golden("diffuse_stratified", &framebuffer);
+}
+
+#[test]
+fn diffuse_independent() {
+    let framebuffer = render_scene_file("diffuse", Some(SamplerKind::Independent));
+    check_golden("diffuse_independent", &framebuffer);
+}
+
+#[test]
+fn specular() {
+    let framebuffer = render_scene_file("specular", None);
+    check_golden("specular", &framebuffer);
+}
+
+#[test]
+fn textures() {
+    let framebuffer = render_scene_file("textures", None);
+    check_golden("textures", &framebuffer);
+}
+
+#[test]
+fn motion_blur() {
+    let framebuffer = render_scene_file("motion_blur", None);
+    check_golden("motion_blur", &framebuffer);
+}
+
+#[test]
+fn instances() {
+    let framebuffer = render_scene_file("instances", None);
+    check_golden("instances", &framebuffer);
+}
+
+#[test]
+fn quads() {
+    let framebuffer = render_scene_file("quads", None);
+    check_golden("quads", &framebuffer);
+}
+
+#[test]
+fn fog() {
+    let framebuffer = render_scene_file("fog", None);
+    check_golden("fog", &framebuffer);
+}
+
+#[test]
+fn volumes() {
+    let framebuffer = render_scene_file("volumes", None);
+    check_golden("volumes", &framebuffer);
+}
+
+#[test]
+fn csg() {
+    let framebuffer = render_scene_file("csg", None);
+    check_golden("csg", &framebuffer);
+}
+
+#[test]
+fn quadrics() {
+    let framebuffer = render_scene_file("quadrics", None);
+    check_golden("quadrics", &framebuffer);
+}
+
+#[test]
+fn book_1_final_scene() {
+    let seed = 3;
+
+    let creator = scene_creators()["Book 1 final scene"];
+    let scene = create_scene(creator, seed);
+
+    let settings = RenderSettings {
+        image_width: 48,
+        image_height: 32,
+        sample_count: 8,
+        max_depth: 6,
+        seed,
+        ..RenderSettings::default()
+    };
+
+    let framebuffer = render(&scene, scene.camera(), &settings, |_, _| {});
+    check_golden("book_1_final_scene", &framebuffer);
+}
+
+//
+//
+//
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/*
+ * Renders `tests/golden/scenes/<name>.toml` with its own settings, `sampler` overrides the one
+ * from the file
+ */
+fn render_scene_file(
+    name: &str,
+    sampler: Option<SamplerKind>,
+) -> Framebuffer {
+    let path = golden_dir().join("scenes").join(format!("{}.toml", name));
+    let file = load_scene_file(&path).unwrap();
+
+    let settings = RenderSettings {
+        sampler: sampler.unwrap_or(file.settings.sampler),
+        ..file.settings
+    };
+
+    render(&file.scene, file.scene.camera(), &settings, |_, _| {})
+}
+
+fn check_golden(
+    name: &str,
+    framebuffer: &Framebuffer,
+) {
+    let reference_path = golden_dir().join("reference").join(format!("{}.pfm", name));
+
+    if std::env::var_os("GOLDEN_UPDATE").is_some() {
+        save_pfm(&reference_path, framebuffer).unwrap();
+        return;
+    }
+
+    let reference = load_pfm(&reference_path)
+        .with_context(|| {
+            format!(
+                "missing reference for '{}', create it with GOLDEN_UPDATE=1",
+                name
+            )
+        })
+        .unwrap();
+
+    assert!(
+        reference.width() == framebuffer.width() && reference.height() == framebuffer.height(),
+        "'{}' rendered at {}x{}, reference is {}x{}",
+        name,
+        framebuffer.width(),
+        framebuffer.height(),
+        reference.width(),
+        reference.height()
+    );
+
+    let (rmse, relative_mse) = error_metrics(framebuffer, &reference);
+
+    if rmse > MAX_RMSE || relative_mse > MAX_RELATIVE_MSE {
+        let output_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        fs::create_dir_all(&output_dir).unwrap();
+
+        let save = |suffix: &str, framebuffer: &Framebuffer| {
+            let path = output_dir.join(format!("{}_{}.png", name, suffix));
+            save_image(&path, framebuffer, None, ExrPrecision::Float).unwrap();
+        };
+
+        save("actual", framebuffer);
+        save("reference", &reference);
+        save("diff", &diff_image(framebuffer, &reference));
+
+        panic!(
+            "'{}' differs from reference: rmse {} (max {}), relative mse {} (max {}), images \
+             written to {}",
+            name,
+            rmse,
+            MAX_RMSE,
+            relative_mse,
+            MAX_RELATIVE_MSE,
+            output_dir.display()
+        );
+    }
+}
+
+/*
+ * Returns rmse and relative mse over all channels of all pixels
+ */
+fn error_metrics(
+    image: &Framebuffer,
+    reference: &Framebuffer,
+) -> (f32, f32) {
+    let mut squared_error = 0.0f64;
+    let mut relative_squared_error = 0.0f64;
+
+    for (a, b) in image.pixels().iter().zip(reference.pixels()) {
+        for (a, b) in channels(a).iter().zip(channels(b).iter()) {
+            let error = (a - b) as f64;
+            squared_error += error * error;
+            relative_squared_error += error * error / (b * b + RELATIVE_MSE_EPSILON) as f64;
+        }
+    }
+
+    let count = (image.pixels().len() * 3) as f64;
+
+    (
+        (squared_error / count).sqrt() as f32,
+        (relative_squared_error / count) as f32,
+    )
+}
+
+fn diff_image(
+    image: &Framebuffer,
+    reference: &Framebuffer,
+) -> Framebuffer {
+    let mut diff = Framebuffer::new(image.width(), image.height());
+
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let a = image.get(x, y);
+            let b = reference.get(x, y);
+
+            diff.set(
+                x,
+                y,
+                Color::from_rgb(
+                    DIFF_SCALE * (a.r() - b.r()).abs(),
+                    DIFF_SCALE * (a.g() - b.g()).abs(),
+                    DIFF_SCALE * (a.b() - b.b()).abs(),
+                ),
+            );
+        }
+    }
+
+    diff
+}
+
+fn channels(color: &Color) -> [f32; 3] {
+    [color.r(), color.g(), color.b()]
+}
+
+/*
+ * Reads little endian color PFM as written by `save_pfm`
+ */
+fn load_pfm(path: &Path) -> anyhow::Result<Framebuffer> {
+    let bytes = fs::read(path).with_context(|| format!("cannot read {}", path.display()))?;
+
+    // header is three whitespace terminated lines: "PF", "<width> <height>", "<scale>"
+    let mut lines = bytes.splitn(4, |&b| b == b'\n');
+    let mut next_line = || -> anyhow::Result<String> {
+        let line = lines.next().context("truncated header")?;
+        Ok(String::from_utf8_lossy(line).trim().to_string())
+    };
+
+    if next_line()? != "PF" {
+        bail!("{} is not a color pfm", path.display());
+    }
+
+    let size = next_line()?;
+    let mut size = size.split_whitespace().map(str::parse::<usize>);
+    let (width, height) = match (size.next(), size.next()) {
+        (Some(Ok(width)), Some(Ok(height))) => (width, height),
+        _ => bail!("{}: invalid size", path.display()),
+    };
+
+    let scale: f32 = next_line()?.parse()?;
+    if scale >= 0.0 {
+        bail!("{}: only little endian pfm is supported", path.display());
+    }
+
+    let data = lines.next().unwrap_or(&[]);
+    if data.len() != width * height * 12 {
+        bail!(
+            "{}: expected {} bytes of pixels",
+            path.display(),
+            width * height * 12
+        );
+    }
+
+    let mut floats = data
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+
+    let mut framebuffer = Framebuffer::new(width, height);
+
+    // rows are stored bottom to top
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let mut channel = || floats.next().unwrap();
+            let (r, g, b) = (channel(), channel(), channel());
+            framebuffer.set(x, y, Color::from_rgb(r, g, b));
+        }
+    }
+
+    Ok(framebuffer)
+}